@@ -0,0 +1,234 @@
+//! # Full flow
+//!
+//! This example goes through the whole flow of the library: log in to Entrez,
+//! generate a token for the Envoy, authenticate with the Envoy, print a summary
+//! of the system, and toggle the power state of a device before restoring it.
+//!
+//! Against real hardware, the credentials and devices are read from the
+//! environment:
+//!
+//! - `ENTREZ_USERNAME` and `ENTREZ_PASSWORD`: The Enphase account
+//! - `ENVOY_HOST`: The hostname or IP address of the Envoy
+//! - `ENVOY_NAME`: The name of the site
+//! - `ENVOY_SERIAL_NUMBER`: The serial number of the Envoy
+//! - `ENVOY_DEVICE_SERIAL`: The serial number of the device to toggle
+//!
+//! ```console
+//! $ cargo run --example full_flow
+//! ```
+//!
+//! With `--mock`, the same flow runs against an in-process fake gateway
+//! serving the captured responses from `fixtures/`, so no hardware or account
+//! is needed:
+//!
+//! ```console
+//! $ cargo run --example full_flow -- --mock
+//! ```
+
+#![expect(clippy::print_stdout, reason = "Examples report their progress")]
+
+use enphase_api::{
+    Entrez, Envoy, Scheme,
+    models::{PowerState, SerialNumber, production::Measurement},
+};
+use wiremock::{
+    Mock, MockServer, ResponseTemplate,
+    matchers::{method, path},
+};
+
+/// The result type of the example.
+type Result<T> = core::result::Result<T, Box<dyn core::error::Error>>;
+
+/// A well-formed (but unsigned) token issued for the Envoy of the fixtures.
+const MOCK_TOKEN: &str = concat!(
+    "eyJhbGciOiJFUzI1NiIsInR5cCI6IkpXVCJ9.",
+    "eyJhdWQiOiIxMjIxMzMwMTIzNDUiLCJpc3MiOiJFbnRyZXoiLCJlbnBoYXNlVXNlciI6Im93bmVyIiwiZXhwIjo0MTAy",
+    "NDQ0ODAwLCJpYXQiOjE3MDQwNjcyMDAsInVzZXJuYW1lIjoib3duZXJAZXhhbXBsZS5jb20ifQ.",
+    "c2lnbmF0dXJl"
+);
+
+/// The site, Envoy and device to use.
+struct Target {
+    /// The name of the site.
+    site: String,
+    /// The serial number of the Envoy.
+    envoy_serial: SerialNumber,
+    /// The serial number of the device to toggle.
+    device_serial: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    if std::env::args().any(|arg| arg == "--mock") {
+        run_mock().await
+    } else {
+        run_hardware().await
+    }
+}
+
+/// Run the flow against the hardware configured in the environment.
+async fn run_hardware() -> Result<()> {
+    let target = Target {
+        site: std::env::var("ENVOY_NAME")?,
+        envoy_serial: SerialNumber::parse(std::env::var("ENVOY_SERIAL_NUMBER")?)?,
+        device_serial: std::env::var("ENVOY_DEVICE_SERIAL")?,
+    };
+    let username = std::env::var("ENTREZ_USERNAME")?;
+    let password = std::env::var("ENTREZ_PASSWORD")?;
+    let envoy = Envoy::from_env()?.with_serial_number(target.envoy_serial.clone());
+    run(&Entrez::default(), &envoy, &target, (&username, &password)).await
+}
+
+/// Run the flow against the fake gateway.
+async fn run_mock() -> Result<()> {
+    let server = fake_gateway().await?;
+    let address = server.address();
+    let target = Target {
+        site: "My Site".to_owned(),
+        envoy_serial: SerialNumber::parse("122133012345")?,
+        device_serial: "603980032".to_owned(),
+    };
+    let entrez = Entrez::new(server.uri());
+    let envoy = Envoy::builder(address.ip())
+        .scheme(Scheme::Http)
+        .port(address.port())
+        .build()?
+        .with_serial_number(target.envoy_serial.clone());
+    run(&entrez, &envoy, &target, ("owner@example.com", "password")).await
+}
+
+/// Run the flow with the given clients.
+///
+/// The Envoy client is configured with the serial number of the target, so
+/// that authenticating checks that the token was issued for the right Envoy.
+async fn run(
+    entrez: &Entrez,
+    envoy: &Envoy,
+    target: &Target,
+    (username, password): (&str, &str),
+) -> Result<()> {
+    entrez.login(username, password).await?;
+    println!("Logged in to Entrez as {username}");
+
+    let token = entrez
+        .generate_token(&target.site, target.envoy_serial.as_str(), true)
+        .await?;
+    println!("Generated a token for {}", target.envoy_serial);
+
+    envoy.authenticate(&token).await?;
+    println!("Authenticated with the Envoy");
+
+    let info = envoy.info().await?;
+    println!(
+        "Envoy {} ({}), software {}",
+        info.serial_number, info.part_number, info.software_version
+    );
+    for measurement in envoy.production().await?.production {
+        if let Measurement::Inverters(inverters) = measurement {
+            println!(
+                "{} inverters producing {} W ({} Wh lifetime)",
+                inverters.active_count, inverters.w_now, inverters.wh_lifetime
+            );
+        }
+    }
+
+    let (toggled, original) = match envoy.get_power_state(&target.device_serial).await? {
+        PowerState::On => (PowerState::Off, PowerState::On),
+        PowerState::Off => (PowerState::On, PowerState::Off),
+        state @ (PowerState::ForcedOff | _) => {
+            println!(
+                "Device {} is {state}, not toggling it",
+                target.device_serial
+            );
+            return Ok(());
+        }
+    };
+    println!("Device {} is {original}", target.device_serial);
+    envoy
+        .set_power_state(&target.device_serial, toggled)
+        .await?;
+    println!("Turned device {} {toggled}", target.device_serial);
+    envoy
+        .set_power_state(&target.device_serial, original)
+        .await?;
+    println!("Restored device {} to {original}", target.device_serial);
+
+    Ok(())
+}
+
+/// Start a fake gateway serving both the Entrez and the Envoy endpoints from
+/// the captured fixtures.
+async fn fake_gateway() -> Result<MockServer> {
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path("/login"))
+        .respond_with(response("entrez", "login-success")?)
+        .mount(&server)
+        .await;
+    let (status, page) = fixture("entrez", "generate-token-success")?;
+    Mock::given(method("POST"))
+        .and(path("/entrez_tokens"))
+        .respond_with(
+            ResponseTemplate::new(status)
+                .set_body_string(page.replace("SANITIZED.JWT.TOKEN", MOCK_TOKEN)),
+        )
+        .mount(&server)
+        .await;
+
+    for (route, name) in [
+        ("/auth/check_jwt", "authenticate-valid"),
+        ("/info", "info"),
+        ("/production.json", "production"),
+        ("/ivp/mod/603980032/mode/power", "get-power"),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(response("envoy", name)?)
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("PUT"))
+        .and(path("/ivp/mod/603980032/mode/power"))
+        .respond_with(response("envoy", "set-power-on")?)
+        .mount(&server)
+        .await;
+
+    Ok(server)
+}
+
+/// Replay a captured response.
+fn response(category: &str, name: &str) -> Result<ResponseTemplate> {
+    let (status, body) = fixture(category, name)?;
+    Ok(ResponseTemplate::new(status).set_body_string(body))
+}
+
+/// Load the status code and body of a captured response.
+fn fixture(category: &str, name: &str) -> Result<(u16, String)> {
+    let fixture_path = format!(
+        "{}/fixtures/{category}/{name}.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let fixture: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(fixture_path)?)?;
+    let status = fixture
+        .get("status_code")
+        .and_then(serde_json::Value::as_u64)
+        .ok_or("Fixture has no status code")?;
+    let body = fixture
+        .get("body")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+    Ok((u16::try_from(status)?, body.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mock_flow() {
+        run_mock()
+            .await
+            .expect("The flow should succeed against the fake gateway");
+    }
+}
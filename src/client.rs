@@ -1,4 +1,38 @@
 //! # Enphase clients
 
+#[cfg(test)]
+pub(crate) mod capture;
+#[cfg(feature = "discovery")]
+pub mod discovery;
 pub mod entrez;
 pub mod envoy;
+pub mod poller;
+pub mod session;
+
+use crate::models::{EnvoyToken, TokenSource};
+
+/// How long before expiry a token is considered due for renewal by default.
+pub(crate) const DEFAULT_REFRESH_MARGIN: core::time::Duration = core::time::Duration::from_mins(5);
+
+/// Record where a token was obtained from, along with its metadata.
+///
+/// The event carries the `source` of the token, its `remaining` validity in
+/// seconds and the `serial` number it was issued for (when the token can be
+/// decoded), and whether an Entrez `login` session was needed. Tokens obtained
+/// through a cache also carry the outcome of the `cache` lookup which led to it
+/// (e.g., `hit`, or `expired` for a token generated in place of an expired
+/// one).
+pub(crate) fn trace_token(source: TokenSource, token: &str, cache: Option<&str>) {
+    let claims = EnvoyToken::parse(token).ok();
+    tracing::debug!(
+        %source,
+        remaining = claims
+            .as_ref()
+            .and_then(EnvoyToken::remaining)
+            .map(|remaining| remaining.as_secs()),
+        serial = claims.as_ref().and_then(EnvoyToken::serial_number),
+        login = source == TokenSource::Entrez,
+        cache,
+        "Token acquired"
+    );
+}
@@ -0,0 +1,88 @@
+//! # Capturing traces in tests
+//!
+//! This module provides a minimal subscriber recording the fields of the
+//! events emitted while it is the default, so that tests can check what is
+//! traced.
+
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::fmt;
+use std::sync::{Mutex, PoisonError};
+
+use tracing::{
+    Event, Metadata, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    subscriber::DefaultGuard,
+};
+
+/// The fields of an event, formatted with [`fmt::Debug`] (so strings are
+/// quoted).
+pub(crate) type Fields = BTreeMap<String, String>;
+
+/// A subscriber recording the fields of every event.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Capture {
+    /// The events recorded so far.
+    events: Arc<Mutex<Vec<Fields>>>,
+}
+
+impl Capture {
+    /// Record the events of the current thread until the guard is dropped.
+    pub(crate) fn install() -> (Self, DefaultGuard) {
+        let capture = Self::default();
+        let guard = tracing::subscriber::set_default(capture.clone());
+        (capture, guard)
+    }
+
+    /// The recorded events with the given message.
+    pub(crate) fn events(&self, message: &str) -> Vec<Fields> {
+        let quoted = format!("{message:?}");
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|fields| {
+                fields
+                    .get("message")
+                    .is_some_and(|recorded| *recorded == quoted || recorded == message)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+impl Subscriber for Capture {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut visitor = Visitor(Fields::new());
+        event.record(&mut visitor);
+        self.events
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(visitor.0);
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+/// A visitor collecting the fields of an event.
+struct Visitor(Fields);
+
+impl Visit for Visitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_owned(), format!("{value:?}"));
+    }
+}
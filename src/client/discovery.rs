@@ -0,0 +1,762 @@
+//! # Discovery of Envoy devices
+//!
+//! Envoy devices advertise themselves over mDNS as
+//! `_enphase-envoy._tcp.local`, which is more reliable than the `envoy.local`
+//! hostname (which often does not resolve across VLANs). This module browses
+//! for these advertisements using one-shot mDNS queries (RFC 6762, section
+//! 5.1), to which the devices reply directly.
+//!
+//! The serial number and firmware version are read from the `serialnum` and
+//! `protovers` TXT records. Only IPv4 is supported.
+
+#![expect(clippy::big_endian_bytes, reason = "DNS uses network byte order")]
+
+use alloc::collections::BTreeMap;
+use core::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    time::Duration,
+};
+
+use tokio::{net::UdpSocket, time::Instant};
+use tracing::{debug, instrument};
+
+use crate::{
+    client::envoy::Envoy,
+    error::{EnphaseError, Result},
+    models::SerialNumber,
+};
+
+/// The mDNS service advertised by Envoy devices.
+pub const SERVICE: &str = "_enphase-envoy._tcp.local";
+
+/// The IPv4 mDNS multicast group and port.
+pub(crate) const MDNS_ADDRESS: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), 5353));
+
+/// How often the query is repeated while browsing, in case it was lost.
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The largest mDNS packet accepted (RFC 6762, section 17).
+const MAX_PACKET_SIZE: usize = 9000;
+
+/// The number of labels after which a name is considered malformed (this also
+/// stops compression pointer loops).
+const MAX_LABELS: usize = 128;
+
+/// DNS record type of IPv4 addresses.
+const TYPE_A: u16 = 1;
+/// DNS record type of pointers.
+const TYPE_PTR: u16 = 12;
+/// DNS record type of text records.
+const TYPE_TXT: u16 = 16;
+/// DNS record type of service locations.
+const TYPE_SRV: u16 = 33;
+
+/// An Envoy device found on the local network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DiscoveredEnvoy {
+    /// The mDNS instance name of the device (e.g., `envoy._enphase-envoy._tcp.local`).
+    pub name: String,
+    /// The IPv4 address of the device.
+    pub address: Ipv4Addr,
+    /// The hostname of the device, if advertised (e.g., `envoy.local`).
+    pub hostname: Option<String>,
+    /// The serial number of the device, if advertised.
+    pub serial_number: Option<SerialNumber>,
+    /// The firmware version of the device, if advertised.
+    pub firmware_version: Option<String>,
+}
+
+impl DiscoveredEnvoy {
+    /// Create a client for the device.
+    ///
+    /// The client connects to the address of the device, and is configured
+    /// with its serial number (see [`Envoy::with_serial_number`]) if it was
+    /// advertised.
+    ///
+    /// # Returns
+    ///
+    /// Returns a new [`Envoy`] client for the device.
+    #[inline]
+    #[must_use]
+    pub fn client(&self) -> Envoy {
+        let client = Envoy::new(self.address);
+        match self.serial_number.clone() {
+            Some(serial_number) => client.with_serial_number(serial_number),
+            None => client,
+        }
+    }
+}
+
+/// Browse the local network for Envoy devices.
+///
+/// Queries are sent throughout the given duration, and each device is
+/// reported once, however many times it answers.
+///
+/// # Arguments
+///
+/// * `duration` - How long to wait for devices to answer
+///
+/// # Returns
+///
+/// Returns the devices found, in the order in which they answered. The list
+/// is empty if no device answered.
+///
+/// # Errors
+///
+/// Returns an I/O error if the query cannot be sent, or a configuration error
+/// if the duration is too long.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::time::Duration;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// for envoy in enphase_api::discover(Duration::from_secs(3)).await? {
+///     println!("{} at {}", envoy.name, envoy.address);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+pub async fn discover(duration: Duration) -> Result<Vec<DiscoveredEnvoy>> {
+    browse(MDNS_ADDRESS, duration, false).await
+}
+
+/// Send queries to the given address for the given duration, collecting the
+/// devices which answer.
+///
+/// If `first` is set, this returns as soon as a device has been found.
+#[instrument(level = "debug")]
+pub(crate) async fn browse(
+    target: SocketAddr,
+    duration: Duration,
+    first: bool,
+) -> Result<Vec<DiscoveredEnvoy>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    let query = query_packet();
+    let start = Instant::now();
+    let deadline = start.checked_add(duration).ok_or_else(|| {
+        EnphaseError::ConfigurationError(format!("Discovery duration {duration:?} is too long"))
+    })?;
+
+    let mut browser = Browser::default();
+    let mut buffer = vec![0_u8; MAX_PACKET_SIZE];
+    let mut next_query = start;
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        if now >= next_query {
+            debug!("Sending mDNS query");
+            socket.send_to(&query, target).await?;
+            next_query = now.checked_add(QUERY_INTERVAL).unwrap_or(deadline);
+        }
+
+        let received =
+            tokio::time::timeout_at(deadline.min(next_query), socket.recv_from(&mut buffer)).await;
+        if let Ok(result) = received {
+            let (length, source) = result?;
+            if let (Some(packet), SocketAddr::V4(peer)) = (buffer.get(..length), source) {
+                debug!("Received {length} bytes from {peer}");
+                browser.handle(packet, *peer.ip());
+            }
+            if first && !browser.envoys().is_empty() {
+                break;
+            }
+        }
+    }
+
+    Ok(browser.envoys())
+}
+
+/// The one-shot query for the Envoy service, asking for a unicast response.
+fn query_packet() -> Vec<u8> {
+    // Header: ID, flags, one question, no records.
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in SERVICE.split('.') {
+        packet.extend(u8::try_from(label.len()));
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    // Class IN, with the unicast-response bit set.
+    packet.extend_from_slice(&0x8001_u16.to_be_bytes());
+    packet
+}
+
+/// The answers collected while browsing.
+///
+/// Names are compared case-insensitively, so the maps are keyed by the
+/// lowercased name.
+#[derive(Debug, Default)]
+struct Browser {
+    /// The instance names of the devices, in the order in which they were
+    /// first announced.
+    instances: Vec<String>,
+    /// The address each instance was announced from.
+    sources: BTreeMap<String, Ipv4Addr>,
+    /// The hostname of each instance.
+    hosts: BTreeMap<String, String>,
+    /// The TXT properties of each instance.
+    properties: BTreeMap<String, BTreeMap<String, String>>,
+    /// The addresses of each hostname.
+    addresses: BTreeMap<String, Ipv4Addr>,
+}
+
+impl Browser {
+    /// Record the answers from an mDNS response.
+    ///
+    /// Malformed packets are ignored, as are queries (e.g., from other
+    /// devices also browsing).
+    fn handle(&mut self, packet: &[u8], source: Ipv4Addr) {
+        let Some(records) = parse_response(packet) else {
+            debug!("Ignoring packet from {source}");
+            return;
+        };
+
+        for record in records {
+            let key = record.name.to_ascii_lowercase();
+            match record.data {
+                RecordData::Ptr(instance) if key == SERVICE => {
+                    let instance_key = instance.to_ascii_lowercase();
+                    if !self.sources.contains_key(&instance_key) {
+                        self.instances.push(instance);
+                    }
+                    self.sources.insert(instance_key, source);
+                }
+                RecordData::Srv(target) => {
+                    self.hosts.insert(key, target);
+                }
+                RecordData::Txt(properties) => {
+                    self.properties.entry(key).or_default().extend(properties);
+                }
+                RecordData::A(address) => {
+                    self.addresses.insert(key, address);
+                }
+                RecordData::Ptr(_) | RecordData::Other => {}
+            }
+        }
+    }
+
+    /// The devices found so far, without duplicates.
+    ///
+    /// A device announced under several instance names (e.g., on several
+    /// interfaces) is reported once, under the first name.
+    fn envoys(&self) -> Vec<DiscoveredEnvoy> {
+        let mut envoys: Vec<DiscoveredEnvoy> = Vec::new();
+        for name in &self.instances {
+            let key = name.to_ascii_lowercase();
+            let Some(&source) = self.sources.get(&key) else {
+                continue;
+            };
+            let hostname = self.hosts.get(&key);
+            let address = hostname
+                .and_then(|host| self.addresses.get(&host.to_ascii_lowercase()))
+                .copied()
+                .unwrap_or(source);
+            let property =
+                |property: &str| -> Option<&String> { self.properties.get(&key)?.get(property) };
+            let envoy = DiscoveredEnvoy {
+                name: name.clone(),
+                address,
+                hostname: hostname.cloned(),
+                serial_number: property("serialnum")
+                    .and_then(|serial| SerialNumber::parse(serial).ok()),
+                firmware_version: property("protovers").cloned(),
+            };
+
+            let duplicate =
+                envoys
+                    .iter()
+                    .any(|known| match (&known.serial_number, &envoy.serial_number) {
+                        (Some(known_serial), Some(serial)) => known_serial == serial,
+                        _ => known.address == envoy.address,
+                    });
+            if !duplicate {
+                envoys.push(envoy);
+            }
+        }
+        envoys
+    }
+}
+
+/// A resource record from an mDNS response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Record {
+    /// The name the record belongs to.
+    name: String,
+    /// The data of the record.
+    data: RecordData,
+}
+
+/// The data of a resource record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordData {
+    /// A pointer to a service instance.
+    Ptr(String),
+    /// The target host of a service instance (the port is not used).
+    Srv(String),
+    /// The `key=value` properties of a service instance.
+    Txt(Vec<(String, String)>),
+    /// An IPv4 address.
+    A(Ipv4Addr),
+    /// A record of another type.
+    Other,
+}
+
+/// Parse the records of an mDNS response.
+///
+/// Records with a TTL of zero (announcing that a record is withdrawn) are
+/// skipped. Returns `None` if the packet is not a response or is malformed.
+fn parse_response(packet: &[u8]) -> Option<Vec<Record>> {
+    let mut reader = Reader::new(packet);
+    let header = reader.take(12)?;
+    let count = |index: usize| -> Option<usize> {
+        let bytes = header.get(index..index.checked_add(2)?)?;
+        Some(usize::from(u16::from_be_bytes(bytes.try_into().ok()?)))
+    };
+    let is_response = header.get(2).is_some_and(|flags| flags & 0x80 != 0);
+    if !is_response {
+        return None;
+    }
+
+    for _ in 0..count(4)? {
+        reader.name()?;
+        reader.take(4)?;
+    }
+
+    let total = [count(6)?, count(8)?, count(10)?]
+        .into_iter()
+        .sum::<usize>();
+    let mut records = Vec::new();
+    for _ in 0..total {
+        let name = reader.name()?;
+        let record_type = reader.u16()?;
+        reader.take(2)?;
+        let ttl = reader.u32()?;
+        let length = usize::from(reader.u16()?);
+        let data_start = reader.position;
+        let data = reader.take(length)?;
+        if ttl == 0 {
+            continue;
+        }
+
+        let mut data_reader = Reader {
+            packet,
+            position: data_start,
+        };
+        let parsed = match record_type {
+            TYPE_PTR => RecordData::Ptr(data_reader.name()?),
+            TYPE_SRV => {
+                // Priority, weight and port precede the target.
+                data_reader.take(6)?;
+                RecordData::Srv(data_reader.name()?)
+            }
+            TYPE_TXT => RecordData::Txt(parse_txt(data)),
+            TYPE_A => {
+                let octets: [u8; 4] = data.try_into().ok()?;
+                RecordData::A(Ipv4Addr::from(octets))
+            }
+            _ => RecordData::Other,
+        };
+        records.push(Record { name, data: parsed });
+    }
+    Some(records)
+}
+
+/// Parse the `key=value` strings of a TXT record.
+///
+/// Keys are lowercased, strings without a value are skipped, and a truncated
+/// string ends the record.
+fn parse_txt(data: &[u8]) -> Vec<(String, String)> {
+    let mut reader = Reader::new(data);
+    let mut properties = Vec::new();
+    while let Some(length) = reader.u8() {
+        let Some(entry) = reader.take(usize::from(length)) else {
+            break;
+        };
+        let text = String::from_utf8_lossy(entry);
+        if let Some((key, value)) = text.split_once('=') {
+            properties.push((key.to_ascii_lowercase(), value.to_owned()));
+        }
+    }
+    properties
+}
+
+/// A cursor over a DNS packet.
+struct Reader<'a> {
+    /// The whole packet, as compressed names refer to earlier offsets.
+    packet: &'a [u8],
+    /// The offset of the next byte to read.
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Create a reader at the start of the packet.
+    const fn new(packet: &'a [u8]) -> Self {
+        Self {
+            packet,
+            position: 0,
+        }
+    }
+
+    /// Read the given number of bytes.
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(length)?;
+        let bytes = self.packet.get(self.position..end)?;
+        self.position = end;
+        Some(bytes)
+    }
+
+    /// Read a byte.
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1)?.first().copied()
+    }
+
+    /// Read a big-endian 16-bit integer.
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    /// Read a big-endian 32-bit integer.
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    /// Read a (possibly compressed) domain name, without the trailing dot.
+    fn name(&mut self) -> Option<String> {
+        let mut labels: Vec<String> = Vec::new();
+        let mut position = self.position;
+        let mut resume = None;
+        for _ in 0..MAX_LABELS {
+            let length = *self.packet.get(position)?;
+            let start = position.checked_add(1)?;
+            if length & 0xC0 == 0xC0 {
+                let low = *self.packet.get(start)?;
+                resume.get_or_insert(start.checked_add(1)?);
+                position = usize::from(u16::from_be_bytes([length & 0x3F, low]));
+            } else if length == 0 {
+                self.position = resume.unwrap_or(start);
+                return Some(labels.join("."));
+            } else {
+                let end = start.checked_add(usize::from(length))?;
+                labels.push(String::from_utf8_lossy(self.packet.get(start..end)?).into_owned());
+                position = end;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// A record to encode in a test response.
+    enum TestRecord<'a> {
+        Ptr(&'a str, &'a str),
+        Srv(&'a str, &'a str),
+        Txt(&'a str, &'a [&'a str]),
+        A(&'a str, [u8; 4]),
+    }
+
+    /// Encode an uncompressed name.
+    fn encode_name(name: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for label in name.split('.') {
+            bytes.push(u8::try_from(label.len()).expect("Label too long"));
+            bytes.extend_from_slice(label.as_bytes());
+        }
+        bytes.push(0);
+        bytes
+    }
+
+    /// Encode an mDNS response holding the given records.
+    fn response(records: &[TestRecord<'_>]) -> Vec<u8> {
+        let count = u16::try_from(records.len()).expect("Too many records");
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0];
+        packet.extend_from_slice(&count.to_be_bytes());
+        packet.extend_from_slice(&[0, 0, 0, 0]);
+        for record in records {
+            let (name, record_type, data) = match *record {
+                TestRecord::Ptr(name, target) => (name, TYPE_PTR, encode_name(target)),
+                TestRecord::Srv(name, target) => {
+                    let mut data = vec![0, 0, 0, 0, 0, 80];
+                    data.extend(encode_name(target));
+                    (name, TYPE_SRV, data)
+                }
+                TestRecord::Txt(name, entries) => {
+                    let mut data = Vec::new();
+                    for entry in entries {
+                        data.push(u8::try_from(entry.len()).expect("Entry too long"));
+                        data.extend_from_slice(entry.as_bytes());
+                    }
+                    (name, TYPE_TXT, data)
+                }
+                TestRecord::A(name, octets) => (name, TYPE_A, octets.to_vec()),
+            };
+            packet.extend(encode_name(name));
+            packet.extend_from_slice(&record_type.to_be_bytes());
+            packet.extend_from_slice(&[0x80, 0x01, 0, 0, 0x11, 0x94]);
+            let length = u16::try_from(data.len()).expect("Record too long");
+            packet.extend_from_slice(&length.to_be_bytes());
+            packet.extend(data);
+        }
+        packet
+    }
+
+    /// The full announcement of an Envoy.
+    fn announcement(instance: &str, host: &str, serial: &str, address: [u8; 4]) -> Vec<u8> {
+        let serialnum = format!("serialnum={serial}");
+        response(&[
+            TestRecord::Ptr(SERVICE, instance),
+            TestRecord::Srv(instance, host),
+            TestRecord::Txt(instance, &["txtvers=1", &serialnum, "protovers=7.6.175"]),
+            TestRecord::A(host, address),
+        ])
+    }
+
+    fn envoy(name: &str, address: [u8; 4], serial: &str) -> DiscoveredEnvoy {
+        DiscoveredEnvoy {
+            name: format!("{name}.{SERVICE}"),
+            address: Ipv4Addr::from(address),
+            hostname: Some(format!("{name}.local")),
+            serial_number: Some(SerialNumber::parse(serial).expect("Valid serial number")),
+            firmware_version: Some("7.6.175".to_owned()),
+        }
+    }
+
+    const SOURCE: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+
+    #[test]
+    fn query_format() {
+        let mut expected = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        expected.extend(encode_name(SERVICE));
+        expected.extend_from_slice(&[0, 12, 0x80, 0x01]);
+
+        assert_eq!(query_packet(), expected);
+    }
+
+    #[test]
+    fn single_envoy() {
+        let mut browser = Browser::default();
+        browser.handle(
+            &announcement(
+                &format!("envoy.{SERVICE}"),
+                "envoy.local",
+                "482243012345",
+                [192, 168, 1, 20],
+            ),
+            SOURCE,
+        );
+
+        assert_eq!(
+            browser.envoys(),
+            vec![envoy("envoy", [192, 168, 1, 20], "482243012345")]
+        );
+    }
+
+    #[test]
+    fn multiple_envoys_deduplicated() {
+        let first = announcement(
+            &format!("envoy.{SERVICE}"),
+            "envoy.local",
+            "482243012345",
+            [192, 168, 1, 20],
+        );
+        let second = announcement(
+            &format!("garage.{SERVICE}"),
+            "garage.local",
+            "121212121212",
+            [192, 168, 2, 30],
+        );
+        // The same Envoy, announced under another name on another interface.
+        let alias = announcement(
+            &format!("envoy-2.{SERVICE}"),
+            "envoy-2.local",
+            "482243012345",
+            [10, 0, 0, 20],
+        );
+
+        let mut browser = Browser::default();
+        for packet in [&first, &second, &first, &alias, &second] {
+            browser.handle(packet, SOURCE);
+        }
+
+        assert_eq!(
+            browser.envoys(),
+            vec![
+                envoy("envoy", [192, 168, 1, 20], "482243012345"),
+                envoy("garage", [192, 168, 2, 30], "121212121212"),
+            ]
+        );
+    }
+
+    #[test]
+    fn partial_announcement() {
+        let instance = format!("Envoy.{SERVICE}");
+        let mut browser = Browser::default();
+        browser.handle(&response(&[TestRecord::Ptr(SERVICE, &instance)]), SOURCE);
+
+        assert_eq!(
+            browser.envoys(),
+            vec![DiscoveredEnvoy {
+                name: instance.clone(),
+                address: SOURCE,
+                hostname: None,
+                serial_number: None,
+                firmware_version: None,
+            }]
+        );
+
+        // The remaining records arrive later, with names in another case.
+        browser.handle(
+            &response(&[
+                TestRecord::Srv(&instance.to_ascii_uppercase(), "envoy.local"),
+                TestRecord::Txt(&instance, &["SerialNum=482243012345"]),
+            ]),
+            SOURCE,
+        );
+        let envoys = browser.envoys();
+        assert_eq!(envoys.len(), 1);
+        assert_eq!(
+            envoys
+                .first()
+                .and_then(|found| found.serial_number.as_ref())
+                .map(SerialNumber::as_str),
+            Some("482243012345")
+        );
+    }
+
+    #[test]
+    fn compressed_names() {
+        // The PTR data and the following record name point back into the
+        // question name at offset 12.
+        let mut packet = vec![0, 0, 0x84, 0, 0, 1, 0, 2, 0, 0, 0, 0];
+        packet.extend(encode_name(SERVICE));
+        packet.extend_from_slice(&[0, 12, 0, 1]);
+        packet.extend_from_slice(&[0xC0, 12, 0, 12, 0, 1, 0, 0, 0, 120, 0, 8]);
+        packet.extend_from_slice(&[5, b'e', b'n', b'v', b'o', b'y', 0xC0, 12]);
+        packet.extend_from_slice(&[0xC0, 12, 0, 12, 0, 1, 0, 0, 0, 0, 0, 2, 0xC0, 12]);
+
+        assert_eq!(
+            parse_response(&packet),
+            Some(vec![Record {
+                name: SERVICE.to_owned(),
+                data: RecordData::Ptr(format!("envoy.{SERVICE}")),
+            }])
+        );
+    }
+
+    #[rstest]
+    #[case::empty(&[])]
+    #[case::query(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0])]
+    #[case::truncated(&[0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0, 5, b'e'])]
+    #[case::pointer_loop(&[0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0xC0, 12])]
+    fn malformed_packets(#[case] packet: &[u8]) {
+        assert_eq!(parse_response(packet), None);
+    }
+
+    #[tokio::test]
+    async fn browse_local_responder() {
+        let responder = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("Failed to bind responder");
+        let target = responder.local_addr().expect("Responder has no address");
+        let server = tokio::spawn(async move {
+            let mut buffer = vec![0_u8; MAX_PACKET_SIZE];
+            let (length, peer) = responder
+                .recv_from(&mut buffer)
+                .await
+                .expect("Failed to receive query");
+            assert_eq!(buffer.get(..length), Some(query_packet().as_slice()));
+            for packet in [
+                announcement(
+                    &format!("envoy.{SERVICE}"),
+                    "envoy.local",
+                    "482243012345",
+                    [192, 168, 1, 20],
+                ),
+                announcement(
+                    &format!("envoy.{SERVICE}"),
+                    "envoy.local",
+                    "482243012345",
+                    [192, 168, 1, 20],
+                ),
+                announcement(
+                    &format!("garage.{SERVICE}"),
+                    "garage.local",
+                    "121212121212",
+                    [192, 168, 2, 30],
+                ),
+            ] {
+                responder
+                    .send_to(&packet, peer)
+                    .await
+                    .expect("Failed to answer");
+            }
+        });
+
+        let envoys = browse(target, Duration::from_millis(500), false)
+            .await
+            .expect("Browsing should succeed");
+        server.await.expect("Responder failed");
+
+        assert_eq!(
+            envoys,
+            vec![
+                envoy("envoy", [192, 168, 1, 20], "482243012345"),
+                envoy("garage", [192, 168, 2, 30], "121212121212"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn browse_first() {
+        let responder = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .expect("Failed to bind responder");
+        let target = responder.local_addr().expect("Responder has no address");
+        let server = tokio::spawn(async move {
+            let mut buffer = vec![0_u8; MAX_PACKET_SIZE];
+            let (_, peer) = responder
+                .recv_from(&mut buffer)
+                .await
+                .expect("Failed to receive query");
+            let packet = announcement(
+                &format!("envoy.{SERVICE}"),
+                "envoy.local",
+                "482243012345",
+                [192, 168, 1, 20],
+            );
+            responder
+                .send_to(&packet, peer)
+                .await
+                .expect("Failed to answer");
+        });
+
+        let started = Instant::now();
+        let envoys = browse(target, Duration::from_secs(30), true)
+            .await
+            .expect("Browsing should succeed");
+        server.await.expect("Responder failed");
+
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "Browsing should stop at the first device"
+        );
+        let client = envoys.first().expect("An Envoy should be found").client();
+        let debug = format!("{client:?}");
+        assert!(
+            debug.contains(r#"base_url: "https://192.168.1.20""#),
+            "Client should connect to the Envoy: {debug}"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! # Entrez client builder
+//!
+//! This module provides a builder for configuring the Entrez client, for
+//! setups which differ from the defaults of [`Entrez::new`] (e.g., a slow
+//! network, or retries on transient failures).
+
+use core::time::Duration;
+
+use super::{DEFAULT_ENTREZ_URL, Entrez};
+use crate::{client::envoy::RetryPolicy, error::Result};
+
+/// The default timeout for requests to Entrez.
+pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A builder for an [`Entrez`] client.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::time::Duration;
+/// use enphase_api::{Entrez, RetryPolicy};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = Entrez::builder()
+///     .timeout(Duration::from_secs(60))
+///     .retry(RetryPolicy::new(3))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct EntrezBuilder {
+    /// The base URL of the Entrez service.
+    url: String,
+    /// The timeout for each request.
+    timeout: Duration,
+    /// The policy for retrying failed requests.
+    retry: RetryPolicy,
+}
+
+impl EntrezBuilder {
+    /// Create a builder for the official Entrez service with the default
+    /// configuration.
+    pub(super) fn new() -> Self {
+        Self {
+            url: DEFAULT_ENTREZ_URL.to_owned(),
+            timeout: DEFAULT_TIMEOUT,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Set the base URL of the Entrez service.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The base URL to use (the official Entrez service by default)
+    #[inline]
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Set the timeout for each request.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The timeout to use (30 seconds by default)
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the policy for retrying failed requests.
+    ///
+    /// The login and token generation forms are not idempotent, so they are
+    /// only retried if they were clearly not sent (the connection could not be
+    /// established), whatever the policy. The site search is retried as any
+    /// read.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The retry policy (no retries by default)
+    #[inline]
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Build the Entrez client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the HTTP client cannot be created.
+    #[inline]
+    pub fn build(self) -> Result<Entrez> {
+        let client = reqwest::Client::builder()
+            .user_agent(format!("enphase-api/{}", env!("CARGO_PKG_VERSION")))
+            .cookie_store(true)
+            .timeout(self.timeout)
+            .build()?;
+
+        Ok(Entrez {
+            client,
+            base_url: self.url,
+            retry: self.retry,
+        })
+    }
+}
@@ -0,0 +1,968 @@
+//! # Envoy client builder
+//!
+//! This module provides a builder for configuring how the Envoy client
+//! connects to the gateway, for setups which differ from the defaults of
+//! [`Envoy::new`] (e.g., an Envoy served over plain HTTP behind a reverse
+//! proxy).
+//!
+//! Very old gateways (e.g., the Envoy-R and early Envoy-S) only negotiate
+//! TLS 1.0 or 1.1, which the TLS backend (rustls) does not support. Requesting
+//! such a version is reported as a configuration error; these gateways can
+//! instead be reached over plain HTTP, either explicitly with [`Scheme::Http`]
+//! or automatically with [`EnvoyBuilder::http_fallback`].
+
+use alloc::sync::Arc;
+use core::{
+    fmt::{self, Display},
+    net::Ipv6Addr,
+    str::FromStr,
+    time::Duration,
+};
+use std::sync::RwLock;
+
+use rustls::client::danger::ServerCertVerifier;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::{
+    Capabilities, Envoy, FirmwareGeneration, RetryPolicy,
+    tls::{self, CaVerifier, PinnedVerifier},
+    token::ExpiryHook,
+};
+use crate::{
+    error::{EnphaseError, ParseEnumError, Result},
+    models::EnvoyToken,
+};
+
+/// The default timeout for requests to the Envoy.
+pub(super) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The scheme used to connect to the Envoy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    /// Plain HTTP.
+    Http,
+    /// HTTPS.
+    #[default]
+    Https,
+}
+
+impl Display for Scheme {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Http => "http",
+            Self::Https => "https",
+        })
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = ParseEnumError;
+
+    #[inline]
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(Self::Http),
+            "https" => Ok(Self::Https),
+            _ => Err(ParseEnumError::new("Scheme", s)),
+        }
+    }
+}
+
+/// A version of the TLS protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[non_exhaustive]
+pub enum TlsVersion {
+    /// TLS 1.0 (not supported).
+    Tls1_0,
+    /// TLS 1.1 (not supported).
+    Tls1_1,
+    /// TLS 1.2.
+    #[default]
+    Tls1_2,
+    /// TLS 1.3.
+    Tls1_3,
+}
+
+impl TlsVersion {
+    /// The name of the version, as used in messages (e.g., `TLS 1.2`).
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Tls1_0 => "TLS 1.0",
+            Self::Tls1_1 => "TLS 1.1",
+            Self::Tls1_2 => "TLS 1.2",
+            Self::Tls1_3 => "TLS 1.3",
+        }
+    }
+}
+
+impl Display for TlsVersion {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Tls1_0 => "tls-1.0",
+            Self::Tls1_1 => "tls-1.1",
+            Self::Tls1_2 => "tls-1.2",
+            Self::Tls1_3 => "tls-1.3",
+        })
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = ParseEnumError;
+
+    #[inline]
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "tls-1.0" => Ok(Self::Tls1_0),
+            "tls-1.1" => Ok(Self::Tls1_1),
+            "tls-1.2" => Ok(Self::Tls1_2),
+            "tls-1.3" => Ok(Self::Tls1_3),
+            _ => Err(ParseEnumError::new("TlsVersion", s)),
+        }
+    }
+}
+
+/// A builder for an [`Envoy`] client.
+///
+/// The defaults match [`Envoy::new`]: HTTPS on the default port, without
+/// certificate verification (as Envoy devices use self-signed certificates),
+/// and a 30 second timeout.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::time::Duration;
+/// use enphase_api::{Envoy, Scheme};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = Envoy::builder("proxy.local")
+///     .scheme(Scheme::Http)
+///     .port(8080)
+///     .timeout(Duration::from_secs(10))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+#[must_use]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "The flags are independent connection options"
+)]
+pub struct EnvoyBuilder {
+    /// The hostname or IP address of the Envoy.
+    host: String,
+    /// The scheme used to connect.
+    scheme: Scheme,
+    /// The port to connect to, if not the default port of the scheme.
+    port: Option<u16>,
+    /// The timeout for each request.
+    timeout: Duration,
+    /// Whether the certificate presented by the Envoy is verified.
+    verify_certificates: bool,
+    /// The PEM-encoded certificate the Envoy must present, if pinned.
+    pinned_certificate: Option<Vec<u8>>,
+    /// The PEM-encoded CA certificates to verify the Envoy against, if any.
+    ca_bundle: Option<Vec<u8>>,
+    /// The hostname to verify the certificate against, if not the host.
+    tls_server_name: Option<String>,
+    /// The minimum TLS version to negotiate.
+    min_tls_version: TlsVersion,
+    /// Whether [`EnvoyBuilder::connect`] may fall back to plain HTTP.
+    http_fallback: bool,
+    /// The policy for retrying failed requests.
+    retry: RetryPolicy,
+    /// Whether redirects from HTTP to HTTPS are followed once.
+    allow_scheme_upgrade: bool,
+    /// Whether the headers of the local web UI are sent.
+    browser_compatible_headers: bool,
+    /// The token to start with, if any.
+    token: Option<String>,
+    /// Whether the token is verified before its first use.
+    verify_token: bool,
+    /// The hook called when the token is about to expire, if any.
+    token_expiring: Option<ExpiryHook>,
+    /// The capabilities known from a previous client.
+    capabilities: Capabilities,
+}
+
+impl EnvoyBuilder {
+    /// Create a builder for the given host with the default configuration.
+    pub(super) fn new(host: impl Display) -> Self {
+        Self {
+            host: host.to_string(),
+            scheme: Scheme::default(),
+            port: None,
+            timeout: DEFAULT_TIMEOUT,
+            verify_certificates: false,
+            pinned_certificate: None,
+            ca_bundle: None,
+            tls_server_name: None,
+            min_tls_version: TlsVersion::default(),
+            http_fallback: false,
+            retry: RetryPolicy::default(),
+            allow_scheme_upgrade: false,
+            browser_compatible_headers: false,
+            token: None,
+            verify_token: false,
+            token_expiring: None,
+            capabilities: Capabilities::default(),
+        }
+    }
+
+    /// Set the scheme used to connect to the Envoy.
+    ///
+    /// # Arguments
+    ///
+    /// * `scheme` - The scheme to use (HTTPS by default)
+    #[inline]
+    pub fn scheme(mut self, scheme: Scheme) -> Self {
+        self.scheme = scheme;
+        self
+    }
+
+    /// Set the port to connect to.
+    ///
+    /// # Arguments
+    ///
+    /// * `port` - The port to use (the default port of the scheme by default)
+    #[inline]
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Set the timeout for each request.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The timeout to use (30 seconds by default)
+    #[inline]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set whether the certificate presented by the Envoy is verified.
+    ///
+    /// Envoy devices use self-signed certificates, so verification is disabled
+    /// by default. Only enable it if the Envoy is served with a certificate
+    /// trusted by the system (e.g., behind a reverse proxy).
+    ///
+    /// # Arguments
+    ///
+    /// * `verify` - Whether to verify the certificate
+    #[inline]
+    pub fn verify_certificates(mut self, verify: bool) -> Self {
+        self.verify_certificates = verify;
+        self
+    }
+
+    /// Pin the certificate presented by the Envoy.
+    ///
+    /// The connection is then only established if the Envoy presents exactly
+    /// this certificate, which protects against interception without requiring
+    /// the self-signed certificate to be trusted by the system. As Envoy
+    /// devices are usually reached by IP address, the hostname and validity
+    /// period of the certificate are not checked. Pinning takes precedence
+    /// over [`EnvoyBuilder::verify_certificates`].
+    ///
+    /// The certificate can be retrieved once with [`Envoy::fetch_certificate`]
+    /// and stored for later use (trust on first use).
+    ///
+    /// # Arguments
+    ///
+    /// * `pem` - The PEM-encoded certificate of the Envoy
+    #[inline]
+    pub fn pinned_certificate(mut self, pem: impl AsRef<[u8]>) -> Self {
+        self.pinned_certificate = Some(pem.as_ref().to_vec());
+        self
+    }
+
+    /// Verify the Envoy against the given CA certificates.
+    ///
+    /// This is suited to gateways which have been re-provisioned with a
+    /// certificate issued by a known (e.g., internal) CA. The certificate chain
+    /// and hostname are then fully verified against the CA certificates only,
+    /// regardless of [`EnvoyBuilder::verify_certificates`]. It cannot be
+    /// combined with [`EnvoyBuilder::pinned_certificate`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pem` - The PEM-encoded CA certificates
+    #[inline]
+    pub fn ca_bundle(mut self, pem: impl AsRef<[u8]>) -> Self {
+        self.ca_bundle = Some(pem.as_ref().to_vec());
+        self
+    }
+
+    /// Set the hostname the certificate is verified against.
+    ///
+    /// By default, the certificate is verified against the host connected to.
+    /// When the Envoy is reached by IP address, this sets the hostname its
+    /// certificate was issued for instead. This requires
+    /// [`EnvoyBuilder::ca_bundle`].
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The hostname the certificate was issued for
+    #[inline]
+    pub fn tls_server_name(mut self, name: impl Display) -> Self {
+        self.tls_server_name = Some(name.to_string());
+        self
+    }
+
+    /// Set the minimum TLS version to negotiate.
+    ///
+    /// Only TLS 1.2 and TLS 1.3 are supported by the TLS backend, so
+    /// requesting an older version makes [`EnvoyBuilder::build`] fail. Use
+    /// [`Scheme::Http`] or [`EnvoyBuilder::http_fallback`] for gateways which
+    /// only support older versions.
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The minimum version (TLS 1.2 by default)
+    #[inline]
+    pub fn min_tls_version(mut self, version: TlsVersion) -> Self {
+        self.min_tls_version = version;
+        self
+    }
+
+    /// Set whether [`EnvoyBuilder::connect`] may fall back to plain HTTP.
+    ///
+    /// When enabled, and no TLS connection can be established (e.g., as the
+    /// gateway only supports TLS versions which are not supported), the client
+    /// connects over plain HTTP instead. As this exposes the token to the
+    /// network, it cannot be combined with certificate verification or
+    /// pinning.
+    ///
+    /// # Arguments
+    ///
+    /// * `fallback` - Whether to fall back to plain HTTP (disabled by default)
+    #[inline]
+    pub fn http_fallback(mut self, fallback: bool) -> Self {
+        self.http_fallback = fallback;
+        self
+    }
+
+    /// Set the policy for retrying failed requests.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The retry policy (no retries by default)
+    #[inline]
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Set whether redirects from HTTP to HTTPS are followed.
+    ///
+    /// Some firmware versions redirect plain HTTP requests to HTTPS. Other
+    /// redirects are never followed (they are reported as errors), and by
+    /// default neither are these, as the client was configured for plain
+    /// HTTP. When enabled, a request redirected to the same resource over
+    /// HTTPS is re-issued once against the redirect location, keeping its
+    /// token. The client is not switched to HTTPS, so each request is
+    /// redirected again; prefer [`Scheme::Https`] once the gateway is known to
+    /// redirect.
+    ///
+    /// # Arguments
+    ///
+    /// * `allow` - Whether to follow redirects to HTTPS (disabled by default)
+    #[inline]
+    pub fn allow_scheme_upgrade(mut self, allow: bool) -> Self {
+        self.allow_scheme_upgrade = allow;
+        self
+    }
+
+    /// Set whether the headers of the local web UI are sent.
+    ///
+    /// Some firmware versions reject requests which do not look like they come
+    /// from the local web UI, with intermittent authentication failures that a
+    /// browser never sees. Firmware 8.2 (e.g. D8.2.4127) is known to answer
+    /// `/ivp/livedata/status` with 401 Unauthorized unless the request has the
+    /// `X-Requested-With` header. When enabled, each request carries the
+    /// headers the web UI sends to the family of the endpoint:
+    /// `X-Requested-With: XMLHttpRequest` and a `Referer` to the UI page, for
+    /// the `/ivp`, `/admin/lib` and `/api/v1` endpoints and the JSON documents
+    /// (such as `/production.json`). Other endpoints (such as `/info`) are
+    /// requested as before.
+    ///
+    /// # Arguments
+    ///
+    /// * `enable` - Whether to send the headers of the web UI (disabled by
+    ///   default)
+    #[inline]
+    pub fn browser_compatible_headers(mut self, enable: bool) -> Self {
+        self.browser_compatible_headers = enable;
+        self
+    }
+
+    /// Set the token attached to requests.
+    ///
+    /// Unlike [`Envoy::authenticate`], the token is not checked against the
+    /// Envoy unless [`EnvoyBuilder::verify_token`] is enabled, so this is
+    /// suited to tokens obtained elsewhere (e.g., persisted after a previous
+    /// authentication, or from another application), with no involvement of
+    /// Entrez.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The JWT token to attach to requests
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use core::time::Duration;
+    /// use enphase_api::Envoy;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let jwt = "";
+    /// let client = Envoy::builder("envoy.local")
+    ///     .token(jwt)
+    ///     .verify_token(true)
+    ///     .on_token_expiring(Duration::from_hours(1), |claims| {
+    ///         println!("Token expiring at {:?}, please renew", claims.expires_at());
+    ///     })
+    ///     .build()?;
+    /// let production = client.production().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn token(mut self, token: impl Display) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Set whether the token is verified before its first use.
+    ///
+    /// When enabled, the token set with [`EnvoyBuilder::token`] is checked
+    /// against the Envoy (as by [`Envoy::authenticate`], including the serial
+    /// numbers) before the first request which needs it, so that calling
+    /// [`Envoy::authenticate`] is not needed. An expired token is rejected
+    /// without contacting the Envoy. The token is verified once per client
+    /// (and its clones); a failed check is repeated on the next request.
+    ///
+    /// # Arguments
+    ///
+    /// * `verify` - Whether to verify the token (disabled by default)
+    #[inline]
+    pub fn verify_token(mut self, verify: bool) -> Self {
+        self.verify_token = verify;
+        self
+    }
+
+    /// Set a hook called when the token is about to expire.
+    ///
+    /// The hook is called once per token, from the first request which needs
+    /// the token within `margin` of its expiry (or after it). It can ask the
+    /// system the token came from for a new one, to be passed to
+    /// [`Envoy::authenticate`]. Tokens which cannot be decoded or have no
+    /// expiry never call the hook.
+    ///
+    /// # Arguments
+    ///
+    /// * `margin` - How long before expiry the hook is called
+    /// * `hook` - The hook, called with the claims of the token
+    #[inline]
+    pub fn on_token_expiring(
+        mut self,
+        margin: Duration,
+        hook: impl Fn(&EnvoyToken) + Send + Sync + 'static,
+    ) -> Self {
+        self.token_expiring = Some(ExpiryHook::new(margin, hook));
+        self
+    }
+
+    /// Set the capabilities known from a previous client.
+    ///
+    /// Known capabilities are used without probing: with a known scheme,
+    /// [`EnvoyBuilder::connect`] does not check whether a TLS connection can
+    /// be established, and with a known information endpoint,
+    /// [`Envoy::info`] requests it directly. See [`Envoy::capabilities`].
+    ///
+    /// # Arguments
+    ///
+    /// * `capabilities` - The capabilities, e.g. parsed with
+    ///   [`Capabilities::from_json`]
+    #[inline]
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Force the capabilities of a firmware generation, skipping detection.
+    ///
+    /// This is for gateways on which probing misfires (e.g., a proxy which
+    /// answers every path). The scheme and the capabilities of the profile
+    /// are set, so neither [`EnvoyBuilder::connect`] nor [`Envoy::info`]
+    /// probe, and the capabilities are never updated; the profile is reported
+    /// in [`Envoy::capabilities`] and in [`EnphaseError::CapabilityMissing`].
+    /// The scheme may still be overridden afterwards with
+    /// [`EnvoyBuilder::scheme`].
+    ///
+    /// # Arguments
+    ///
+    /// * `profile` - The firmware generation of the Envoy
+    #[inline]
+    pub fn firmware_profile(mut self, profile: FirmwareGeneration) -> Self {
+        self.capabilities = profile.capabilities();
+        if let Some(scheme) = self.capabilities.scheme {
+            self.scheme = scheme;
+        }
+        self
+    }
+
+    /// Build the Envoy client.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configured [`Envoy`] client.
+    ///
+    /// # Errors
+    ///
+    /// Returns a configuration error if the pinned certificate or CA bundle is
+    /// not PEM-encoded, if the minimum TLS version is not supported, or if
+    /// incompatible options are combined (e.g., pinning with a CA bundle, or
+    /// plain HTTP fallback with certificate verification). Returns an error if
+    /// the HTTP client cannot be built (e.g., if the TLS backend fails to
+    /// initialize).
+    #[inline]
+    pub fn build(mut self) -> Result<Envoy> {
+        self.validate()?;
+        if self.capabilities.is_forced() {
+            self.capabilities.scheme = Some(self.scheme);
+        }
+
+        let host = url_host(&self.host);
+        let base_url = match self.port {
+            Some(port) => format!("{}://{host}:{port}", self.scheme),
+            None => format!("{}://{host}", self.scheme),
+        };
+
+        let client_builder = reqwest::Client::builder()
+            .user_agent(format!("enphase-api/{}", env!("CARGO_PKG_VERSION")))
+            .cookie_store(true)
+            .timeout(self.timeout)
+            .redirect(reqwest::redirect::Policy::none());
+        let verifier: Option<Arc<dyn ServerCertVerifier>> =
+            match (self.pinned_certificate, self.ca_bundle) {
+                (Some(pem), _) => {
+                    Some(Arc::new(PinnedVerifier::new(tls::parse_certificate(&pem)?)))
+                }
+                (None, Some(bundle)) => Some(Arc::new(CaVerifier::new(
+                    &bundle,
+                    self.tls_server_name.as_deref(),
+                )?)),
+                (None, None) => None,
+            };
+        let client = match verifier {
+            Some(custom) => client_builder
+                .tls_backend_preconfigured(tls::client_config(custom, self.min_tls_version)?),
+            None => client_builder
+                .danger_accept_invalid_certs(!self.verify_certificates)
+                .tls_version_min(match self.min_tls_version {
+                    TlsVersion::Tls1_3 => reqwest::tls::Version::TLS_1_3,
+                    TlsVersion::Tls1_0 | TlsVersion::Tls1_1 | TlsVersion::Tls1_2 => {
+                        reqwest::tls::Version::TLS_1_2
+                    }
+                }),
+        }
+        .build()?;
+
+        Ok(Envoy {
+            client,
+            base_url,
+            token: Arc::new(RwLock::new(self.token)),
+            serial_number: None,
+            device_serial_number: Arc::default(),
+            firmware_version: Arc::default(),
+            retry: self.retry,
+            allow_scheme_upgrade: self.allow_scheme_upgrade,
+            browser_headers: self.browser_compatible_headers,
+            capabilities: Arc::new(RwLock::new(self.capabilities)),
+            device_map: Arc::default(),
+            token_check: self.verify_token.then(Arc::default),
+            expiry_hook: self.token_expiring,
+        })
+    }
+
+    /// Check that the configuration is consistent.
+    fn validate(&self) -> Result<()> {
+        let error = if self.min_tls_version < TlsVersion::Tls1_2 {
+            format!(
+                "{} is not supported by the TLS backend; connect over plain HTTP instead",
+                self.min_tls_version.name()
+            )
+        } else if self.pinned_certificate.is_some() && self.ca_bundle.is_some() {
+            "A pinned certificate cannot be combined with a CA bundle".to_owned()
+        } else if self.tls_server_name.is_some() && self.ca_bundle.is_none() {
+            "A TLS server name can only be set along with a CA bundle".to_owned()
+        } else if self.http_fallback
+            && (self.verify_certificates
+                || self.pinned_certificate.is_some()
+                || self.ca_bundle.is_some())
+        {
+            "Plain HTTP fallback cannot be combined with certificate verification or pinning"
+                .to_owned()
+        } else {
+            return Ok(());
+        };
+        Err(EnphaseError::ConfigurationError(error))
+    }
+
+    /// Build the Envoy client, falling back to plain HTTP if permitted.
+    ///
+    /// Without [`EnvoyBuilder::http_fallback`], this is the same as
+    /// [`EnvoyBuilder::build`]. With it, a request is made to check that a
+    /// TLS connection can be established, and if it cannot, the client is
+    /// built for plain HTTP instead (on the same port, if one is set). The
+    /// outcome is recorded in the [`Envoy::capabilities`] of the client; if
+    /// the scheme is already known (see [`EnvoyBuilder::capabilities`]), no
+    /// request is made.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configured [`Envoy`] client.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in the same cases as [`EnvoyBuilder::build`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use enphase_api::Envoy;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = Envoy::builder("192.168.1.100")
+    ///     .http_fallback(true)
+    ///     .connect()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub async fn connect(self) -> Result<Envoy> {
+        if !self.http_fallback || self.scheme == Scheme::Http {
+            return self.build();
+        }
+        if let Some(scheme) = self.capabilities.scheme {
+            debug!("Connecting over {scheme}, as known from the capabilities");
+            return self.scheme(scheme).build();
+        }
+
+        let fallback = self.clone().scheme(Scheme::Http);
+        let envoy = self.build()?;
+        let scheme = match envoy.client.get(&envoy.base_url).send().await {
+            Err(e) if e.is_connect() => {
+                warn!("Falling back to plain HTTP, as no TLS connection could be established: {e}");
+                Scheme::Http
+            }
+            _ => Scheme::Https,
+        };
+        let connected = if scheme == Scheme::Http {
+            fallback.build()?
+        } else {
+            envoy
+        };
+        connected.update_capabilities(|capabilities| capabilities.scheme = Some(scheme));
+        Ok(connected)
+    }
+}
+
+/// The host as written in a URL, with IPv6 addresses in brackets.
+pub(super) fn url_host(host: &str) -> String {
+    if host.parse::<Ipv6Addr>().is_ok() {
+        format!("[{host}]")
+    } else {
+        host.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(EnvoyBuilder::new("envoy.local"), "https://envoy.local")]
+    #[case(EnvoyBuilder::new("envoy.local").port(8443), "https://envoy.local:8443")]
+    #[case(EnvoyBuilder::new("proxy").scheme(Scheme::Http), "http://proxy")]
+    #[case(
+        EnvoyBuilder::new("192.168.1.100").scheme(Scheme::Http).port(8080),
+        "http://192.168.1.100:8080"
+    )]
+    #[case(EnvoyBuilder::new("fd00::1"), "https://[fd00::1]")]
+    #[case(EnvoyBuilder::new("fd00::1").port(8443), "https://[fd00::1]:8443")]
+    #[case(EnvoyBuilder::new("[fd00::1]"), "https://[fd00::1]")]
+    fn base_url(#[case] builder: EnvoyBuilder, #[case] expected: &str) {
+        let envoy = builder.build().expect("Client should build");
+        assert_eq!(envoy.base_url, expected);
+    }
+
+    #[tokio::test]
+    async fn connect_with_forced_profile_skips_probe() {
+        use wiremock::MockServer;
+
+        let mock_server = MockServer::start().await;
+        let address = mock_server.address();
+        let envoy = EnvoyBuilder::new(address.ip())
+            .port(address.port())
+            .http_fallback(true)
+            .firmware_profile(FirmwareGeneration::V7)
+            .connect()
+            .await
+            .expect("Client should connect");
+
+        assert_eq!(envoy.base_url, format!("https://{address}"));
+        assert_eq!(envoy.capabilities(), FirmwareGeneration::V7.capabilities());
+        assert_eq!(
+            mock_server
+                .received_requests()
+                .await
+                .expect("Requests should be recorded")
+                .len(),
+            0,
+            "No probe should be made"
+        );
+    }
+
+    #[test]
+    fn defaults_match_new() {
+        let built = EnvoyBuilder::new("envoy.local")
+            .build()
+            .expect("Client should build");
+        let new = Envoy::new("envoy.local");
+
+        assert_eq!(built.base_url, new.base_url);
+        assert_eq!(built.token(), None);
+    }
+
+    #[test]
+    fn token_is_stored() {
+        let envoy = EnvoyBuilder::new("envoy.local")
+            .token("persisted_token")
+            .build()
+            .expect("Client should build");
+
+        assert_eq!(envoy.token().as_deref(), Some("persisted_token"));
+    }
+
+    #[rstest]
+    #[case::http(Scheme::Http, "http")]
+    #[case::https(Scheme::Https, "https")]
+    fn scheme_string_round_trip(#[case] scheme: Scheme, #[case] text: &str) {
+        assert_eq!(scheme.to_string(), text);
+        assert_eq!(text.parse::<Scheme>().expect("Should parse"), scheme);
+    }
+
+    #[rstest]
+    #[case::tls1_0(TlsVersion::Tls1_0, "tls-1.0")]
+    #[case::tls1_1(TlsVersion::Tls1_1, "tls-1.1")]
+    #[case::tls1_2(TlsVersion::Tls1_2, "tls-1.2")]
+    #[case::tls1_3(TlsVersion::Tls1_3, "tls-1.3")]
+    fn tls_version_string_round_trip(#[case] version: TlsVersion, #[case] text: &str) {
+        assert_eq!(version.to_string(), text);
+        assert_eq!(text.parse::<TlsVersion>().expect("Should parse"), version);
+    }
+
+    #[test]
+    fn tls_version_from_str_unknown() {
+        let err = "TLS 1.2"
+            .parse::<TlsVersion>()
+            .expect_err("Unknown value should fail to parse");
+        assert_eq!(err.to_string(), r#"Unknown TlsVersion value "TLS 1.2""#);
+    }
+
+    #[rstest]
+    #[case(TlsVersion::Tls1_0, false)]
+    #[case(TlsVersion::Tls1_1, false)]
+    #[case(TlsVersion::Tls1_2, true)]
+    #[case(TlsVersion::Tls1_3, true)]
+    fn min_tls_version(#[case] version: TlsVersion, #[case] supported: bool) {
+        let result = EnvoyBuilder::new("envoy.local")
+            .min_tls_version(version)
+            .build();
+
+        match result {
+            Ok(_) => assert!(supported, "{version} should be rejected"),
+            Err(EnphaseError::ConfigurationError(message)) => {
+                assert!(!supported, "{version} should be accepted");
+                assert!(
+                    message.contains(version.name()),
+                    "Message should name the version: {message}"
+                );
+            }
+            Err(e) => panic!("Expected a configuration error, got {e:?}"),
+        }
+    }
+
+    #[rstest]
+    #[case(EnvoyBuilder::new("envoy.local").verify_certificates(true))]
+    #[case(EnvoyBuilder::new("envoy.local").pinned_certificate(include_bytes!("../../../fixtures/tls/envoy.pem")))]
+    #[case(EnvoyBuilder::new("envoy.local").ca_bundle(include_bytes!("../../../fixtures/tls/ca.pem")))]
+    fn http_fallback_requires_insecure_tls(#[case] builder: EnvoyBuilder) {
+        let result = builder.http_fallback(true).build();
+
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Expected a configuration error, got {result:?}"
+        );
+    }
+
+    #[rstest]
+    #[case::server_name_without_bundle(EnvoyBuilder::new("envoy.local").tls_server_name("envoy.example"))]
+    #[case::pinned_with_bundle(
+        EnvoyBuilder::new("envoy.local")
+            .ca_bundle(include_bytes!("../../../fixtures/tls/ca.pem"))
+            .pinned_certificate(include_bytes!("../../../fixtures/tls/envoy.pem"))
+    )]
+    #[case::empty_bundle(EnvoyBuilder::new("envoy.local").ca_bundle(""))]
+    #[case::invalid_server_name(
+        EnvoyBuilder::new("envoy.local")
+            .ca_bundle(include_bytes!("../../../fixtures/tls/ca.pem"))
+            .tls_server_name("not a hostname")
+    )]
+    fn invalid_ca_configuration(#[case] builder: EnvoyBuilder) {
+        let result = builder.build();
+
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Expected a configuration error, got {result:?}"
+        );
+    }
+
+    #[rstest]
+    #[case(true, "http")]
+    #[case(false, "https")]
+    #[tokio::test]
+    async fn connect_to_plain_http(#[case] fallback: bool, #[case] scheme: &str) {
+        use wiremock::MockServer;
+
+        let mock_server = MockServer::start().await;
+        let address = mock_server.address();
+        let envoy = EnvoyBuilder::new(address.ip())
+            .port(address.port())
+            .http_fallback(fallback)
+            .connect()
+            .await
+            .expect("Client should connect");
+
+        assert_eq!(envoy.base_url, format!("{scheme}://{address}"));
+    }
+
+    #[tokio::test]
+    async fn connect_records_fallback() {
+        use wiremock::MockServer;
+
+        let mock_server = MockServer::start().await;
+        let address = mock_server.address();
+        let envoy = EnvoyBuilder::new(address.ip())
+            .port(address.port())
+            .http_fallback(true)
+            .connect()
+            .await
+            .expect("Client should connect");
+
+        assert_eq!(envoy.capabilities().scheme, Some(Scheme::Http));
+    }
+
+    #[tokio::test]
+    async fn connect_with_known_scheme_skips_probe() {
+        use wiremock::MockServer;
+
+        let mock_server = MockServer::start().await;
+        let address = mock_server.address();
+        let capabilities =
+            Capabilities::from_json(r#"{"scheme":"http"}"#).expect("Capabilities should parse");
+        let envoy = EnvoyBuilder::new(address.ip())
+            .port(address.port())
+            .http_fallback(true)
+            .capabilities(capabilities)
+            .connect()
+            .await
+            .expect("Client should connect");
+
+        assert_eq!(envoy.base_url, format!("http://{address}"));
+        assert_eq!(envoy.capabilities(), capabilities);
+        assert_eq!(
+            mock_server
+                .received_requests()
+                .await
+                .expect("Requests should be recorded")
+                .len(),
+            0,
+            "No probe should be made"
+        );
+    }
+
+    #[tokio::test]
+    async fn timeout_is_reported() {
+        use wiremock::matchers::method;
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("[]")
+                    .set_delay(Duration::from_secs(5)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let address = mock_server.address();
+        let envoy = EnvoyBuilder::new(address.ip())
+            .scheme(Scheme::Http)
+            .port(address.port())
+            .timeout(Duration::from_millis(100))
+            .token("persisted_token")
+            .build()
+            .expect("Client should build");
+
+        let result = envoy.inverters_production().await;
+        assert!(
+            matches!(result, Err(EnphaseError::Timeout(_))),
+            "Expected a timeout, got {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn plain_http_on_custom_port() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/production/inverters"))
+            .and(header("Authorization", "Bearer persisted_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let address = mock_server.address();
+        let envoy = EnvoyBuilder::new(address.ip())
+            .scheme(Scheme::Http)
+            .port(address.port())
+            .token("persisted_token")
+            .build()
+            .expect("Client should build");
+
+        let inverters = envoy
+            .inverters_production()
+            .await
+            .expect("Request should reach the mock server");
+        assert!(inverters.is_empty(), "Mock server serves no inverters");
+    }
+}
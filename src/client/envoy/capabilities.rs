@@ -0,0 +1,277 @@
+//! # Known capabilities
+//!
+//! Some requests need probing before the client knows how to reach the Envoy:
+//! [`EnvoyBuilder::connect`] checks whether a TLS connection can be
+//! established before falling back to plain HTTP, and [`Envoy::info`] falls
+//! back to `/info.xml` on firmware which does not serve `/info`. Each probe
+//! costs a round trip, which matters on slow or metered links.
+//!
+//! The outcome of the probes is recorded in [`Capabilities`], which can be
+//! serialized and supplied to a later client with
+//! [`EnvoyBuilder::capabilities`]. Known capabilities are used without
+//! probing; an endpoint which stops working (e.g., after a firmware update)
+//! is probed again and the capabilities updated.
+//!
+//! Probing can also be skipped entirely by forcing a [`FirmwareGeneration`]
+//! with [`EnvoyBuilder::firmware_profile`], for gateways on which detection
+//! misfires. The capabilities of the profile are then used as-is, and never
+//! probed again or updated.
+//!
+//! [`EnvoyBuilder::connect`]: super::EnvoyBuilder::connect
+//! [`EnvoyBuilder::capabilities`]: super::EnvoyBuilder::capabilities
+//! [`EnvoyBuilder::firmware_profile`]: super::EnvoyBuilder::firmware_profile
+//! [`Envoy::info`]: super::Envoy::info
+
+use core::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::Scheme;
+use crate::{
+    endpoint::Endpoint,
+    error::{ParseEnumError, Result},
+};
+
+/// The capabilities of an Envoy, as learned by probing.
+///
+/// Each capability is `None` until it has been probed (or supplied).
+///
+/// # Example
+///
+/// ```no_run
+/// use enphase_api::{Capabilities, Envoy};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = Envoy::builder("192.168.1.100")
+///     .http_fallback(true)
+///     .connect()
+///     .await?;
+/// client.info().await?;
+/// let saved = client.capabilities().to_json()?;
+///
+/// // Later, skip the probes
+/// let client = Envoy::builder("192.168.1.100")
+///     .http_fallback(true)
+///     .capabilities(Capabilities::from_json(&saved)?)
+///     .connect()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The scheme the Envoy accepts connections on, as probed by
+    /// [`EnvoyBuilder::connect`](super::EnvoyBuilder::connect).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheme: Option<Scheme>,
+    /// The endpoint serving the device information.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub info: Option<InfoEndpoint>,
+    /// The firmware profile the capabilities were forced to, if any (see
+    /// [`EnvoyBuilder::firmware_profile`](super::EnvoyBuilder::firmware_profile)).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<FirmwareGeneration>,
+}
+
+impl Capabilities {
+    /// Whether the capabilities were forced to a firmware profile, and so are
+    /// never probed.
+    #[inline]
+    #[must_use]
+    pub const fn is_forced(&self) -> bool {
+        self.profile.is_some()
+    }
+
+    /// Serialize the capabilities to JSON.
+    ///
+    /// # Returns
+    ///
+    /// Returns the capabilities as a JSON object.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::JsonError`](crate::EnphaseError::JsonError)
+    /// if serialization fails.
+    #[inline]
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Parse capabilities serialized by [`Capabilities::to_json`].
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The serialized capabilities
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed capabilities.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::JsonError`](crate::EnphaseError::JsonError)
+    /// if the JSON is not valid.
+    #[inline]
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+/// A generation of Envoy firmware, whose capabilities are known without
+/// probing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "kebab-case")]
+pub enum FirmwareGeneration {
+    /// Firmware before 7.0, served over plain HTTP with the device information
+    /// at `/info.xml`.
+    Legacy,
+    /// Firmware 7.0 and later, served over HTTPS with the device information
+    /// at `/info`.
+    V7,
+}
+
+impl FirmwareGeneration {
+    /// The capabilities of the firmware generation, forced to it.
+    #[inline]
+    #[must_use]
+    pub const fn capabilities(self) -> Capabilities {
+        let (scheme, info) = match self {
+            Self::Legacy => (Scheme::Http, InfoEndpoint::InfoXml),
+            Self::V7 => (Scheme::Https, InfoEndpoint::Info),
+        };
+        Capabilities {
+            scheme: Some(scheme),
+            info: Some(info),
+            profile: Some(self),
+        }
+    }
+}
+
+impl Display for FirmwareGeneration {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Legacy => "legacy",
+            Self::V7 => "v7",
+        })
+    }
+}
+
+impl FromStr for FirmwareGeneration {
+    type Err = ParseEnumError;
+
+    #[inline]
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "legacy" => Ok(Self::Legacy),
+            "v7" => Ok(Self::V7),
+            _ => Err(ParseEnumError::new("FirmwareGeneration", s)),
+        }
+    }
+}
+
+/// The endpoint serving the device information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum InfoEndpoint {
+    /// `/info`, served by current firmware.
+    #[serde(rename = "/info")]
+    Info,
+    /// `/info.xml`, served by older firmware.
+    #[serde(rename = "/info.xml")]
+    InfoXml,
+}
+
+impl InfoEndpoint {
+    /// The endpoint to request.
+    pub(super) fn endpoint(self) -> Endpoint {
+        match self {
+            Self::Info => Endpoint::info(),
+            Self::InfoXml => Endpoint::info_xml(),
+        }
+    }
+
+    /// The other endpoint, tried when this one does not exist.
+    pub(super) const fn alternative(self) -> Self {
+        match self {
+            Self::Info => Self::InfoXml,
+            Self::InfoXml => Self::Info,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::unknown(Capabilities::default(), "{}")]
+    #[case::https(
+        Capabilities { scheme: Some(Scheme::Https), info: Some(InfoEndpoint::Info), profile: None },
+        r#"{"scheme":"https","info":"/info"}"#
+    )]
+    #[case::legacy(
+        Capabilities { scheme: Some(Scheme::Http), info: Some(InfoEndpoint::InfoXml), profile: None },
+        r#"{"scheme":"http","info":"/info.xml"}"#
+    )]
+    fn json_round_trip(#[case] capabilities: Capabilities, #[case] json: &str) {
+        assert_eq!(capabilities.to_json().expect("Should serialize"), json);
+        assert_eq!(
+            Capabilities::from_json(json).expect("Should parse"),
+            capabilities
+        );
+    }
+
+    #[rstest]
+    #[case(
+        FirmwareGeneration::Legacy,
+        r#"{"scheme":"http","info":"/info.xml","profile":"legacy"}"#
+    )]
+    #[case(
+        FirmwareGeneration::V7,
+        r#"{"scheme":"https","info":"/info","profile":"v7"}"#
+    )]
+    fn firmware_profile(#[case] generation: FirmwareGeneration, #[case] json: &str) {
+        let capabilities = generation.capabilities();
+
+        assert!(capabilities.is_forced(), "Profile should be forced");
+        assert_eq!(capabilities.to_json().expect("Should serialize"), json);
+        assert_eq!(
+            generation
+                .to_string()
+                .parse::<FirmwareGeneration>()
+                .expect("Should parse"),
+            generation
+        );
+    }
+
+    #[test]
+    fn json_ignores_unknown_fields() {
+        let capabilities =
+            Capabilities::from_json(r#"{"info":"/info","meters":true}"#).expect("Should parse");
+
+        assert_eq!(capabilities.info, Some(InfoEndpoint::Info));
+        assert_eq!(capabilities.scheme, None);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case(r#"{"scheme":"ftp"}"#)]
+    #[case(r#"{"info":"/home"}"#)]
+    fn json_invalid(#[case] json: &str) {
+        let result = Capabilities::from_json(json);
+
+        assert!(
+            matches!(result, Err(crate::EnphaseError::JsonError(_))),
+            "Expected a JSON error, got {result:?}"
+        );
+    }
+}
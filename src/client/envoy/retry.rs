@@ -0,0 +1,377 @@
+//! # Retrying requests
+//!
+//! Envoy devices regularly drop connections or answer with server errors for
+//! short periods (e.g., while uploading their reports). This module provides
+//! the [`RetryPolicy`] deciding which failed requests are retried, and how
+//! long to wait between attempts.
+//!
+//! Only connection errors, timeouts and server errors (5xx) are retried; other
+//! responses (in particular 401 and 403) are returned immediately. Rejected
+//! certificates surface as connection errors, but are never retried as the
+//! Envoy presents the same certificate on every attempt.
+//!
+//! Reads are always safe to retry. A write which failed may still have been
+//! applied by the Envoy (e.g., when the response timed out), so writes are
+//! handled according to the idempotency of their endpoint (see
+//! [`WriteRetry`]): idempotent writes are repeated, and other writes are only
+//! repeated if they were clearly not sent (the connection could not be
+//! established), or once reading back the state of the Envoy shows that the
+//! failed attempt was not applied.
+//!
+//! The same policy applies to the Entrez client, whose login and token
+//! generation forms are not idempotent.
+
+use core::time::Duration;
+
+use reqwest::{Client, Method, Request, Response};
+use tracing::debug;
+
+use crate::endpoint::Idempotency;
+use crate::error::EnphaseError;
+
+/// The policy for retrying failed requests.
+///
+/// By default, requests are not retried. Retried requests wait for the
+/// initial backoff before the second attempt, with the wait doubling after
+/// each further attempt up to the maximum backoff.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::time::Duration;
+/// use enphase_api::{Envoy, RetryPolicy};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let client = Envoy::builder("envoy.local")
+///     .retry(RetryPolicy::new(3).backoff(Duration::from_secs(1), Duration::from_secs(10)))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[must_use]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first.
+    max_attempts: u32,
+    /// The wait before the second attempt.
+    initial_backoff: Duration,
+    /// The longest wait between two attempts.
+    max_backoff: Duration,
+    /// Whether server errors (5xx) are retried.
+    retry_server_errors: bool,
+    /// Whether writes are retried.
+    retry_writes: bool,
+}
+
+/// How a write which failed is handled before it is attempted again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum WriteRetry {
+    /// The write is attempted again.
+    Repeat,
+    /// The state of the Envoy is read back first, and the write is attempted
+    /// again only if the failed attempt was not applied.
+    ReadBack,
+    /// The write is attempted again only if it was not sent, because the
+    /// connection could not be established.
+    BeforeTransmission,
+    /// The write is not attempted again.
+    Never,
+}
+
+impl Default for RetryPolicy {
+    #[inline]
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy making up to the given number of attempts.
+    ///
+    /// The backoff starts at 500 milliseconds and is capped at 10 seconds.
+    /// Server errors and writes are retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - The maximum number of attempts, including the first
+    ///   (a value of 0 is treated as 1)
+    #[inline]
+    pub const fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: if max_attempts == 0 { 1 } else { max_attempts },
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            retry_server_errors: true,
+            retry_writes: true,
+        }
+    }
+
+    /// Set the wait between attempts.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial` - The wait before the second attempt
+    /// * `max` - The longest wait between two attempts
+    #[inline]
+    pub const fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Set whether server errors (5xx) are retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - Whether to retry server errors (enabled by default)
+    #[inline]
+    pub const fn retry_server_errors(mut self, retry: bool) -> Self {
+        self.retry_server_errors = retry;
+        self
+    }
+
+    /// Set whether writes are retried.
+    ///
+    /// A write which timed out may still have been applied by the Envoy, so
+    /// only writes which are safe to repeat (such as setting the power mode of
+    /// a device, or the tariff) are retried as such. Other writes (such as
+    /// starting a device scan through [`Envoy::request_json`], or logging in
+    /// to Entrez) are never repeated blindly: they are retried only if the
+    /// connection could not be established, or with
+    /// [`Envoy::request_json_with_read_back`] once reading back the state of
+    /// the Envoy shows that the failed attempt was not applied.
+    ///
+    /// [`Envoy::request_json`]: crate::Envoy::request_json
+    /// [`Envoy::request_json_with_read_back`]: crate::Envoy::request_json_with_read_back
+    ///
+    /// # Arguments
+    ///
+    /// * `retry` - Whether to retry writes (enabled by default)
+    #[inline]
+    pub const fn retry_writes(mut self, retry: bool) -> Self {
+        self.retry_writes = retry;
+        self
+    }
+
+    /// How a failed write to an endpoint with the given idempotency is handled,
+    /// depending on whether its state can be read back.
+    pub(super) fn write_retry(&self, idempotency: Idempotency, read_back: bool) -> WriteRetry {
+        match idempotency {
+            _ if !self.retry_writes => WriteRetry::Never,
+            Idempotency::Idempotent => WriteRetry::Repeat,
+            Idempotency::NonIdempotent if read_back => WriteRetry::ReadBack,
+            Idempotency::NonIdempotent => WriteRetry::BeforeTransmission,
+        }
+    }
+
+    /// How a failed request with the given method, to an endpoint with the
+    /// given idempotency, is handled.
+    fn request_retry(
+        &self,
+        method: &Method,
+        idempotency: Idempotency,
+        read_back: bool,
+    ) -> WriteRetry {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => WriteRetry::Repeat,
+            _ => self.write_retry(idempotency, read_back),
+        }
+    }
+
+    /// The maximum number of attempts for a request with the given method, to
+    /// an endpoint with the given idempotency.
+    pub(super) fn attempts_for(
+        &self,
+        method: &Method,
+        idempotency: Idempotency,
+        read_back: bool,
+    ) -> u32 {
+        match self.request_retry(method, idempotency, read_back) {
+            WriteRetry::Never => 1,
+            WriteRetry::Repeat | WriteRetry::ReadBack | WriteRetry::BeforeTransmission => {
+                self.max_attempts
+            }
+        }
+    }
+
+    /// Execute a request to an endpoint with the given idempotency, retrying
+    /// it according to the policy.
+    ///
+    /// The response of the last attempt is returned whatever its status, so
+    /// that callers map it the same way whether or not it was retried.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the last attempt fails to get a response. If the
+    /// request was retried, the error records the number of attempts (see
+    /// [`EnphaseError::Retried`]).
+    pub(crate) async fn execute(
+        &self,
+        client: &Client,
+        request: Request,
+        idempotency: Idempotency,
+    ) -> Result<Response, EnphaseError> {
+        let retry = self.request_retry(request.method(), idempotency, false);
+        let max_attempts = self.attempts_for(request.method(), idempotency, false);
+
+        let mut attempt = 1_u32;
+        loop {
+            let retryable = if attempt < max_attempts {
+                request.try_clone()
+            } else {
+                None
+            };
+            let Some(current) = retryable else {
+                return client
+                    .execute(request)
+                    .await
+                    .map_err(|e| with_attempts(attempt, e.into()));
+            };
+
+            if let Some(response) = self.try_attempt(client, current, attempt, retry).await? {
+                return Ok(response);
+            }
+            tokio::time::sleep(self.backoff_after(attempt)).await;
+            attempt = attempt.saturating_add(1);
+        }
+    }
+
+    /// Make an attempt of a request which may be retried as given.
+    ///
+    /// # Returns
+    ///
+    /// Returns the response, or `None` if the attempt failed in a way which is
+    /// retried.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attempt fails in a way which is not retried.
+    pub(super) async fn try_attempt(
+        &self,
+        client: &Client,
+        request: Request,
+        attempt: u32,
+        retry: WriteRetry,
+    ) -> Result<Option<Response>, EnphaseError> {
+        let before_transmission = retry == WriteRetry::BeforeTransmission;
+        match client.execute(request).await {
+            Ok(response) if before_transmission || !self.retries_status(response.status()) => {
+                Ok(Some(response))
+            }
+            Ok(response) => {
+                debug!("Attempt {attempt} failed: HTTP {}", response.status());
+                Ok(None)
+            }
+            Err(e) if Self::retries_error(&e) && (e.is_connect() || !before_transmission) => {
+                debug!("Attempt {attempt} failed: {e}");
+                Ok(None)
+            }
+            Err(e) => Err(with_attempts(attempt, e.into())),
+        }
+    }
+
+    /// Whether a response with the given status is retried.
+    fn retries_status(&self, status: reqwest::StatusCode) -> bool {
+        self.retry_server_errors && status.is_server_error()
+    }
+
+    /// Whether a request which failed with the given error is retried.
+    ///
+    /// Certificate verification failures are reported as connection errors,
+    /// but are not retried.
+    fn retries_error(error: &reqwest::Error) -> bool {
+        (error.is_connect() || error.is_timeout()) && super::tls::certificate_error(error).is_none()
+    }
+
+    /// The wait after the given (1-based) attempt.
+    pub(super) fn backoff_after(&self, attempt: u32) -> Duration {
+        let factor = 2_u32
+            .checked_pow(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Record the number of attempts in the error of a request which has been
+/// retried.
+pub(super) fn with_attempts(attempts: u32, error: EnphaseError) -> EnphaseError {
+    if attempts > 1 {
+        EnphaseError::Retried {
+            attempts,
+            source: Box::new(error),
+        }
+    } else {
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Method::GET, Idempotency::NonIdempotent, false, 3)]
+    #[case(Method::GET, Idempotency::NonIdempotent, true, 3)]
+    #[case(Method::PUT, Idempotency::Idempotent, false, 1)]
+    #[case(Method::PUT, Idempotency::Idempotent, true, 3)]
+    #[case(Method::POST, Idempotency::Idempotent, true, 3)]
+    #[case(Method::POST, Idempotency::NonIdempotent, true, 3)]
+    #[case(Method::POST, Idempotency::NonIdempotent, false, 1)]
+    fn attempts_for_method(
+        #[case] method: Method,
+        #[case] idempotency: Idempotency,
+        #[case] writes: bool,
+        #[case] expected: u32,
+    ) {
+        let policy = RetryPolicy::new(3).retry_writes(writes);
+
+        assert_eq!(policy.attempts_for(&method, idempotency, false), expected);
+    }
+
+    #[rstest]
+    #[case(Idempotency::Idempotent, true, false, WriteRetry::Repeat)]
+    #[case(Idempotency::Idempotent, true, true, WriteRetry::Repeat)]
+    #[case(
+        Idempotency::NonIdempotent,
+        true,
+        false,
+        WriteRetry::BeforeTransmission
+    )]
+    #[case(Idempotency::NonIdempotent, true, true, WriteRetry::ReadBack)]
+    #[case(Idempotency::Idempotent, false, false, WriteRetry::Never)]
+    #[case(Idempotency::NonIdempotent, false, true, WriteRetry::Never)]
+    fn write_retry_decision(
+        #[case] idempotency: Idempotency,
+        #[case] writes: bool,
+        #[case] read_back: bool,
+        #[case] expected: WriteRetry,
+    ) {
+        let policy = RetryPolicy::new(3).retry_writes(writes);
+
+        assert_eq!(policy.write_retry(idempotency, read_back), expected);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy =
+            RetryPolicy::new(10).backoff(Duration::from_millis(100), Duration::from_millis(500));
+        let backoffs: Vec<u128> = (1..=5)
+            .map(|attempt| policy.backoff_after(attempt).as_millis())
+            .collect();
+
+        assert_eq!(backoffs, vec![100, 200, 400, 500, 500]);
+    }
+
+    #[test]
+    fn default_does_not_retry() {
+        assert_eq!(
+            RetryPolicy::default().attempts_for(&Method::GET, Idempotency::Idempotent, false),
+            1
+        );
+        assert_eq!(RetryPolicy::new(0), RetryPolicy::default());
+    }
+}
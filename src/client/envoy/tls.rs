@@ -0,0 +1,825 @@
+//! # Certificate verification
+//!
+//! Envoy devices serve self-signed certificates, which cannot be verified
+//! against a certificate authority. Rather than disabling verification
+//! altogether, the client can pin the certificate of the Envoy: the handshake
+//! only succeeds if the Envoy presents exactly that certificate. The signatures
+//! of the handshake are still verified, so a server which merely replays the
+//! certificate (without holding its private key) is rejected.
+//!
+//! As Envoy devices are usually reached by IP address, and their certificates
+//! are not issued for it, neither the hostname nor the validity period of the
+//! pinned certificate are checked.
+//!
+//! Gateways which have been re-provisioned with a certificate issued by a
+//! known CA can instead be fully verified against a CA bundle, with the
+//! hostname to check optionally overridden for access by IP address.
+//!
+//! The certificate of a gateway names its serial number in the subject, which
+//! identifies the gateway before any token is sent to it.
+
+use alloc::sync::Arc;
+use core::{
+    error::Error as StdError,
+    fmt::{self, Display},
+};
+use std::{io, sync::Mutex};
+
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, OtherError, RootCertStore,
+    SignatureScheme, SupportedProtocolVersion,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::{CryptoProvider, aws_lc_rs, verify_tls12_signature, verify_tls13_signature},
+    pki_types::{CertificateDer, ServerName, UnixTime, pem::PemObject as _},
+    version,
+};
+
+use super::TlsVersion;
+use crate::{
+    error::{EnphaseError, Result},
+    models::SerialNumber,
+};
+
+/// The DER tag of an object identifier.
+const DER_OID: u8 = 0x06;
+/// The DER tag of a sequence.
+const DER_SEQUENCE: u8 = 0x30;
+/// The DER tag of a set.
+const DER_SET: u8 = 0x31;
+/// The encoded object identifier of the common name attribute (2.5.4.3).
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+/// The encoded object identifier of the serial number attribute (2.5.4.5).
+const OID_SERIAL_NUMBER: &[u8] = &[0x55, 0x04, 0x05];
+
+/// Parse a PEM-encoded certificate.
+///
+/// # Errors
+///
+/// Returns a configuration error if the input does not contain a PEM-encoded
+/// certificate.
+pub(super) fn parse_certificate(pem: &[u8]) -> Result<CertificateDer<'static>> {
+    CertificateDer::from_pem_slice(pem)
+        .map_err(|e| EnphaseError::ConfigurationError(format!("Invalid pinned certificate: {e}")))
+}
+
+/// Encode a certificate as PEM.
+pub(super) fn encode_certificate(certificate: &CertificateDer<'_>) -> String {
+    let encoded = STANDARD.encode(certificate);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        // Base64 is ASCII, so each chunk is valid UTF-8.
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Build a TLS configuration which uses the given certificate verifier.
+///
+/// Versions older than TLS 1.2 are not supported, and are treated as TLS 1.2.
+pub(super) fn client_config(
+    verifier: Arc<dyn ServerCertVerifier>,
+    min_version: TlsVersion,
+) -> Result<ClientConfig> {
+    let versions: &[&SupportedProtocolVersion] = if min_version >= TlsVersion::Tls1_3 {
+        &[&version::TLS13]
+    } else {
+        rustls::DEFAULT_VERSIONS
+    };
+    Ok(
+        ClientConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+            .with_protocol_versions(versions)
+            .map_err(|e| EnphaseError::ConfigurationError(format!("Failed to configure TLS: {e}")))?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth(),
+    )
+}
+
+/// The certificate error which caused a request to fail, if any.
+///
+/// Certificate errors are reported with their own variants rather than as
+/// generic HTTP errors, so that a certificate which does not match the pinned
+/// certificate, is not trusted, or is not valid for the hostname can be told
+/// apart.
+pub(crate) fn certificate_error(error: &reqwest::Error) -> Option<EnphaseError> {
+    let mut current: Option<&(dyn StdError + 'static)> = Some(error);
+    while let Some(cause) = current {
+        if cause.is::<CertificateMismatch>() {
+            return Some(EnphaseError::CertificateMismatch);
+        }
+        current = match cause.downcast_ref::<rustls::Error>() {
+            Some(rustls::Error::InvalidCertificate(CertificateError::Other(other))) => Some(other),
+            Some(
+                rustls_error @ rustls::Error::InvalidCertificate(
+                    CertificateError::NotValidForName
+                    | CertificateError::NotValidForNameContext { .. },
+                ),
+            ) => return Some(EnphaseError::HostnameMismatch(rustls_error.to_string())),
+            Some(rustls_error @ rustls::Error::InvalidCertificate(_)) => {
+                return Some(EnphaseError::UntrustedCertificate(rustls_error.to_string()));
+            }
+            _ => match cause.downcast_ref::<io::Error>() {
+                // The source of an I/O error skips the error it wraps.
+                Some(io_error) => io_error
+                    .get_ref()
+                    .map(|inner| -> &(dyn StdError + 'static) { inner }),
+                None => cause.source(),
+            },
+        };
+    }
+    None
+}
+
+/// The serial numbers named by a certificate.
+///
+/// The serial number of a gateway may appear as the common name or the
+/// `serialNumber` attribute of the subject, or as a label of one of the DNS
+/// names of the certificate. Values which are not serial numbers (e.g., the
+/// `envoy` common name) are skipped.
+///
+/// # Errors
+///
+/// Returns an invalid response error if the certificate cannot be parsed.
+pub(super) fn certificate_serial_numbers(
+    certificate: &CertificateDer<'_>,
+) -> Result<Vec<SerialNumber>> {
+    let parsed = webpki::EndEntityCert::try_from(certificate)
+        .map_err(|e| EnphaseError::InvalidResponse(format!("Invalid certificate: {e}")))?;
+
+    let subject = der_elements(parsed.subject())
+        .filter(|&(tag, _)| tag == DER_SET)
+        .flat_map(|(_, set)| der_elements(set))
+        .filter_map(|(tag, attribute)| {
+            let mut fields = der_elements(attribute);
+            match (tag, fields.next(), fields.next()) {
+                (DER_SEQUENCE, Some((DER_OID, oid)), Some((_, value)))
+                    if oid == OID_COMMON_NAME || oid == OID_SERIAL_NUMBER =>
+                {
+                    core::str::from_utf8(value).ok()
+                }
+                _ => None,
+            }
+        });
+    let names = parsed.valid_dns_names().flat_map(|name| name.split('.'));
+
+    let mut serial_numbers: Vec<SerialNumber> = Vec::new();
+    for serial_number in subject
+        .chain(names)
+        .filter_map(|candidate| SerialNumber::parse(candidate).ok())
+    {
+        if !serial_numbers.contains(&serial_number) {
+            serial_numbers.push(serial_number);
+        }
+    }
+    Ok(serial_numbers)
+}
+
+/// Iterate over the DER elements (tag and contents) encoded in the input.
+///
+/// Iteration stops at the first element which is not well formed.
+fn der_elements(mut input: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    core::iter::from_fn(move || {
+        let (&tag, after_tag) = input.split_first()?;
+        let (&first, after_length) = after_tag.split_first()?;
+        let (length, contents) = if first < 0x80 {
+            (usize::from(first), after_length)
+        } else {
+            let (octets, rest) = after_length.split_at_checked(usize::from(first & 0x7f))?;
+            let long = octets.iter().try_fold(0_usize, |length, &octet| {
+                length.checked_mul(256)?.checked_add(usize::from(octet))
+            })?;
+            (long, rest)
+        };
+        let (element, rest) = contents.split_at_checked(length)?;
+        input = rest;
+        Some((tag, element))
+    })
+}
+
+/// The error raised during the handshake when the pinned certificate is not
+/// presented.
+#[derive(Debug)]
+struct CertificateMismatch;
+
+impl Display for CertificateMismatch {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("certificate mismatch")
+    }
+}
+
+impl StdError for CertificateMismatch {}
+
+/// A verifier which only accepts the pinned certificate.
+#[derive(Debug)]
+pub(super) struct PinnedVerifier {
+    /// The certificate the Envoy must present.
+    certificate: CertificateDer<'static>,
+    /// The provider used to verify the handshake signatures.
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedVerifier {
+    /// Create a verifier pinning the given certificate.
+    pub(super) fn new(certificate: CertificateDer<'static>) -> Self {
+        Self {
+            certificate,
+            provider: Arc::new(aws_lc_rs::default_provider()),
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    #[inline]
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> core::result::Result<ServerCertVerified, rustls::Error> {
+        if *end_entity == self.certificate {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                OtherError(Arc::new(CertificateMismatch)),
+            )))
+        }
+    }
+
+    #[inline]
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    #[inline]
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    #[inline]
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// A verifier which checks the certificate chain against a CA bundle.
+#[derive(Debug)]
+pub(super) struct CaVerifier {
+    /// The verifier checking the chain and hostname.
+    inner: Arc<WebPkiServerVerifier>,
+    /// The hostname to check the certificate against, if not the host
+    /// connected to.
+    server_name: Option<ServerName<'static>>,
+}
+
+impl CaVerifier {
+    /// Create a verifier trusting the certificates in the given bundle.
+    ///
+    /// # Errors
+    ///
+    /// Returns a configuration error if the bundle does not contain any valid
+    /// PEM-encoded certificate, or if the server name is not a valid hostname.
+    pub(super) fn new(bundle: &[u8], tls_server_name: Option<&str>) -> Result<Self> {
+        let mut roots = RootCertStore::empty();
+        for parsed in CertificateDer::pem_slice_iter(bundle) {
+            let certificate = parsed
+                .map_err(|e| EnphaseError::ConfigurationError(format!("Invalid CA bundle: {e}")))?;
+            roots
+                .add(certificate)
+                .map_err(|e| EnphaseError::ConfigurationError(format!("Invalid CA bundle: {e}")))?;
+        }
+        if roots.is_empty() {
+            return Err(EnphaseError::ConfigurationError(
+                "The CA bundle contains no certificates".to_owned(),
+            ));
+        }
+
+        let inner = WebPkiServerVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(aws_lc_rs::default_provider()),
+        )
+        .build()
+        .map_err(|e| EnphaseError::ConfigurationError(format!("Invalid CA bundle: {e}")))?;
+        let server_name = tls_server_name
+            .map(|name| {
+                ServerName::try_from(name.to_owned()).map_err(|e| {
+                    EnphaseError::ConfigurationError(format!("Invalid TLS server name: {e}"))
+                })
+            })
+            .transpose()?;
+
+        Ok(Self { inner, server_name })
+    }
+}
+
+impl ServerCertVerifier for CaVerifier {
+    #[inline]
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> core::result::Result<ServerCertVerified, rustls::Error> {
+        self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            self.server_name.as_ref().unwrap_or(server_name),
+            ocsp_response,
+            now,
+        )
+    }
+
+    #[inline]
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    #[inline]
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    #[inline]
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// A verifier which accepts any certificate, and records the certificate
+/// presented by the server.
+#[derive(Debug)]
+pub(super) struct CapturingVerifier {
+    /// The certificate presented by the server, once connected.
+    certificate: Mutex<Option<CertificateDer<'static>>>,
+    /// The provider used to verify the handshake signatures.
+    provider: Arc<CryptoProvider>,
+}
+
+impl CapturingVerifier {
+    /// Create a verifier which has not captured a certificate yet.
+    pub(super) fn new() -> Self {
+        Self {
+            certificate: Mutex::new(None),
+            provider: Arc::new(aws_lc_rs::default_provider()),
+        }
+    }
+
+    /// Take the captured certificate, if any.
+    pub(super) fn take(&self) -> Option<CertificateDer<'static>> {
+        self.certificate.lock().ok()?.take()
+    }
+}
+
+impl ServerCertVerifier for CapturingVerifier {
+    #[inline]
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> core::result::Result<ServerCertVerified, rustls::Error> {
+        if let Ok(mut certificate) = self.certificate.lock() {
+            *certificate = Some(end_entity.clone().into_owned());
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    #[inline]
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    #[inline]
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> core::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    #[inline]
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entrez, Envoy, EnvoySession, RetryPolicy, Scheme};
+    use core::{
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use rustls::{ServerConfig, pki_types::PrivateKeyDer};
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_rustls::TlsAcceptor;
+    use wiremock::{
+        Mock, MockServer, ResponseTemplate,
+        matchers::{method, path},
+    };
+
+    /// The certificate served by the test server.
+    const ENVOY_PEM: &[u8] = include_bytes!("../../../fixtures/tls/envoy.pem");
+    /// The private key of the certificate served by the test server.
+    const ENVOY_KEY: &[u8] = include_bytes!("../../../fixtures/tls/envoy.key");
+    /// A certificate which is not served by the test server.
+    const OTHER_PEM: &[u8] = include_bytes!("../../../fixtures/tls/other.pem");
+    /// A CA certificate, which issued [`SITE42_PEM`].
+    const CA_PEM: &[u8] = include_bytes!("../../../fixtures/tls/ca.pem");
+    /// A certificate for `envoy.site42.example`, issued by [`CA_PEM`].
+    const SITE42_PEM: &[u8] = include_bytes!("../../../fixtures/tls/site42.pem");
+    /// The private key of [`SITE42_PEM`].
+    const SITE42_KEY: &[u8] = include_bytes!("../../../fixtures/tls/site42.key");
+    /// A certificate naming the serial number `122133012345` in its subject.
+    const SERIAL_PEM: &[u8] = include_bytes!("../../../fixtures/tls/serial.pem");
+    /// The private key of [`SERIAL_PEM`].
+    const SERIAL_KEY: &[u8] = include_bytes!("../../../fixtures/tls/serial.key");
+
+    /// Start a TLS server presenting the given certificate, which answers
+    /// every request with an empty JSON array.
+    async fn start_tls_server(certificate_pem: &[u8], key_pem: &[u8]) -> SocketAddr {
+        start_counting_tls_server(certificate_pem, key_pem, Arc::default()).await
+    }
+
+    /// Start a TLS server as [`start_tls_server`], counting the connections
+    /// it accepts.
+    async fn start_counting_tls_server(
+        certificate_pem: &[u8],
+        key_pem: &[u8],
+        connections: Arc<AtomicUsize>,
+    ) -> SocketAddr {
+        let certificate =
+            parse_certificate(certificate_pem).expect("Fixture should be a certificate");
+        let key = PrivateKeyDer::from_pem_slice(key_pem).expect("Fixture should be a key");
+        let config = ServerConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("Default protocol versions should be supported")
+            .with_no_client_auth()
+            .with_single_cert(vec![certificate], key)
+            .expect("Fixture key should match the certificate");
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Should bind to a local port");
+        let address = listener
+            .local_addr()
+            .expect("Listener should have an address");
+
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve(acceptor.clone(), stream));
+            }
+        });
+
+        address
+    }
+
+    /// Answer a single request on the connection.
+    async fn serve(acceptor: TlsAcceptor, stream: TcpStream) -> io::Result<()> {
+        let mut tls_stream = acceptor.accept(stream).await?;
+        let mut request = [0_u8; 4096];
+        if tls_stream.read(&mut request).await? == 0 {
+            return Ok(());
+        }
+        tls_stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]")
+            .await?;
+        tls_stream.shutdown().await
+    }
+
+    #[test]
+    fn pem_round_trip() {
+        let certificate = parse_certificate(ENVOY_PEM).expect("Fixture should be a certificate");
+        let encoded = encode_certificate(&certificate);
+
+        assert_eq!(encoded.as_bytes(), ENVOY_PEM);
+    }
+
+    #[test]
+    fn invalid_pem() {
+        let result = Envoy::with_pinned_cert("envoy.local", b"not a certificate");
+
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Expected a configuration error, got {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn pinned_certificate_accepted() {
+        let address = start_tls_server(ENVOY_PEM, ENVOY_KEY).await;
+        let envoy = Envoy::builder(address.ip())
+            .port(address.port())
+            .pinned_certificate(ENVOY_PEM)
+            .token("valid_token_here")
+            .build()
+            .expect("Client should build");
+
+        let inverters = envoy
+            .inverters_production()
+            .await
+            .expect("Pinned certificate should be accepted");
+        assert!(inverters.is_empty(), "Test server serves no inverters");
+    }
+
+    #[tokio::test]
+    async fn pinned_certificate_mismatch() {
+        let address = start_tls_server(ENVOY_PEM, ENVOY_KEY).await;
+        let envoy = Envoy::builder(address.ip())
+            .port(address.port())
+            .pinned_certificate(OTHER_PEM)
+            .token("valid_token_here")
+            .build()
+            .expect("Client should build");
+
+        let error = envoy
+            .inverters_production()
+            .await
+            .expect_err("Another certificate should be rejected");
+        assert!(
+            matches!(error, EnphaseError::CertificateMismatch),
+            "Expected a certificate mismatch, got {error:?}"
+        );
+        assert!(
+            error.to_string().contains("certificate mismatch"),
+            "Message should name the mismatch: {error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn pinned_certificate_mismatch_not_retried() {
+        let connections = Arc::new(AtomicUsize::new(0));
+        let address =
+            start_counting_tls_server(ENVOY_PEM, ENVOY_KEY, Arc::clone(&connections)).await;
+        let envoy = Envoy::builder(address.ip())
+            .port(address.port())
+            .pinned_certificate(OTHER_PEM)
+            .token("valid_token_here")
+            .retry(RetryPolicy::new(3).backoff(Duration::ZERO, Duration::ZERO))
+            .build()
+            .expect("Client should build");
+
+        let error = envoy
+            .inverters_production()
+            .await
+            .expect_err("Another certificate should be rejected");
+        assert!(
+            matches!(error, EnphaseError::CertificateMismatch),
+            "Expected an unwrapped certificate mismatch, got {error:?}"
+        );
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn fetch_certificate() {
+        let address = start_tls_server(ENVOY_PEM, ENVOY_KEY).await;
+        let envoy = Envoy::builder(address.ip())
+            .port(address.port())
+            .build()
+            .expect("Client should build");
+
+        let pem = envoy
+            .fetch_certificate()
+            .await
+            .expect("Certificate should be fetched");
+        assert_eq!(pem.as_bytes(), ENVOY_PEM);
+
+        let pinned = Envoy::builder(address.ip())
+            .port(address.port())
+            .pinned_certificate(&pem)
+            .token("valid_token_here")
+            .build()
+            .expect("Fetched certificate should be valid");
+        pinned
+            .inverters_production()
+            .await
+            .expect("Fetched certificate should be accepted");
+    }
+
+    #[rstest]
+    #[case::trusted(CA_PEM, Some("envoy.site42.example"), None)]
+    #[case::untrusted(OTHER_PEM, Some("envoy.site42.example"), Some("not trusted"))]
+    #[case::wrong_server_name(CA_PEM, Some("envoy.site43.example"), Some("hostname mismatch"))]
+    #[case::ip_address(CA_PEM, None, Some("hostname mismatch"))]
+    #[tokio::test]
+    async fn ca_bundle(
+        #[case] bundle: &[u8],
+        #[case] server_name: Option<&str>,
+        #[case] failure: Option<&str>,
+    ) {
+        let address = start_tls_server(SITE42_PEM, SITE42_KEY).await;
+        let mut builder = Envoy::builder(address.ip())
+            .port(address.port())
+            .ca_bundle(bundle)
+            .token("valid_token_here");
+        if let Some(name) = server_name {
+            builder = builder.tls_server_name(name);
+        }
+        let envoy = builder.build().expect("Client should build");
+
+        let result = envoy.inverters_production().await;
+        match (result, failure) {
+            (Ok(_), None) => {}
+            (
+                Err(
+                    error @ (EnphaseError::UntrustedCertificate(_)
+                    | EnphaseError::HostnameMismatch(_)),
+                ),
+                Some(message),
+            ) => assert!(
+                error.to_string().contains(message),
+                "Expected {message:?}, got {error}"
+            ),
+            (unexpected, _) => panic!("Unexpected result {unexpected:?}, expected {failure:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn scheme_upgrade_followed_once() {
+        let address = start_tls_server(ENVOY_PEM, ENVOY_KEY).await;
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/v1/production/inverters"))
+            .respond_with(ResponseTemplate::new(301).insert_header(
+                "Location",
+                format!("https://{address}/api/v1/production/inverters"),
+            ))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let mock_address = mock_server.address();
+        let envoy = Envoy::builder(mock_address.ip())
+            .scheme(Scheme::Http)
+            .port(mock_address.port())
+            .allow_scheme_upgrade(true)
+            .token("valid_token_here")
+            .build()
+            .expect("Client should build");
+
+        let inverters = envoy
+            .inverters_production()
+            .await
+            .expect("Redirect to HTTPS should be followed");
+        assert!(inverters.is_empty(), "Test server serves no inverters");
+    }
+
+    #[rstest]
+    #[case::serial_number(SERIAL_PEM, &["122133012345"])]
+    #[case::no_serial_number(ENVOY_PEM, &[])]
+    #[case::dns_name(SITE42_PEM, &[])]
+    fn serial_numbers(#[case] pem: &[u8], #[case] expected: &[&str]) {
+        let certificate = parse_certificate(pem).expect("Fixture should be a certificate");
+        let serial_numbers = certificate_serial_numbers(&certificate)
+            .expect("Fixture should be parsed")
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+
+        assert_eq!(serial_numbers, expected);
+    }
+
+    #[rstest]
+    #[case::matching(SERIAL_PEM, SERIAL_KEY, "122133012345", Ok(()))]
+    #[case::formatted(SERIAL_PEM, SERIAL_KEY, "SN: 1221 3301 2345", Ok(()))]
+    #[case::other_serial_number(SERIAL_PEM, SERIAL_KEY, "482243012345", Err(Some("122133012345")))]
+    #[case::no_serial_number(ENVOY_PEM, ENVOY_KEY, "122133012345", Err(None))]
+    #[tokio::test]
+    async fn verify_identity(
+        #[case] certificate_pem: &[u8],
+        #[case] key_pem: &[u8],
+        #[case] expected: &str,
+        #[case] outcome: core::result::Result<(), Option<&str>>,
+    ) {
+        let address = start_tls_server(certificate_pem, key_pem).await;
+        let envoy = Envoy::builder(address.ip())
+            .port(address.port())
+            .build()
+            .expect("Client should build");
+        let serial_number = SerialNumber::parse(expected).expect("Serial number should parse");
+
+        let result = envoy.verify_identity(&serial_number).await;
+        match (result, outcome) {
+            (Ok(()), Ok(())) => {}
+            (
+                Err(EnphaseError::IdentityMismatch {
+                    expected: reported,
+                    presented,
+                }),
+                Err(certificate),
+            ) => {
+                assert_eq!(reported, serial_number.as_str());
+                assert_eq!(presented.as_deref(), certificate);
+            }
+            (unexpected, _) => panic!("Unexpected result {unexpected:?}, expected {outcome:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn session_identity_check() {
+        let address = start_tls_server(SERIAL_PEM, SERIAL_KEY).await;
+        let envoy = Envoy::builder(address.ip())
+            .port(address.port())
+            .build()
+            .expect("Client should build");
+        let token = "eyJhbGciOiJFUzI1NiIsInR5cCI6IkpXVCJ9.eyJhdWQiOiI0ODIyNDMwMTIzNDUiLCJleHAiOjQxMDI0NDQ4MDB9.signature";
+        let session = EnvoySession::new(Entrez::default(), envoy, "Site", "482243012345", true)
+            .with_token(token)
+            .expect("Token should be well formed")
+            .with_identity_check(true);
+
+        let result = session
+            .call(async |client| client.inverters_production().await)
+            .await;
+        assert!(
+            matches!(result, Err(EnphaseError::IdentityMismatch { .. })),
+            "Expected an identity mismatch before authenticating, got {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn fetch_certificate_over_http() {
+        let envoy = Envoy::builder("envoy.local")
+            .scheme(Scheme::Http)
+            .build()
+            .expect("Client should build");
+
+        let result = envoy.fetch_certificate().await;
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Expected a configuration error, got {result:?}"
+        );
+    }
+}
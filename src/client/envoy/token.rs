@@ -0,0 +1,89 @@
+//! # Externally obtained tokens
+//!
+//! Tokens are often obtained outside this crate (e.g., by Home Assistant, or
+//! through the flow of the mobile app), in which case only the Envoy half of
+//! the authentication is needed. Such a token can be set with
+//! [`EnvoyBuilder::token`] and used without calling [`Envoy::authenticate`]:
+//!
+//! - With [`EnvoyBuilder::verify_token`], the token is checked against the
+//!   Envoy (as by [`Envoy::authenticate`]) before the first request which
+//!   needs it, which also establishes the session cookie. An expired token is
+//!   rejected without contacting the Envoy.
+//! - With [`EnvoyBuilder::on_token_expiring`], a hook is called once the token
+//!   is about to expire, so that the external system can be asked for a new
+//!   one (which is then passed to [`Envoy::authenticate`]).
+//!
+//! [`Envoy`]: super::Envoy
+//! [`Envoy::authenticate`]: super::Envoy::authenticate
+//! [`EnvoyBuilder::token`]: super::EnvoyBuilder::token
+//! [`EnvoyBuilder::verify_token`]: super::EnvoyBuilder::verify_token
+//! [`EnvoyBuilder::on_token_expiring`]: super::EnvoyBuilder::on_token_expiring
+
+use alloc::sync::Arc;
+use core::{fmt, time::Duration};
+use std::sync::{Mutex, PoisonError};
+
+use tracing::debug;
+
+use crate::models::EnvoyToken;
+
+/// The hook called when the token is about to expire.
+type Callback = dyn Fn(&EnvoyToken) + Send + Sync;
+
+/// A hook called once per token, when it is about to expire.
+#[derive(Clone)]
+pub(super) struct ExpiryHook {
+    /// How long before expiry the hook is called.
+    margin: Duration,
+    /// The hook.
+    callback: Arc<Callback>,
+    /// The last token the hook was called for, shared between clones.
+    notified: Arc<Mutex<Option<String>>>,
+}
+
+impl fmt::Debug for ExpiryHook {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExpiryHook")
+            .field("margin", &self.margin)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ExpiryHook {
+    /// Create a hook called `margin` before the token expires.
+    pub(super) fn new(
+        margin: Duration,
+        callback: impl Fn(&EnvoyToken) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            margin,
+            callback: Arc::new(callback),
+            notified: Arc::default(),
+        }
+    }
+
+    /// Call the hook if the token is about to expire (or has expired), unless
+    /// it was already called for that token.
+    ///
+    /// Tokens which cannot be decoded, or have no expiry, never call the
+    /// hook.
+    pub(super) fn check(&self, token: &str) {
+        let Ok(claims) = EnvoyToken::parse(token) else {
+            return;
+        };
+        if !claims.expires_within(self.margin) {
+            return;
+        }
+
+        {
+            let mut notified = self.notified.lock().unwrap_or_else(PoisonError::into_inner);
+            if notified.as_deref() == Some(token) {
+                return;
+            }
+            *notified = Some(token.to_owned());
+        }
+        debug!(expires_at = ?claims.expires_at(), "Token about to expire");
+        (self.callback)(&claims);
+    }
+}
@@ -0,0 +1,715 @@
+//! # Envoy Poller
+//!
+//! This module provides a poller which reads the Envoy at a fixed interval and
+//! reports only what changed.
+//!
+//! Loggers typically read the same endpoints over and over, and most readings
+//! are identical (or nearly so) to the previous ones. The poller keeps the last
+//! reported reading of each target, and emits a [`PollEvent`] only when a target
+//! changes: power states whenever they flip, and power readings when a value
+//! moves by more than a configurable threshold from the last reported value.
+//! Failed reads are reported as [`PollEvent::Error`] and polling continues.
+//!
+//! The poller runs no background task: every request is made from within
+//! [`EnvoyPoller::next_event`], so nothing outlives the poller once it (or the
+//! future of `next_event`) is dropped.
+
+use alloc::{collections::VecDeque, sync::Arc};
+use core::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use tokio::{sync::Notify, time::Instant};
+use tracing::{debug, instrument};
+
+use crate::{
+    client::envoy::Envoy,
+    error::EnphaseError,
+    models::{
+        PowerState,
+        meters::MeterReading,
+        production::{Measurement, ProductionResponse},
+    },
+};
+
+/// Something the poller reads.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PollTarget {
+    /// The production data (see [`Envoy::production`]).
+    Production,
+    /// The meter readings (see [`Envoy::meter_readings`]).
+    MeterReadings,
+    /// The power state of a device (see [`Envoy::get_power_state`]).
+    PowerState(String),
+}
+
+/// A change reported by the poller.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PollEvent {
+    /// The production data changed.
+    Production(ProductionResponse),
+    /// The meter readings changed.
+    MeterReadings(Vec<MeterReading>),
+    /// The power state of a device changed.
+    PowerState {
+        /// The serial number of the device.
+        serial_number: String,
+        /// The power state.
+        state: PowerState,
+    },
+    /// A target could not be read. Polling continues.
+    Error {
+        /// The target which could not be read.
+        target: PollTarget,
+        /// The error.
+        error: EnphaseError,
+    },
+}
+
+/// A handle to stop an [`EnvoyPoller`], e.g. from another task.
+///
+/// # Drop
+///
+/// The handle only shares the stop signal: dropping it does not stop the
+/// poller, and stopping a poller which has been dropped does nothing.
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle {
+    /// The signal shared with the poller.
+    signal: Arc<StopSignal>,
+}
+
+/// The stop signal shared between a poller and its handles.
+#[derive(Debug, Default)]
+struct StopSignal {
+    /// Whether the poller has been stopped.
+    stopped: AtomicBool,
+    /// Wakes the poller while it waits for the next poll.
+    notify: Notify,
+}
+
+impl StopHandle {
+    /// Stop the poller.
+    ///
+    /// A poller waiting for its next poll stops immediately; a poller reading
+    /// the Envoy stops once the read completes. Either way,
+    /// [`EnvoyPoller::next_event`] then returns `None`, and pending events
+    /// are discarded.
+    #[inline]
+    pub fn stop(&self) {
+        self.signal.stopped.store(true, Ordering::Release);
+        self.signal.notify.notify_one();
+    }
+
+    /// Whether the poller has been stopped.
+    #[inline]
+    #[must_use]
+    pub fn is_stopped(&self) -> bool {
+        self.signal.stopped.load(Ordering::Acquire)
+    }
+}
+
+/// The last reported reading of a target.
+#[derive(Debug, Clone, PartialEq)]
+enum Snapshot {
+    /// The power values of the readings, in watts.
+    Power(Vec<f64>),
+    /// A power state.
+    State(PowerState),
+}
+
+impl Snapshot {
+    /// Whether this reading differs from the last reported one by more than
+    /// the threshold.
+    fn changed_from(&self, last: Option<&Self>, threshold: f64) -> bool {
+        match (self, last) {
+            (Self::Power(current), Some(Self::Power(previous))) => {
+                current.len() != previous.len()
+                    || current
+                        .iter()
+                        .zip(previous)
+                        .any(|(&now, &before)| exceeds(now, before, threshold))
+            }
+            (Self::State(current), Some(&Self::State(previous))) => *current != previous,
+            (Self::Power(_) | Self::State(_), _) => true,
+        }
+    }
+}
+
+/// Whether two power values differ by more than the threshold.
+#[expect(
+    clippy::float_arithmetic,
+    reason = "Power readings are floating point values"
+)]
+fn exceeds(now: f64, before: f64, threshold: f64) -> bool {
+    (now - before).abs() > threshold
+}
+
+/// Polls an Envoy at a fixed interval, reporting changes.
+///
+/// Targets are registered with [`EnvoyPoller::production`],
+/// [`EnvoyPoller::meter_readings`] and [`EnvoyPoller::power_state`]. Each
+/// call to [`EnvoyPoller::next_event`] returns the next change, polling the
+/// targets (in the order they were registered) as needed. The first
+/// successful read of each target is always reported.
+///
+/// The Envoy client must already be authenticated.
+///
+/// # Drop
+///
+/// [`EnvoyPoller::next_event`] is cancel safe: if its future is dropped
+/// (e.g., by `tokio::select!` or a timeout), the request in flight is aborted
+/// and its connection closed, while the changes already read stay queued for
+/// the next call. Dropping the poller releases its clone of the Envoy client.
+///
+/// # Example
+///
+/// ```no_run
+/// use core::time::Duration;
+///
+/// use enphase_api::{Envoy, EnvoyPoller, PollEvent};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let envoy = Envoy::new("envoy.local");
+/// envoy.authenticate("token").await?;
+///
+/// let mut poller = EnvoyPoller::new(envoy, Duration::from_secs(10))
+///     .production()
+///     .power_state("603980032")
+///     .threshold(50.0);
+/// let stop = poller.stop_handle();
+/// tokio::spawn(async move {
+///     tokio::time::sleep(Duration::from_hours(1)).await;
+///     stop.stop();
+/// });
+///
+/// while let Some(event) = poller.next_event().await {
+///     match event {
+///         PollEvent::Error { target, error } => eprintln!("Failed to read {target:?}: {error}"),
+///         event => println!("{event:?}"),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "EnvoyPoller reads better than client::poller::Poller"
+)]
+pub struct EnvoyPoller {
+    /// Client used to access the Envoy.
+    envoy: Envoy,
+    /// The time between two polls.
+    interval: Duration,
+    /// The smallest change in power, in watts, which is reported.
+    threshold: f64,
+    /// The targets, along with their last reported reading.
+    targets: Vec<(PollTarget, Option<Snapshot>)>,
+    /// The events of the last poll which have not been returned yet.
+    pending: VecDeque<PollEvent>,
+    /// When the next poll is due, or `None` if no poll has been made.
+    next_poll: Option<Instant>,
+    /// The stop signal.
+    stop: StopHandle,
+}
+
+impl EnvoyPoller {
+    /// Create a poller for the given Envoy.
+    ///
+    /// The poller has no targets; no request is made until the first call to
+    /// [`EnvoyPoller::next_event`].
+    ///
+    /// # Arguments
+    ///
+    /// * `envoy` - The authenticated Envoy client to poll
+    /// * `interval` - The time between two polls
+    ///
+    /// # Returns
+    ///
+    /// Returns a new [`EnvoyPoller`].
+    #[inline]
+    #[must_use]
+    pub fn new(envoy: Envoy, interval: Duration) -> Self {
+        Self {
+            envoy,
+            interval,
+            threshold: 0.0,
+            targets: Vec::new(),
+            pending: VecDeque::new(),
+            next_poll: None,
+            stop: StopHandle::default(),
+        }
+    }
+
+    /// Poll the production data.
+    #[inline]
+    #[must_use]
+    pub fn production(self) -> Self {
+        self.target(PollTarget::Production)
+    }
+
+    /// Poll the meter readings.
+    #[inline]
+    #[must_use]
+    pub fn meter_readings(self) -> Self {
+        self.target(PollTarget::MeterReadings)
+    }
+
+    /// Poll the power state of a device.
+    ///
+    /// # Arguments
+    ///
+    /// * `serial` - The serial number of the device
+    #[inline]
+    #[must_use]
+    pub fn power_state(self, serial: impl Into<String>) -> Self {
+        self.target(PollTarget::PowerState(serial.into()))
+    }
+
+    /// Set the smallest change in power which is reported.
+    ///
+    /// Power readings are compared with the last reported reading, so that
+    /// slow drifts are reported once they add up to the threshold. By
+    /// default, any change is reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `watts` - The threshold, in watts
+    #[inline]
+    #[must_use]
+    pub fn threshold(self, watts: f64) -> Self {
+        Self {
+            threshold: watts.abs(),
+            ..self
+        }
+    }
+
+    /// Get a handle to stop the poller.
+    #[inline]
+    #[must_use]
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Get the next change.
+    ///
+    /// This waits for the next poll when the last one reported nothing new,
+    /// and so may take several intervals to return.
+    ///
+    /// # Returns
+    ///
+    /// Returns the next event, or `None` once the poller has been stopped (or
+    /// if it has no targets).
+    #[inline]
+    #[instrument(skip(self), level = "debug")]
+    pub async fn next_event(&mut self) -> Option<PollEvent> {
+        loop {
+            if self.stop.is_stopped() || self.targets.is_empty() {
+                return None;
+            }
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+
+            if let Some(due) = self.next_poll {
+                // Waiting for the stop signal doubles as an interruptible sleep.
+                if tokio::time::timeout_at(due, self.stop.signal.notify.notified())
+                    .await
+                    .is_ok()
+                {
+                    continue;
+                }
+            }
+            let now = Instant::now();
+            self.next_poll = Some(now.checked_add(self.interval).unwrap_or(now));
+            self.poll().await;
+        }
+    }
+
+    /// Add a target, unless it is already registered.
+    fn target(mut self, target: PollTarget) -> Self {
+        if !self.targets.iter().any(|(known, _)| *known == target) {
+            self.targets.push((target, None));
+        }
+        self
+    }
+
+    /// Read every target once, queueing the changes.
+    async fn poll(&mut self) {
+        debug!("Polling {} targets", self.targets.len());
+
+        let targets: Vec<PollTarget> = self
+            .targets
+            .iter()
+            .map(|(target, _)| target.clone())
+            .collect();
+        for (index, target) in targets.into_iter().enumerate() {
+            let (snapshot, event) = match self.read(&target).await {
+                Ok(reading) => reading,
+                Err(error) => {
+                    debug!(?target, %error, "Failed to poll");
+                    self.pending.push_back(PollEvent::Error { target, error });
+                    continue;
+                }
+            };
+            if let Some(&mut (_, ref mut last)) = self.targets.get_mut(index)
+                && snapshot.changed_from(last.as_ref(), self.threshold)
+            {
+                *last = Some(snapshot);
+                self.pending.push_back(event);
+            }
+        }
+    }
+
+    /// Read a target.
+    async fn read(&self, target: &PollTarget) -> Result<(Snapshot, PollEvent), EnphaseError> {
+        match *target {
+            PollTarget::Production => {
+                let production = self.envoy.production().await?;
+                let power = production
+                    .production
+                    .iter()
+                    .chain(&production.consumption)
+                    .chain(&production.storage)
+                    .filter_map(|measurement| match *measurement {
+                        Measurement::Inverters(ref inverters) => Some(inverters.w_now),
+                        Measurement::Eim(ref eim) => Some(eim.w_now),
+                        Measurement::Acb(ref acb) => Some(acb.w_now),
+                        Measurement::Unknown => None,
+                    })
+                    .collect();
+                Ok((Snapshot::Power(power), PollEvent::Production(production)))
+            }
+            PollTarget::MeterReadings => {
+                let readings = self.envoy.meter_readings().await?;
+                let power = readings
+                    .iter()
+                    .map(|reading| reading.readings.active_power)
+                    .collect();
+                Ok((Snapshot::Power(power), PollEvent::MeterReadings(readings)))
+            }
+            PollTarget::PowerState(ref serial_number) => {
+                let state = self.envoy.get_power_state(serial_number).await?;
+                Ok((
+                    Snapshot::State(state),
+                    PollEvent::PowerState {
+                        serial_number: serial_number.clone(),
+                        state,
+                    },
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// The interval used by the tests.
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    /// How long the tests wait for an event which should not happen.
+    const QUIET: Duration = Duration::from_millis(100);
+
+    /// A production response with the given inverter power.
+    fn production(watts: u32) -> String {
+        format!(
+            r#"{{"production": [{{"type": "inverters", "activeCount": 1, "readingTime": 0, "wNow": {watts}, "whLifetime": 0}}]}}"#
+        )
+    }
+
+    /// Mount a production mock answering with the given power for the given
+    /// number of requests, in priority order.
+    async fn mount_production(mock_server: &MockServer, watts: &[u32]) {
+        for (priority, &value) in (1_u8..).zip(watts) {
+            let mock = Mock::given(method("GET"))
+                .and(path("/production.json"))
+                .respond_with(ResponseTemplate::new(200).set_body_string(production(value)))
+                .with_priority(priority);
+            if priority < u8::try_from(watts.len()).expect("Few readings") {
+                mock.up_to_n_times(1).mount(mock_server).await;
+            } else {
+                mock.mount(mock_server).await;
+            }
+        }
+    }
+
+    /// Check that an event reports the production with the given power.
+    fn assert_production(event: Option<PollEvent>, watts: u32) {
+        let Some(PollEvent::Production(response)) = event else {
+            panic!("Expected a production event, got {event:?}");
+        };
+        let expected: ProductionResponse =
+            serde_json::from_str(&production(watts)).expect("Should deserialize");
+        assert_eq!(response, expected);
+    }
+
+    #[tokio::test]
+    async fn reports_changes_beyond_threshold() {
+        let mock_server = MockServer::start().await;
+        mount_production(&mock_server, &[200, 205, 240, 240, 300]).await;
+
+        let mut poller = EnvoyPoller::new(Envoy::for_mock_server(&mock_server), INTERVAL)
+            .production()
+            .threshold(30.0);
+
+        assert_production(poller.next_event().await, 200);
+        // 205 W is within the threshold of 200 W, but 240 W is not.
+        assert_production(poller.next_event().await, 240);
+        assert_production(poller.next_event().await, 300);
+        assert!(
+            tokio::time::timeout(QUIET, poller.next_event())
+                .await
+                .is_err(),
+            "Unchanged readings should not be reported"
+        );
+    }
+
+    #[tokio::test]
+    async fn drop_mid_request_releases_everything() {
+        use tokio::{io::AsyncReadExt as _, net::TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Should bind to a local port");
+        let port = listener
+            .local_addr()
+            .expect("Listener should have an address")
+            .port();
+        let envoy = Envoy::builder("127.0.0.1")
+            .scheme(crate::Scheme::Http)
+            .port(port)
+            .build()
+            .expect("Should build");
+
+        let mut poller = EnvoyPoller::new(envoy.clone(), INTERVAL).production();
+        let stop = poller.stop_handle();
+        let request = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("Should accept");
+            let mut buffer = [0_u8; 4096];
+            while stream.read(&mut buffer).await.expect("Should read") > 0 {}
+        });
+        assert!(
+            tokio::time::timeout(QUIET, poller.next_event())
+                .await
+                .is_err(),
+            "The unanswered request should still be in flight"
+        );
+        drop(poller);
+
+        // The client is still alive, so the connection is closed because the
+        // request was aborted, not because its pool was dropped.
+        tokio::time::timeout(Duration::from_secs(5), request)
+            .await
+            .expect("The connection should be closed")
+            .expect("The server should not panic");
+        assert_eq!(
+            Arc::strong_count(&stop.signal),
+            1,
+            "The stop signal should only be held by the handle"
+        );
+        stop.stop();
+        drop(envoy);
+    }
+
+    #[tokio::test]
+    async fn reports_power_state_changes() {
+        let mock_server = MockServer::start().await;
+        for (priority, forced_off) in [(1, false), (2, false), (3, true)] {
+            let mock = Mock::given(method("GET"))
+                .and(path("/ivp/mod/603980032/mode/power"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_string(format!(r#"{{"powerForcedOff": {forced_off}}}"#)),
+                )
+                .with_priority(priority);
+            if priority < 3 {
+                mock.up_to_n_times(1).mount(&mock_server).await;
+            } else {
+                mock.mount(&mock_server).await;
+            }
+        }
+
+        let mut poller = EnvoyPoller::new(Envoy::for_mock_server(&mock_server), INTERVAL)
+            .power_state("603980032");
+
+        for expected in [PowerState::On, PowerState::Off] {
+            let event = poller.next_event().await;
+            assert!(
+                matches!(event, Some(PollEvent::PowerState { ref serial_number, state }) if serial_number == "603980032" && state == expected),
+                "Expected power state {expected}, got {event:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_are_reported_and_polling_continues() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/production.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("Not JSON"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/production.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(production(200)))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/ivp/mod/603980032/mode/power"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"powerForcedOff": true}"#))
+            .mount(&mock_server)
+            .await;
+
+        let mut poller = EnvoyPoller::new(Envoy::for_mock_server(&mock_server), INTERVAL)
+            .production()
+            .power_state("603980032");
+
+        let error = poller.next_event().await;
+        assert!(
+            matches!(
+                error,
+                Some(PollEvent::Error {
+                    target: PollTarget::Production,
+                    error: EnphaseError::JsonError(_)
+                })
+            ),
+            "Expected a production error, got {error:?}"
+        );
+        let state = poller.next_event().await;
+        assert!(
+            matches!(
+                state,
+                Some(PollEvent::PowerState {
+                    state: PowerState::Off,
+                    ..
+                })
+            ),
+            "Other targets should still be polled, got {state:?}"
+        );
+        assert_production(poller.next_event().await, 200);
+    }
+
+    #[tokio::test]
+    async fn meter_readings() {
+        let mock_server = MockServer::start().await;
+        let fixture: serde_json::Value = serde_json::from_str(
+            &std::fs::read_to_string("fixtures/envoy/meter-readings.json")
+                .expect("Fixture should exist"),
+        )
+        .expect("Fixture should be JSON");
+        Mock::given(method("GET"))
+            .and(path("/ivp/meters/readings"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(
+                    fixture
+                        .get("body")
+                        .and_then(serde_json::Value::as_str)
+                        .expect("body is not a string"),
+                ),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let mut poller =
+            EnvoyPoller::new(Envoy::for_mock_server(&mock_server), INTERVAL).meter_readings();
+
+        let event = poller.next_event().await;
+        assert!(
+            matches!(event, Some(PollEvent::MeterReadings(ref readings)) if !readings.is_empty()),
+            "Expected meter readings, got {event:?}"
+        );
+        assert!(
+            tokio::time::timeout(QUIET, poller.next_event())
+                .await
+                .is_err(),
+            "Unchanged readings should not be reported"
+        );
+    }
+
+    #[tokio::test]
+    async fn stop_while_waiting() {
+        let mock_server = MockServer::start().await;
+        mount_production(&mock_server, &[200]).await;
+
+        let mut poller = EnvoyPoller::new(
+            Envoy::for_mock_server(&mock_server),
+            Duration::from_hours(1),
+        )
+        .production();
+        let stop = poller.stop_handle();
+
+        assert!(
+            poller.next_event().await.is_some(),
+            "The first reading should be reported"
+        );
+        let waiting = tokio::spawn(async move { poller.next_event().await });
+        tokio::time::sleep(INTERVAL).await;
+        stop.stop();
+
+        let event = tokio::time::timeout(QUIET, waiting)
+            .await
+            .expect("The poller should stop promptly")
+            .expect("The task should not panic");
+        assert!(event.is_none(), "Expected no event, got {event:?}");
+        assert!(stop.is_stopped(), "The handle should report the stop");
+    }
+
+    #[tokio::test]
+    async fn stopped_before_polling() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let mut poller =
+            EnvoyPoller::new(Envoy::for_mock_server(&mock_server), INTERVAL).production();
+        poller.stop_handle().stop();
+
+        assert!(
+            poller.next_event().await.is_none(),
+            "A stopped poller should not poll"
+        );
+    }
+
+    #[tokio::test]
+    async fn no_targets() {
+        let mock_server = MockServer::start().await;
+        let mut poller = EnvoyPoller::new(Envoy::for_mock_server(&mock_server), INTERVAL);
+
+        assert!(
+            poller.next_event().await.is_none(),
+            "A poller without targets should not wait forever"
+        );
+    }
+
+    #[test]
+    fn targets_are_deduplicated() {
+        let poller = EnvoyPoller::new(Envoy::new("envoy.local"), INTERVAL)
+            .production()
+            .power_state("603980032")
+            .production()
+            .power_state("603980032");
+
+        let targets: Vec<&PollTarget> = poller.targets.iter().map(|(target, _)| target).collect();
+        assert_eq!(
+            targets,
+            vec![
+                &PollTarget::Production,
+                &PollTarget::PowerState("603980032".to_owned())
+            ]
+        );
+    }
+}
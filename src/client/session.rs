@@ -0,0 +1,664 @@
+//! # Envoy Session
+//!
+//! This module provides a session which keeps an Envoy client authenticated
+//! over long periods of time.
+//!
+//! Tokens generated by Entrez expire (after 12 hours for uncommissioned
+//! systems, and after a year for commissioned ones). The session owns both an
+//! [`Entrez`] and an [`Envoy`] client, and generates and authenticates a new
+//! token whenever the current one is about to expire or is rejected by the
+//! Envoy.
+
+use core::fmt;
+use core::time::Duration;
+use std::time::SystemTime;
+
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{debug, instrument};
+
+use crate::{
+    client::{DEFAULT_REFRESH_MARGIN, entrez::Entrez, envoy::Envoy, trace_token},
+    error::{EnphaseError, Result},
+    models::{EnvoyToken, SerialNumber, TokenSource},
+};
+
+/// A session keeping an Envoy client authenticated.
+///
+/// The session generates a token through Entrez on first use, and refreshes
+/// it proactively shortly before it expires (based on the `exp` claim of the
+/// token) as well as reactively whenever the Envoy rejects it.
+///
+/// The Entrez client must already be logged in.
+#[derive(Debug)]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "EnvoySession reads better than client::session::Session"
+)]
+pub struct EnvoySession {
+    /// Client used to generate tokens.
+    entrez: Entrez,
+    /// Client used to access the Envoy.
+    envoy: Envoy,
+    /// Name of the site the Envoy belongs to.
+    site_name: String,
+    /// Serial number of the Envoy.
+    serial_number: String,
+    /// Whether the system is commissioned.
+    commissioned: bool,
+    /// How long before expiry the token is refreshed.
+    refresh_margin: Duration,
+    /// Whether the identity of the Envoy is verified before authenticating.
+    identity_check: bool,
+    /// Set once the identity of the Envoy has been verified.
+    identity_verified: OnceCell<()>,
+    /// The current token.
+    token: Mutex<Option<SessionToken>>,
+}
+
+/// A token along with its decoded claims.
+struct SessionToken {
+    /// The JWT token.
+    value: String,
+    /// The decoded claims of the token.
+    claims: EnvoyToken,
+    /// Whether the Envoy has accepted the token.
+    authenticated: bool,
+    /// Where the token was obtained from.
+    source: TokenSource,
+}
+
+impl fmt::Debug for SessionToken {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The token is a credential, so only its metadata is shown.
+        f.debug_struct("SessionToken")
+            .field("claims", &self.claims)
+            .field("authenticated", &self.authenticated)
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SessionToken {
+    /// Wrap a token obtained from the given source, decoding its claims.
+    fn new(value: String, source: TokenSource) -> Result<Self> {
+        let claims = EnvoyToken::parse(&value)?;
+        Ok(Self {
+            value,
+            claims,
+            authenticated: false,
+            source,
+        })
+    }
+}
+
+impl EnvoySession {
+    /// Create a new session for the given Envoy.
+    ///
+    /// No request is made until the session is first used.
+    ///
+    /// # Arguments
+    ///
+    /// * `entrez` - A logged in Entrez client, used to generate tokens
+    /// * `envoy` - The Envoy client to keep authenticated
+    /// * `site_name` - The name of the site the Envoy belongs to
+    /// * `serial_number` - The serial number of the Envoy
+    /// * `commissioned` - Whether the system is commissioned
+    ///
+    /// # Returns
+    ///
+    /// Returns a new [`EnvoySession`].
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use enphase_api::{Entrez, Envoy, EnvoySession};
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let entrez = Entrez::default();
+    /// entrez.login_with_env().await?;
+    ///
+    /// let envoy = Envoy::new("envoy.local");
+    /// let session = EnvoySession::new(entrez, envoy, "My Site", "121212121212", true);
+    /// let production = session.call(async |envoy| envoy.production().await).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    pub fn new(
+        entrez: Entrez,
+        envoy: Envoy,
+        site_name: impl Into<String>,
+        serial_number: impl Into<String>,
+        commissioned: bool,
+    ) -> Self {
+        Self {
+            entrez,
+            envoy,
+            site_name: site_name.into(),
+            serial_number: serial_number.into(),
+            commissioned,
+            refresh_margin: DEFAULT_REFRESH_MARGIN,
+            identity_check: false,
+            identity_verified: OnceCell::new(),
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Start the session with a previously obtained token.
+    ///
+    /// This allows a token persisted across restarts to be reused instead of
+    /// generating a new one. The token is authenticated on first use, and is
+    /// replaced if it has expired or is rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The JWT token to start with
+    ///
+    /// # Returns
+    ///
+    /// Returns the session using the given token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the token is not a well-formed JWT.
+    #[inline]
+    pub fn with_token(self, token: impl Into<String>) -> Result<Self> {
+        let session_token = SessionToken::new(token.into(), TokenSource::Local)?;
+        Ok(Self {
+            token: Mutex::new(Some(session_token)),
+            ..self
+        })
+    }
+
+    /// Set how long before expiry the token is refreshed.
+    ///
+    /// Defaults to five minutes.
+    ///
+    /// # Arguments
+    ///
+    /// * `margin` - How long before expiry the token is refreshed
+    ///
+    /// # Returns
+    ///
+    /// Returns the session using the given margin.
+    #[inline]
+    #[must_use]
+    pub fn with_refresh_margin(self, margin: Duration) -> Self {
+        Self {
+            refresh_margin: margin,
+            ..self
+        }
+    }
+
+    /// Set whether the identity of the Envoy is verified before a token is
+    /// first sent to it.
+    ///
+    /// When enabled, the serial number named by the certificate of the Envoy
+    /// is checked against the serial number of the session (see
+    /// [`Envoy::verify_identity`]) before the first authentication, and the
+    /// session fails with [`EnphaseError::IdentityMismatch`] if they differ.
+    /// The check is made once per session.
+    ///
+    /// # Arguments
+    ///
+    /// * `check` - Whether to verify the identity (disabled by default)
+    ///
+    /// # Returns
+    ///
+    /// Returns the session using the given setting.
+    #[inline]
+    #[must_use]
+    pub fn with_identity_check(self, check: bool) -> Self {
+        Self {
+            identity_check: check,
+            ..self
+        }
+    }
+
+    /// The Envoy client of the session.
+    ///
+    /// Requests made directly through this client are not retried when the
+    /// token is rejected; use [`EnvoySession::call`] for that.
+    #[inline]
+    pub fn envoy(&self) -> &Envoy {
+        &self.envoy
+    }
+
+    /// The current token, if one has been obtained.
+    ///
+    /// This can be persisted and passed to [`EnvoySession::with_token`] to
+    /// reuse the token after a restart.
+    #[inline]
+    pub async fn token(&self) -> Option<String> {
+        self.token
+            .lock()
+            .await
+            .as_ref()
+            .map(|token| token.value.clone())
+    }
+
+    /// When the current token expires, if a token has been obtained and it
+    /// has an expiry.
+    #[inline]
+    pub async fn expires_at(&self) -> Option<SystemTime> {
+        self.token
+            .lock()
+            .await
+            .as_ref()
+            .and_then(|token| token.claims.expires_at())
+    }
+
+    /// Where the current token was obtained from, if a token has been
+    /// obtained.
+    ///
+    /// Tokens passed to [`EnvoySession::with_token`] are
+    /// [`TokenSource::Local`], and tokens generated by the session are
+    /// [`TokenSource::Entrez`]. The expiry and serial number of the token can
+    /// be inspected by decoding it (see [`EnvoyToken::parse`]).
+    #[inline]
+    pub async fn token_source(&self) -> Option<TokenSource> {
+        self.token.lock().await.as_ref().map(|token| token.source)
+    }
+
+    /// Make a request to the Envoy within the session.
+    ///
+    /// A token is obtained first if there is none, or if the current one is
+    /// about to expire. If the request then fails with
+    /// [`EnphaseError::AuthenticationFailed`], a new token is generated and
+    /// the request is retried once.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request to make with the authenticated Envoy client
+    ///
+    /// # Returns
+    ///
+    /// Returns the result of the request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a token cannot be obtained, or if the request
+    /// fails (after a retry, in the case of an authentication failure).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use enphase_api::EnvoySession;
+    ///
+    /// # async fn example(session: &EnvoySession) -> Result<(), Box<dyn std::error::Error>> {
+    /// let is_on = session
+    ///     .call(async |envoy| envoy.get_power_state("603980032").await)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[inline]
+    #[instrument(skip(self, request), level = "debug")]
+    pub async fn call<T>(&self, request: impl AsyncFn(&Envoy) -> Result<T>) -> Result<T> {
+        let token = self.ensure_token().await?;
+
+        match request(&self.envoy).await {
+            Err(EnphaseError::AuthenticationFailed(reason)) => {
+                debug!("Token rejected ({reason}), refreshing");
+                self.invalidate(&token).await;
+                self.ensure_token().await?;
+                request(&self.envoy).await
+            }
+            result => result,
+        }
+    }
+
+    /// Generate and authenticate a new token, regardless of the current one.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the token cannot be generated or is rejected by
+    /// the Envoy.
+    #[inline]
+    #[instrument(skip(self), level = "debug")]
+    pub async fn refresh(&self) -> Result<()> {
+        let mut current = self.token.lock().await;
+        *current = Some(self.generate("requested").await?);
+        Ok(())
+    }
+
+    /// Ensure the Envoy is authenticated with a token which is not about to
+    /// expire, returning that token.
+    async fn ensure_token(&self) -> Result<String> {
+        let mut current = self.token.lock().await;
+
+        let reason = match *current {
+            None => "missing",
+            Some(ref token) if token.claims.expires_within(self.refresh_margin) => "expired",
+            Some(ref mut token) => {
+                if token.authenticated {
+                    return Ok(token.value.clone());
+                }
+                match self.authenticate(&token.value).await {
+                    Ok(()) => {
+                        token.authenticated = true;
+                        trace_token(token.source, &token.value, None);
+                        return Ok(token.value.clone());
+                    }
+                    Err(EnphaseError::AuthenticationFailed(reason)) => {
+                        debug!("Stored token rejected ({reason})");
+                        "rejected"
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        };
+
+        let token = self.generate(reason).await?;
+        let value = token.value.clone();
+        *current = Some(token);
+        Ok(value)
+    }
+
+    /// Discard the current token if it is the one which was rejected.
+    ///
+    /// Another request may have already replaced the rejected token, in which
+    /// case the replacement is kept.
+    async fn invalidate(&self, rejected: &str) {
+        let mut current = self.token.lock().await;
+        if current
+            .as_ref()
+            .is_some_and(|token| token.value == rejected)
+        {
+            *current = None;
+        }
+    }
+
+    /// Authenticate the Envoy with the given token, verifying its identity
+    /// first if enabled.
+    async fn authenticate(&self, token: &str) -> Result<()> {
+        self.check_identity().await?;
+        self.envoy.authenticate(token).await
+    }
+
+    /// Verify the identity of the Envoy, if enabled and not done yet.
+    async fn check_identity(&self) -> Result<()> {
+        if !self.identity_check {
+            return Ok(());
+        }
+        self.identity_verified
+            .get_or_try_init(|| async {
+                let expected = SerialNumber::parse(&self.serial_number)?;
+                self.envoy.verify_identity(&expected).await
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Generate a new token and authenticate the Envoy with it.
+    ///
+    /// The `reason` for replacing the current token (e.g., `expired`) is
+    /// traced along with the generation.
+    async fn generate(&self, reason: &str) -> Result<SessionToken> {
+        // Checked before a token is minted for a gateway which may be wrong.
+        self.check_identity().await?;
+        debug!(reason, "Generating a new token");
+        let value = self
+            .entrez
+            .generate_token(&self.site_name, &self.serial_number, self.commissioned)
+            .await?;
+
+        let mut token = SessionToken::new(value, TokenSource::Entrez)?;
+        self.authenticate(&token.value).await?;
+        token.authenticated = true;
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client::capture::Capture, models::PowerState};
+    use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+    use std::time::UNIX_EPOCH;
+    use wiremock::matchers::{header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// Build an unsigned token expiring after the given number of seconds.
+    fn token_expiring_in(name: &str, seconds: u64) -> String {
+        let exp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Clock should be after the epoch")
+            .as_secs()
+            .saturating_add(seconds);
+        format!(
+            "{}.{}.{name}",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256","typ":"JWT"}"#),
+            URL_SAFE_NO_PAD.encode(format!(r#"{{"aud":"482243012345","exp":{exp}}}"#))
+        )
+    }
+
+    /// Mount a token generation mock returning the given token.
+    async fn mount_generate_token(mock_server: &MockServer, token: &str, expected: u64) {
+        Mock::given(method("POST"))
+            .and(path("/entrez_tokens"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"<textarea name="accessToken" id="JWTToken" rows="10">{token}</textarea>"#
+            )))
+            .expect(expected)
+            .mount(mock_server)
+            .await;
+    }
+
+    /// Mount a JWT check mock accepting the given token.
+    async fn mount_check_jwt(mock_server: &MockServer, token: &str, expected: u64) {
+        Mock::given(method("GET"))
+            .and(path("/auth/check_jwt"))
+            .and(header("Authorization", format!("Bearer {token}")))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string("<!DOCTYPE html><h2>Valid token.</h2>\n"),
+            )
+            .expect(expected)
+            .mount(mock_server)
+            .await;
+    }
+
+    /// Mount a power state mock responding to the given token with the given
+    /// status.
+    async fn mount_get_power(mock_server: &MockServer, token: &str, status: u16) {
+        Mock::given(method("GET"))
+            .and(path("/ivp/mod/603980032/mode/power"))
+            .and(header("Authorization", format!("Bearer {token}")))
+            .respond_with(
+                ResponseTemplate::new(status).set_body_string(r#"{"powerForcedOff":false}"#),
+            )
+            .expect(1)
+            .mount(mock_server)
+            .await;
+    }
+
+    fn session(mock_server: &MockServer) -> EnvoySession {
+        EnvoySession::new(
+            Entrez::new(mock_server.uri()),
+            Envoy::for_mock_server(mock_server),
+            "My Site",
+            "482243012345",
+            true,
+        )
+    }
+
+    #[tokio::test]
+    async fn call_generates_token() {
+        let mock_server = MockServer::start().await;
+        let token = token_expiring_in("new", 3600);
+
+        mount_generate_token(&mock_server, &token, 1).await;
+        mount_check_jwt(&mock_server, &token, 1).await;
+        mount_get_power(&mock_server, &token, 200).await;
+
+        let session = session(&mock_server);
+        let state = session
+            .call(async |envoy| envoy.get_power_state("603980032").await)
+            .await
+            .expect("Should succeed");
+
+        assert_eq!(state, PowerState::On);
+        assert_eq!(session.token().await, Some(token));
+        assert!(
+            session.expires_at().await.is_some(),
+            "Expiry should be decoded from the token"
+        );
+    }
+
+    #[tokio::test]
+    async fn call_refreshes_after_rejection() {
+        let mock_server = MockServer::start().await;
+        let old = token_expiring_in("old", 3600);
+        let new = token_expiring_in("new", 3600);
+
+        mount_check_jwt(&mock_server, &old, 1).await;
+        mount_get_power(&mock_server, &old, 401).await;
+        mount_generate_token(&mock_server, &new, 1).await;
+        mount_check_jwt(&mock_server, &new, 1).await;
+        mount_get_power(&mock_server, &new, 200).await;
+
+        let session = session(&mock_server)
+            .with_token(&old)
+            .expect("Token should be valid");
+        let state = session
+            .call(async |envoy| envoy.get_power_state("603980032").await)
+            .await
+            .expect("Should succeed after refreshing the token");
+
+        assert_eq!(state, PowerState::On);
+        assert_eq!(session.token().await, Some(new));
+    }
+
+    #[tokio::test]
+    async fn call_refreshes_before_expiry() {
+        let mock_server = MockServer::start().await;
+        let old = token_expiring_in("old", 60);
+        let new = token_expiring_in("new", 3600);
+
+        mount_check_jwt(&mock_server, &old, 0).await;
+        mount_generate_token(&mock_server, &new, 1).await;
+        mount_check_jwt(&mock_server, &new, 1).await;
+        mount_get_power(&mock_server, &new, 200).await;
+
+        let session = session(&mock_server)
+            .with_token(&old)
+            .expect("Token should be valid");
+        session
+            .call(async |envoy| envoy.get_power_state("603980032").await)
+            .await
+            .expect("Should succeed with the refreshed token");
+
+        assert_eq!(session.token().await, Some(new));
+    }
+
+    #[rstest]
+    #[case::stored(3600, "local", None)]
+    #[case::expired(60, "entrez", Some("expired"))]
+    #[tokio::test]
+    async fn token_acquisition_traced(
+        #[case] validity: u64,
+        #[case] source: &str,
+        #[case] reason: Option<&str>,
+    ) {
+        let mock_server = MockServer::start().await;
+        let stored = token_expiring_in("stored", validity);
+        let new = token_expiring_in("new", 7200);
+        let used = if reason.is_some() { &new } else { &stored };
+
+        mount_generate_token(&mock_server, &new, u64::from(reason.is_some())).await;
+        mount_check_jwt(&mock_server, used, 1).await;
+        mount_get_power(&mock_server, used, 200).await;
+
+        let session = session(&mock_server)
+            .with_token(&stored)
+            .expect("Token should be valid");
+        let (capture, guard) = Capture::install();
+        let result = session
+            .call(async |envoy| envoy.get_power_state("603980032").await)
+            .await;
+        drop(guard);
+        result.expect("Should succeed");
+
+        let acquired = capture.events("Token acquired");
+        let [event] = acquired.as_slice() else {
+            panic!("Expected one token event, got {acquired:?}");
+        };
+        assert_eq!(event.get("source").map(String::as_str), Some(source));
+        assert_eq!(
+            event.get("serial").map(String::as_str),
+            Some(r#""482243012345""#)
+        );
+        assert_eq!(
+            event.get("login").map(String::as_str),
+            Some(if reason.is_some() { "true" } else { "false" })
+        );
+        assert!(
+            event.contains_key("remaining"),
+            "Remaining validity should be traced: {event:?}"
+        );
+        let generated = capture.events("Generating a new token");
+        assert_eq!(
+            generated
+                .iter()
+                .map(|fields| fields.get("reason").cloned())
+                .collect::<Vec<_>>(),
+            reason
+                .map(|expected| Some(format!("{expected:?}")))
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            session
+                .token_source()
+                .await
+                .map(|recorded| recorded.to_string()),
+            Some(source.to_owned())
+        );
+    }
+
+    #[tokio::test]
+    async fn call_reuses_authenticated_token() {
+        let mock_server = MockServer::start().await;
+        let token = token_expiring_in("token", 3600);
+
+        mount_generate_token(&mock_server, &token, 1).await;
+        mount_check_jwt(&mock_server, &token, 1).await;
+        Mock::given(method("GET"))
+            .and(path("/ivp/mod/603980032/mode/power"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"powerForcedOff":true}"#))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let session = session(&mock_server);
+        for _ in 0..2_u8 {
+            let state = session
+                .call(async |envoy| envoy.get_power_state("603980032").await)
+                .await
+                .expect("Should succeed");
+            assert_eq!(state, PowerState::Off);
+        }
+    }
+
+    #[test]
+    fn with_token_rejects_malformed_token() {
+        let result = EnvoySession::new(
+            Entrez::new("http://127.0.0.1:1"),
+            Envoy::new("127.0.0.1:1"),
+            "My Site",
+            "482243012345",
+            true,
+        )
+        .with_token("not-a-token");
+
+        assert!(
+            matches!(result, Err(EnphaseError::InvalidResponse(_))),
+            "Malformed token should be rejected, got {result:?}"
+        );
+    }
+}
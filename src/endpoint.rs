@@ -0,0 +1,482 @@
+//! # Endpoint paths
+//!
+//! This module defines the paths of the known Entrez and Envoy endpoints.
+//! Each endpoint is built through a dedicated constructor so that path segments
+//! derived from user input (such as device serial numbers) are validated and
+//! percent-encoded in a single place, rather than being interpolated ad hoc
+//! into URLs by each client method.
+//!
+//! Some endpoints shape their output based on the `Accept` header, so each
+//! endpoint also declares the representation it expects. JSON is the default;
+//! only endpoints which serve HTML pages or XML documents request those.
+//!
+//! Writes are retried only when repeating them is safe, so each endpoint also
+//! declares whether writes to it are idempotent (see [`Idempotency`]).
+//!
+//! Some firmware also expects the headers sent by the local web UI, so the
+//! headers the UI sends to each family of endpoints are kept here as data (see
+//! [`BrowserHeaders`]).
+
+use core::fmt::{self, Display, Write as _};
+
+use crate::error::{EnphaseError, Result};
+
+/// The `Accept` header value for JSON endpoints.
+pub(crate) const ACCEPT_JSON: &str = "application/json";
+
+/// The `Accept` header value for endpoints serving HTML pages.
+pub(crate) const ACCEPT_HTML: &str = "text/html";
+
+/// The `Accept` header value for endpoints serving XML documents.
+pub(crate) const ACCEPT_XML: &str = "application/xml";
+
+/// The `Accept` header value for endpoints serving any representation.
+pub(crate) const ACCEPT_ANY: &str = "*/*";
+
+/// The value of the `X-Requested-With` header sent by the local web UI.
+pub(crate) const XML_HTTP_REQUEST: &str = "XMLHttpRequest";
+
+/// The headers the local web UI sends to a family of endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) struct BrowserHeaders {
+    /// Whether the UI requests the endpoints from a script, sending
+    /// `X-Requested-With: XMLHttpRequest`.
+    xhr: bool,
+    /// The path of the UI page requesting the endpoints, sent as the
+    /// `Referer` relative to the base URL.
+    referer: Option<&'static str>,
+}
+
+impl BrowserHeaders {
+    /// Whether `X-Requested-With: XMLHttpRequest` is sent.
+    pub(crate) fn xhr(self) -> bool {
+        self.xhr
+    }
+
+    /// The path of the UI page sent as the `Referer`, if any.
+    pub(crate) fn referer(self) -> Option<&'static str> {
+        self.referer
+    }
+}
+
+/// The headers sent by the scripts of the local web UI.
+const UI_SCRIPT: BrowserHeaders = BrowserHeaders {
+    xhr: true,
+    referer: Some("/home"),
+};
+
+/// The headers sent by the local web UI, by path prefix of the endpoint
+/// family.
+///
+/// The first matching prefix applies. Endpoints the UI does not request from
+/// its scripts (such as `/info` and the Entrez endpoints) are not listed and
+/// get no extra headers.
+const BROWSER_HEADERS: &[(&str, BrowserHeaders)] = &[
+    ("/ivp/", UI_SCRIPT),
+    ("/admin/lib/", UI_SCRIPT),
+    ("/api/v1/", UI_SCRIPT),
+    ("/production.json", UI_SCRIPT),
+    ("/inventory.json", UI_SCRIPT),
+    ("/home.json", UI_SCRIPT),
+];
+
+/// Whether a write to an endpoint can safely be repeated.
+///
+/// Reads are always safe to repeat, whatever the endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Idempotency {
+    /// Repeating the write has the same effect as making it once, e.g. because
+    /// it sets a power mode or settings to a given value.
+    Idempotent,
+    /// Repeating the write may apply it twice, e.g. because it starts a job
+    /// (such as a device scan or provisioning) or submits credentials.
+    NonIdempotent,
+}
+
+/// A known API endpoint, relative to the base URL of the service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Endpoint {
+    /// The path of the endpoint, always starting with `/`.
+    path: String,
+    /// The value of the `Accept` header to send to the endpoint.
+    accept: &'static str,
+    /// Whether writes to the endpoint can safely be repeated.
+    idempotency: Idempotency,
+}
+
+/// The paths of the Envoy endpoints which do not need a token.
+const PUBLIC_PATHS: &[&str] = &["/auth/check_jwt", "/info", "/info.xml"];
+
+impl Endpoint {
+    /// The Entrez login form endpoint.
+    pub(crate) fn entrez_login() -> Self {
+        Self::fixed("/login")
+            .with_accept(ACCEPT_HTML)
+            .non_idempotent()
+    }
+
+    /// The Entrez token generation endpoint.
+    pub(crate) fn entrez_tokens() -> Self {
+        Self::fixed("/entrez_tokens")
+            .with_accept(ACCEPT_HTML)
+            .non_idempotent()
+    }
+
+    /// The Entrez site search endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`EnphaseError::ConfigurationError`] if the query is empty.
+    pub(crate) fn entrez_sites(query: impl Display) -> Result<Self> {
+        Ok(Self {
+            path: format!("/site/{}", segment(query)?),
+            accept: ACCEPT_JSON,
+            idempotency: Idempotency::Idempotent,
+        })
+    }
+
+    /// The Envoy JWT validation endpoint.
+    pub(crate) fn check_jwt() -> Self {
+        Self::fixed("/auth/check_jwt").with_accept(ACCEPT_HTML)
+    }
+
+    /// The Envoy device information endpoint.
+    pub(crate) fn info() -> Self {
+        Self::fixed("/info").with_accept(ACCEPT_XML)
+    }
+
+    /// The Envoy device information endpoint on older firmware.
+    pub(crate) fn info_xml() -> Self {
+        Self::fixed("/info.xml").with_accept(ACCEPT_XML)
+    }
+
+    /// The Envoy production data endpoint, including per-meter details.
+    pub(crate) fn production() -> Self {
+        Self::fixed("/production.json?details=1")
+    }
+
+    /// The Envoy per-inverter production endpoint.
+    pub(crate) fn inverters_production() -> Self {
+        Self::fixed("/api/v1/production/inverters")
+    }
+
+    /// The Envoy meter configuration endpoint.
+    pub(crate) fn meters() -> Self {
+        Self::fixed("/ivp/meters")
+    }
+
+    /// The Envoy meter readings endpoint.
+    pub(crate) fn meter_readings() -> Self {
+        Self::fixed("/ivp/meters/readings")
+    }
+
+    /// The Envoy live data streaming control endpoint.
+    pub(crate) fn livedata_stream() -> Self {
+        Self::fixed("/ivp/livedata/stream")
+    }
+
+    /// The Envoy live data endpoint.
+    pub(crate) fn livedata_status() -> Self {
+        Self::fixed("/ivp/livedata/status")
+    }
+
+    /// The Envoy home summary endpoint.
+    pub(crate) fn home() -> Self {
+        Self::fixed("/home.json")
+    }
+
+    /// The Envoy device inventory endpoint.
+    pub(crate) fn inventory() -> Self {
+        Self::fixed("/inventory.json")
+    }
+
+    /// The Envoy Ensemble inventory endpoint.
+    pub(crate) fn ensemble_inventory() -> Self {
+        Self::fixed("/ivp/ensemble/inventory")
+    }
+
+    /// The Envoy Ensemble aggregated battery status endpoint.
+    pub(crate) fn ensemble_secctrl() -> Self {
+        Self::fixed("/ivp/ensemble/secctrl")
+    }
+
+    /// The Envoy tariff and battery storage settings endpoint.
+    pub(crate) fn tariff() -> Self {
+        Self::fixed("/admin/lib/tariff")
+    }
+
+    /// The Envoy power mode endpoint for a single device.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`EnphaseError::ConfigurationError`] if the serial number is
+    /// empty.
+    pub(crate) fn power_mode(serial: impl Display) -> Result<Self> {
+        Ok(Self {
+            path: format!("/ivp/mod/{}/mode/power", segment(serial)?),
+            accept: ACCEPT_JSON,
+            idempotency: Idempotency::Idempotent,
+        })
+    }
+
+    /// An endpoint which is not (yet) supported, with a path given by the
+    /// caller.
+    ///
+    /// The path is used verbatim, including any query string. Nothing is
+    /// known about what writes to the endpoint do, so they are treated as
+    /// [`Idempotency::NonIdempotent`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`EnphaseError::ConfigurationError`] if the path does not
+    /// start with `/`.
+    pub(crate) fn raw(path: &str) -> Result<Self> {
+        if !path.starts_with('/') {
+            return Err(EnphaseError::ConfigurationError(format!(
+                "Path must start with '/', got {path:?}"
+            )));
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            accept: ACCEPT_JSON,
+            idempotency: Idempotency::NonIdempotent,
+        })
+    }
+
+    /// Create a JSON endpoint from a fixed, known-good path.
+    ///
+    /// Writes to the endpoint are idempotent unless overridden with
+    /// [`Endpoint::non_idempotent`].
+    fn fixed(path: &'static str) -> Self {
+        Self {
+            path: path.to_owned(),
+            accept: ACCEPT_JSON,
+            idempotency: Idempotency::Idempotent,
+        }
+    }
+
+    /// Mark writes to the endpoint as unsafe to repeat.
+    fn non_idempotent(self) -> Self {
+        Self {
+            idempotency: Idempotency::NonIdempotent,
+            ..self
+        }
+    }
+
+    /// Override the `Accept` header sent to the endpoint.
+    pub(crate) fn with_accept(self, accept: &'static str) -> Self {
+        Self { accept, ..self }
+    }
+
+    /// The value of the `Accept` header to send to the endpoint.
+    pub(crate) fn accept(&self) -> &'static str {
+        self.accept
+    }
+
+    /// Whether writes to the endpoint can safely be repeated.
+    pub(crate) fn idempotency(&self) -> Idempotency {
+        self.idempotency
+    }
+
+    /// The headers the local web UI sends to the endpoint.
+    ///
+    /// These depend only on the family of the endpoint, so unsupported
+    /// endpoints (see [`Endpoint::raw`]) get the headers of their family too.
+    pub(crate) fn browser_headers(&self) -> BrowserHeaders {
+        BROWSER_HEADERS
+            .iter()
+            .find(|&&(prefix, _)| self.path.starts_with(prefix))
+            .map(|&(_, headers)| headers)
+            .unwrap_or_default()
+    }
+
+    /// Whether the Envoy endpoint at the given path can be requested without
+    /// a token (e.g., to check a token, or to identify the Envoy before
+    /// authenticating).
+    pub(crate) fn is_public(path: &str) -> bool {
+        PUBLIC_PATHS.contains(&path)
+    }
+
+    /// The full URL of the endpoint for the given base URL.
+    ///
+    /// Any trailing slashes on the base URL are ignored, so that base URLs
+    /// with a path prefix (e.g., behind a reverse proxy) are joined correctly.
+    pub(crate) fn url(&self, base_url: &str) -> String {
+        format!("{}{}", base_url.trim_end_matches('/'), self.path)
+    }
+}
+
+impl Display for Endpoint {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path)
+    }
+}
+
+/// Validate and percent-encode a single path segment.
+///
+/// Only the unreserved characters from RFC 3986 are left as-is; everything
+/// else (including `/`, `?` and `#`) is percent-encoded so the value cannot
+/// alter the structure of the URL.
+fn segment(value: impl Display) -> Result<String> {
+    let raw = value.to_string();
+    let trimmed = raw.trim();
+    if trimmed.is_empty() {
+        return Err(EnphaseError::ConfigurationError(
+            "Path segment must not be empty".to_owned(),
+        ));
+    }
+
+    let mut encoded = String::with_capacity(trimmed.len());
+    for byte in trimmed.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            write!(encoded, "%{byte:02X}").map_err(|e| {
+                EnphaseError::ConfigurationError(format!("Failed to encode path segment: {e}"))
+            })?;
+        }
+    }
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(Endpoint::entrez_login(), "/login")]
+    #[case(Endpoint::entrez_tokens(), "/entrez_tokens")]
+    #[case(Endpoint::check_jwt(), "/auth/check_jwt")]
+    #[case(Endpoint::info(), "/info")]
+    #[case(Endpoint::info_xml(), "/info.xml")]
+    #[case(Endpoint::production(), "/production.json?details=1")]
+    #[case(Endpoint::inverters_production(), "/api/v1/production/inverters")]
+    #[case(Endpoint::meters(), "/ivp/meters")]
+    #[case(Endpoint::meter_readings(), "/ivp/meters/readings")]
+    #[case(Endpoint::livedata_stream(), "/ivp/livedata/stream")]
+    #[case(Endpoint::livedata_status(), "/ivp/livedata/status")]
+    #[case(Endpoint::home(), "/home.json")]
+    #[case(Endpoint::inventory(), "/inventory.json")]
+    #[case(Endpoint::ensemble_inventory(), "/ivp/ensemble/inventory")]
+    #[case(Endpoint::ensemble_secctrl(), "/ivp/ensemble/secctrl")]
+    #[case(Endpoint::tariff(), "/admin/lib/tariff")]
+    fn fixed_paths(#[case] endpoint: Endpoint, #[case] expected: &str) {
+        assert_eq!(endpoint.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case(Endpoint::entrez_login(), ACCEPT_HTML)]
+    #[case(Endpoint::entrez_tokens(), ACCEPT_HTML)]
+    #[case(Endpoint::check_jwt(), ACCEPT_HTML)]
+    #[case(Endpoint::info(), ACCEPT_XML)]
+    #[case(Endpoint::info_xml(), ACCEPT_XML)]
+    #[case(Endpoint::production(), ACCEPT_JSON)]
+    #[case(Endpoint::inverters_production(), ACCEPT_JSON)]
+    #[case(Endpoint::meters(), ACCEPT_JSON)]
+    #[case(Endpoint::meter_readings(), ACCEPT_JSON)]
+    #[case(Endpoint::livedata_stream(), ACCEPT_JSON)]
+    #[case(Endpoint::livedata_status(), ACCEPT_JSON)]
+    #[case(Endpoint::home(), ACCEPT_JSON)]
+    #[case(Endpoint::inventory(), ACCEPT_JSON)]
+    #[case(Endpoint::ensemble_inventory(), ACCEPT_JSON)]
+    #[case(Endpoint::ensemble_secctrl(), ACCEPT_JSON)]
+    #[case(Endpoint::tariff(), ACCEPT_JSON)]
+    #[case(Endpoint::power_mode("603980032").expect("Serial should be valid"), ACCEPT_JSON)]
+    #[case(Endpoint::entrez_sites("My Site").expect("Query should be valid"), ACCEPT_JSON)]
+    fn accept_headers(#[case] endpoint: Endpoint, #[case] expected: &str) {
+        assert_eq!(endpoint.accept(), expected);
+    }
+
+    #[rstest]
+    #[case(Endpoint::entrez_login(), Idempotency::NonIdempotent)]
+    #[case(Endpoint::entrez_tokens(), Idempotency::NonIdempotent)]
+    #[case(Endpoint::livedata_stream(), Idempotency::Idempotent)]
+    #[case(Endpoint::tariff(), Idempotency::Idempotent)]
+    #[case(Endpoint::power_mode("603980032").expect("Serial should be valid"), Idempotency::Idempotent)]
+    #[case(Endpoint::raw("/ivp/peb/newscan").expect("Path should be valid"), Idempotency::NonIdempotent)]
+    fn idempotency(#[case] endpoint: Endpoint, #[case] expected: Idempotency) {
+        assert_eq!(endpoint.idempotency(), expected);
+    }
+
+    #[rstest]
+    #[case(Endpoint::entrez_login(), BrowserHeaders::default())]
+    #[case(Endpoint::entrez_sites("My Site").expect("Query should be valid"), BrowserHeaders::default())]
+    #[case(Endpoint::check_jwt(), BrowserHeaders::default())]
+    #[case(Endpoint::info(), BrowserHeaders::default())]
+    #[case(Endpoint::info_xml(), BrowserHeaders::default())]
+    #[case(Endpoint::production(), UI_SCRIPT)]
+    #[case(Endpoint::inverters_production(), UI_SCRIPT)]
+    #[case(Endpoint::livedata_status(), UI_SCRIPT)]
+    #[case(Endpoint::home(), UI_SCRIPT)]
+    #[case(Endpoint::inventory(), UI_SCRIPT)]
+    #[case(Endpoint::tariff(), UI_SCRIPT)]
+    #[case(Endpoint::power_mode("603980032").expect("Serial should be valid"), UI_SCRIPT)]
+    #[case(Endpoint::raw("/ivp/pdm/energy").expect("Path should be valid"), UI_SCRIPT)]
+    #[case(Endpoint::raw("/ivpx").expect("Path should be valid"), BrowserHeaders::default())]
+    fn browser_headers(#[case] endpoint: Endpoint, #[case] expected: BrowserHeaders) {
+        assert_eq!(endpoint.browser_headers(), expected);
+    }
+
+    #[rstest]
+    #[case("603980032", "/ivp/mod/603980032/mode/power")]
+    #[case(" 603980032 ", "/ivp/mod/603980032/mode/power")]
+    #[case("a/b", "/ivp/mod/a%2Fb/mode/power")]
+    #[case("a b?c#d", "/ivp/mod/a%20b%3Fc%23d/mode/power")]
+    #[case("\u{fc}", "/ivp/mod/%C3%BC/mode/power")]
+    fn power_mode_encoding(#[case] serial: &str, #[case] expected: &str) {
+        let endpoint = Endpoint::power_mode(serial).expect("Serial should be valid");
+        assert_eq!(endpoint.to_string(), expected);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("   ")]
+    fn power_mode_rejects_empty(#[case] serial: &str) {
+        let result = Endpoint::power_mode(serial);
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Empty serial should be rejected"
+        );
+    }
+
+    #[test]
+    fn entrez_sites_encoding() {
+        let endpoint = Endpoint::entrez_sites("My Site").expect("Query should be valid");
+        assert_eq!(endpoint.to_string(), "/site/My%20Site");
+    }
+
+    #[rstest]
+    #[case("/", "/")]
+    #[case("/ivp/pdm/energy", "/ivp/pdm/energy")]
+    #[case("/api/v1/x?a=b c&d=%2F", "/api/v1/x?a=b c&d=%2F")]
+    fn raw_paths_are_verbatim(#[case] raw: &str, #[case] expected: &str) {
+        let endpoint = Endpoint::raw(raw).expect("Path should be valid");
+        assert_eq!(endpoint.to_string(), expected);
+        assert_eq!(endpoint.accept(), ACCEPT_JSON);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("ivp/pdm/energy")]
+    #[case(" /ivp/pdm/energy")]
+    #[case("https://example.com/")]
+    fn raw_rejects_relative_paths(#[case] raw: &str) {
+        let result = Endpoint::raw(raw);
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Path {raw:?} should be rejected"
+        );
+    }
+
+    #[rstest]
+    #[case("https://envoy.local", "https://envoy.local/auth/check_jwt")]
+    #[case("https://envoy.local/", "https://envoy.local/auth/check_jwt")]
+    #[case("https://proxy/envoy", "https://proxy/envoy/auth/check_jwt")]
+    #[case("https://proxy/envoy/", "https://proxy/envoy/auth/check_jwt")]
+    fn url_prefixes(#[case] base_url: &str, #[case] expected: &str) {
+        assert_eq!(Endpoint::check_jwt().url(base_url), expected);
+    }
+}
@@ -0,0 +1,137 @@
+//! # Environment configuration
+//!
+//! This module reads the configuration of the clients from environment
+//! variables. All environment lookups go through [`var`], so that error
+//! messages consistently name the exact variable which was missing.
+//!
+//! With the `dotenv` feature, variables can additionally be loaded from a
+//! `.env` file using [`load_dotenv`]. Variables which are already set in the
+//! environment always take precedence over those in the file.
+
+use crate::error::{EnphaseError, Result};
+
+/// Read an environment variable.
+///
+/// # Errors
+///
+/// Returns a [`EnphaseError::ConfigurationError`] naming the variable if it is
+/// not set or is not valid Unicode.
+pub(crate) fn var(name: &str) -> Result<String> {
+    std::env::var(name).map_err(|_e| {
+        EnphaseError::ConfigurationError(format!("{name} environment variable not set"))
+    })
+}
+
+/// Load environment variables from a `.env` file.
+///
+/// Each variable in the file is only set if it is not already present in the
+/// environment, so the real environment always takes precedence over the file.
+///
+/// This function modifies the environment of the current process. As with
+/// [`std::env::set_var`], it should be called early in `main`, before any other
+/// threads are spawned.
+///
+/// # Arguments
+///
+/// * `path` - The path of the `.env` file to load
+///
+/// # Returns
+///
+/// Returns `Ok(())` if the file was loaded successfully.
+///
+/// # Errors
+///
+/// Returns a [`EnphaseError::ConfigurationError`] if the file cannot be read
+/// or parsed.
+///
+/// # Example
+///
+/// ```no_run
+/// use enphase_api::{Entrez, load_dotenv};
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// load_dotenv(".env")?;
+/// let client = Entrez::default();
+/// client.login_with_env().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "dotenv")]
+#[inline]
+pub fn load_dotenv(path: impl AsRef<std::path::Path>) -> Result<()> {
+    let file = path.as_ref();
+    dotenvy::from_path(file).map_err(|e| {
+        EnphaseError::ConfigurationError(format!(
+            "Failed to load environment file {}: {e}",
+            file.display()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn var_missing_names_variable() {
+        let result = var("ENPHASE_API_TEST_MISSING_VARIABLE");
+        let Err(EnphaseError::ConfigurationError(message)) = result else {
+            panic!("Expected ConfigurationError, got {result:?}");
+        };
+        assert_eq!(
+            message,
+            "ENPHASE_API_TEST_MISSING_VARIABLE environment variable not set"
+        );
+    }
+
+    #[cfg(feature = "dotenv")]
+    #[expect(
+        clippy::multiple_unsafe_ops_per_block,
+        reason = "Setting and removing environment variables in tests"
+    )]
+    #[test]
+    fn load_dotenv_precedence() {
+        let path =
+            std::env::temp_dir().join(format!("enphase-api-test-{}.env", std::process::id()));
+        std::fs::write(
+            &path,
+            "ENPHASE_API_TEST_DOTENV_REAL=from_file\nENPHASE_API_TEST_DOTENV_FILE=from_file\n",
+        )
+        .expect("Failed to write .env file");
+
+        // SAFETY: This is a test function and we need to set environment variables
+        // for testing purposes. The variables are unique to this test and
+        // cleaned up after the test.
+        unsafe {
+            std::env::set_var("ENPHASE_API_TEST_DOTENV_REAL", "from_env");
+        }
+
+        let result = load_dotenv(&path);
+        let real = var("ENPHASE_API_TEST_DOTENV_REAL");
+        let file = var("ENPHASE_API_TEST_DOTENV_FILE");
+
+        // SAFETY: Removing test environment variables that were set earlier in this test.
+        // No other code should be accessing these variables concurrently.
+        unsafe {
+            std::env::remove_var("ENPHASE_API_TEST_DOTENV_REAL");
+            std::env::remove_var("ENPHASE_API_TEST_DOTENV_FILE");
+        }
+        std::fs::remove_file(&path).expect("Failed to remove .env file");
+
+        result.expect("Loading the .env file should succeed");
+        assert_eq!(real.expect("Variable should be set"), "from_env");
+        assert_eq!(file.expect("Variable should be set"), "from_file");
+    }
+
+    #[cfg(feature = "dotenv")]
+    #[test]
+    fn load_dotenv_missing_file() {
+        let result = load_dotenv("/nonexistent/enphase-api/.env");
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Missing .env file should be a configuration error"
+        );
+    }
+}
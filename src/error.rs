@@ -3,13 +3,106 @@
 //! This module contains all error types and handling for the Enphase API
 //! client.
 
+#[cfg(feature = "client")]
+use crate::client::envoy::certificate_error;
+
 /// Error types that can occur when using the Enphase API client.
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
 pub enum EnphaseError {
     /// HTTP request error from reqwest.
+    #[cfg(feature = "client")]
     #[error("HTTP request failed: {0}")]
-    Http(#[from] reqwest::Error),
+    Http(reqwest::Error),
+
+    /// The request timed out.
+    ///
+    /// This is split from [`EnphaseError::Http`] as timeouts are usually
+    /// transient, so callers may want to retry the request.
+    #[cfg(feature = "client")]
+    #[error("HTTP request timed out: {0}")]
+    Timeout(reqwest::Error),
+
+    /// The Envoy did not present the pinned certificate.
+    ///
+    /// This means that either the certificate of the Envoy has changed (e.g.,
+    /// after a firmware update), or that another device is intercepting the
+    /// connection.
+    #[error("TLS certificate mismatch: the Envoy did not present the pinned certificate")]
+    CertificateMismatch,
+
+    /// The certificate presented by the Envoy does not name the expected
+    /// serial number.
+    ///
+    /// Either another gateway is answering at the address, or the connection
+    /// is being intercepted.
+    #[error(
+        "Envoy identity mismatch (expected: {expected}, certificate: {})",
+        .presented.as_deref().unwrap_or("no serial number")
+    )]
+    IdentityMismatch {
+        /// The serial number the Envoy was expected to have.
+        expected: String,
+        /// The serial number named by the certificate, if any.
+        presented: Option<String>,
+    },
+
+    /// The certificate presented by the Envoy is not trusted.
+    ///
+    /// For example, the certificate was not issued by a trusted CA, or has
+    /// expired.
+    #[error("TLS certificate not trusted: {0}")]
+    UntrustedCertificate(String),
+
+    /// The certificate presented by the Envoy is not valid for its hostname.
+    #[error("TLS hostname mismatch: {0}")]
+    HostnameMismatch(String),
+
+    /// A request failed after being retried, without getting a response
+    /// (e.g., a connection error or a timeout).
+    ///
+    /// The source holds the error of the last attempt. A request whose last
+    /// attempt gets a response is reported like any other response.
+    #[error("{source} (after {attempts} attempts)")]
+    Retried {
+        /// The number of attempts made.
+        attempts: u32,
+        /// The error of the last attempt.
+        source: Box<EnphaseError>,
+    },
+
+    /// No Envoy answered while browsing the local network.
+    #[error("No Envoy found on the local network")]
+    NoEnvoyFound,
+
+    /// None of the endpoints able to serve a capability answered, e.g. because
+    /// a firmware update removed the endpoint which used to serve it.
+    #[error(
+        "The Envoy (firmware {}{}) does not serve the {capability} (tried: {})",
+        .firmware.as_deref().unwrap_or("unknown"),
+        .profile.as_ref().map(|forced| format!(", forced to the {forced} profile")).unwrap_or_default(),
+        .tried.join(", ")
+    )]
+    CapabilityMissing {
+        /// What the endpoints serve (e.g., `device information`).
+        capability: String,
+        /// The firmware version of the Envoy, if known.
+        firmware: Option<String>,
+        /// The firmware profile the client was forced to, if any (see
+        /// [`EnvoyBuilder::firmware_profile`](crate::EnvoyBuilder::firmware_profile)).
+        profile: Option<String>,
+        /// The endpoints which were tried, in order.
+        tried: Vec<String>,
+    },
+
+    /// The Envoy does not know the device with the given serial number.
+    #[error("Device not found: {0}")]
+    DeviceNotFound(String),
+
+    /// The Envoy is temporarily unable to handle the request (e.g., it is
+    /// busy or the device is locked), so the request may be retried later.
+    #[error("Envoy temporarily unavailable: {0}")]
+    Unavailable(String),
 
     /// Invalid response from the API.
     #[error("Invalid API response: {0}")]
@@ -23,6 +116,38 @@ pub enum EnphaseError {
     #[error("Configuration error: {0}")]
     ConfigurationError(String),
 
+    /// A serial number could not be parsed.
+    ///
+    /// If several interpretations of the input were possible, `candidates`
+    /// lists each of them.
+    #[error("Invalid serial number {input:?} (candidates: {candidates:?})")]
+    InvalidSerialNumber {
+        /// The serial number as provided.
+        input: String,
+        /// The interpretations which were considered.
+        candidates: Vec<String>,
+    },
+
+    /// The serial numbers known for an Envoy do not match.
+    ///
+    /// This typically means that a token issued for one Envoy is being used
+    /// with another. Each field holds the serial number from one source, or
+    /// `None` if it is not known.
+    #[error(
+        "Serial number mismatch (token: {}, configured: {}, device: {})",
+        .token.as_deref().unwrap_or("unknown"),
+        .configured.as_deref().unwrap_or("unknown"),
+        .device.as_deref().unwrap_or("unknown")
+    )]
+    SerialNumberMismatch {
+        /// The serial number the token was issued for.
+        token: Option<String>,
+        /// The serial number the client was configured with.
+        configured: Option<String>,
+        /// The serial number reported by the device.
+        device: Option<String>,
+    },
+
     /// I/O error.
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
@@ -32,5 +157,40 @@ pub enum EnphaseError {
     JsonError(#[from] serde_json::Error),
 }
 
+#[cfg(feature = "client")]
+impl From<reqwest::Error> for EnphaseError {
+    #[inline]
+    fn from(error: reqwest::Error) -> Self {
+        if let Some(certificate_error) = certificate_error(&error) {
+            certificate_error
+        } else if error.is_timeout() {
+            Self::Timeout(error)
+        } else {
+            Self::Http(error)
+        }
+    }
+}
+
 /// Result type for Enphase API operations.
 pub type Result<T> = core::result::Result<T, EnphaseError>;
+
+/// Error returned when parsing an enum from its string form fails.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown {kind} value {value:?}")]
+#[non_exhaustive]
+pub struct ParseEnumError {
+    /// The name of the enum which was being parsed.
+    pub kind: &'static str,
+    /// The value which could not be parsed.
+    pub value: String,
+}
+
+impl ParseEnumError {
+    /// Create a new parse error for the given enum and value.
+    pub(crate) fn new(kind: &'static str, value: impl Into<String>) -> Self {
+        Self {
+            kind,
+            value: value.into(),
+        }
+    }
+}
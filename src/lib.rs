@@ -1,13 +1,46 @@
 //! # Rust client for Enphase/Envoy systems.
+//!
+//! ## Features
+//!
+//! - `client` (default): The [`Entrez`] and [`Envoy`] HTTP clients. Disabling
+//!   this feature leaves only the [`models`] and error types, without pulling
+//!   in an HTTP or TLS stack.
+//! - `discovery`: Discovery of Envoy devices on the local network over mDNS,
+//!   with [`discover`] and [`Envoy::discover_first`].
+//! - `dotenv`: Support for loading environment variables from a `.env` file
+//!   with [`load_dotenv`].
 
 #![expect(clippy::pub_use, reason = "Root API exports for convenience")]
 
+extern crate alloc;
+
+#[cfg(feature = "client")]
 mod client;
+#[cfg(feature = "client")]
+mod endpoint;
+#[cfg(feature = "client")]
+mod env;
 mod error;
 pub mod models;
 
 // Export main clients
-pub use client::{entrez::Entrez, envoy::Envoy};
+#[cfg(feature = "discovery")]
+pub use client::discovery::{DiscoveredEnvoy, discover};
+#[cfg(feature = "client")]
+pub use client::{
+    entrez::{Entrez, EntrezBuilder},
+    envoy::{
+        Capabilities, Envoy, EnvoyBuilder, FirmwareGeneration, InfoEndpoint, RetryPolicy, Scheme,
+        TlsVersion,
+    },
+    poller::{EnvoyPoller, PollEvent, PollTarget, StopHandle},
+    session::EnvoySession,
+};
+#[cfg(feature = "dotenv")]
+pub use env::load_dotenv;
+/// The HTTP method of a raw request (see [`Envoy::request_json`]).
+#[cfg(feature = "client")]
+pub use reqwest::Method;
 
 // Export error types (both names for compatibility)
-pub use error::{EnphaseError, Result};
+pub use error::{EnphaseError, ParseEnumError, Result};
@@ -1,8 +1,82 @@
 //! # Models for the Enphase API client
 //!
 //! This module contains data models used by the Enphase API client.
+//!
+//! ## String representations
+//!
+//! The public enums whose variants carry no data implement
+//! [`Display`](core::fmt::Display) and [`FromStr`] using lowercase kebab-case
+//! strings (e.g., `on`, `off`). These strings are part of the stable API: they
+//! will not change within a major version, so they are safe to store (e.g., in
+//! a database). They are independent from the wire format used by the Envoy,
+//! which is mapped separately.
+//!
+//! Enums whose variants carry data have no such strings:
+//! [`ensemble::StateOfHealth`] and [`merge::ConflictPolicy`] implement
+//! neither trait, and [`inventory::DeviceStatus`] displays the status code as
+//! reported by the Envoy (see [`inventory::DeviceStatus::as_str`]), since
+//! unknown codes are kept as they are.
+
+use core::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EnphaseError, Result};
+
+/// Implement the stable `Display` and `FromStr` conversions for an enum.
+///
+/// Each variant must be listed along with its string form. As the generated
+/// `Display` implementation matches exhaustively, adding a variant without a
+/// string form fails to compile. The generated `STRINGS` table is used by the
+/// tests to check that every string round-trips.
+macro_rules! string_enum {
+    ($name:ident { $($variant:ident => $text:literal),+ $(,)? }) => {
+        impl $name {
+            /// All variants along with their string forms.
+            #[cfg(test)]
+            pub(crate) const STRINGS: &[(Self, &str)] = &[$((Self::$variant, $text)),+];
+        }
 
-use serde::Deserialize;
+        impl core::fmt::Display for $name {
+            #[inline]
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.write_str(match *self {
+                    $(Self::$variant => $text),+
+                })
+            }
+        }
+
+        impl core::str::FromStr for $name {
+            type Err = $crate::error::ParseEnumError;
+
+            #[inline]
+            fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+                match s {
+                    $($text => Ok(Self::$variant),)+
+                    _ => Err($crate::error::ParseEnumError::new(stringify!($name), s)),
+                }
+            }
+        }
+    };
+}
+
+pub mod canonical;
+pub mod ensemble;
+pub mod flow;
+pub mod home;
+pub mod info;
+pub mod inventory;
+pub mod livedata;
+pub mod merge;
+pub mod meters;
+pub mod metrics;
+pub mod production;
+pub mod site;
+pub mod tariff;
+pub mod template;
+mod token;
+
+pub use token::{EnphaseUser, EnvoyToken, TokenSource};
 
 /// Power state for an inverter or device.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -12,52 +86,454 @@ pub enum PowerState {
     On,
     /// Power is OFF.
     Off,
+    /// Power is OFF although the device was last requested to be on: the
+    /// Envoy holds it off, e.g. for the grid profile or a demand response
+    /// (DRM) command.
+    ///
+    /// This state is only reported, and cannot be requested.
+    ForcedOff,
 }
 
-/// Response structure for getting the power status.
-#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+/// The power status of a device, as reported by the Envoy at
+/// `/ivp/mod/{serial}/mode/power`.
+///
+/// Use [`PowerState::from_response`] to read the power state.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[non_exhaustive]
 #[serde(rename_all = "camelCase")]
 pub struct PowerStatusResponse {
     /// Whether power is forced off.
     pub power_forced_off: bool,
+    /// The production power limit, as a percentage of the rating of the
+    /// device, if the device reports one.
+    ///
+    /// The name of this field (`powerLimit`) has not been confirmed against a
+    /// capture from a device with a power limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_limit: Option<u8>,
+    /// Number of values in [`PowerStatusResponse::arr`], on firmware which
+    /// reports the requested mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u32>,
+    /// The last requested mode, on firmware which reports it, with the same
+    /// values as [`SetPowerRequest::arr`]: `0` for on and `1` for off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arr: Option<Vec<u8>>,
 }
 
+/// The request body setting the power mode of a device, sent to
+/// `/ivp/mod/{serial}/mode/power`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SetPowerRequest {
+    /// Number of values in [`SetPowerRequest::arr`].
+    pub length: u32,
+    /// The requested mode: `0` for on and `1` for off.
+    pub arr: Vec<u8>,
+    /// The production power limit, as a percentage of the rating of the
+    /// device.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u8>,
+}
+
+impl SetPowerRequest {
+    /// Create a request setting the power state of a device.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EnphaseError::ConfigurationError`] for
+    /// [`PowerState::ForcedOff`], which cannot be requested.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::{PowerState, SetPowerRequest};
+    ///
+    /// let request = SetPowerRequest::new(PowerState::Off)?;
+    /// assert_eq!(serde_json::to_string(&request)?, r#"{"length":1,"arr":[1]}"#);
+    /// # Ok::<(), Box<dyn std::error::Error>>(())
+    /// ```
+    #[inline]
+    pub fn new(state: PowerState) -> Result<Self> {
+        Ok(Self {
+            length: 1,
+            arr: vec![state.payload_value()?],
+            limit: None,
+        })
+    }
+
+    /// Create a request limiting the production power of a device, which
+    /// also turns the device on.
+    ///
+    /// The name of the limit field (`limit`) has not been confirmed against a
+    /// capture, and firmware without power limits ignores it and only turns
+    /// the device on. The limit should be read back once set, as
+    /// [`Envoy::set_power_limit`](crate::Envoy::set_power_limit) does.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EnphaseError::ConfigurationError`] if the percentage is
+    /// greater than 100.
+    #[inline]
+    pub fn limited(percent: u8) -> Result<Self> {
+        if percent > 100 {
+            return Err(EnphaseError::ConfigurationError(format!(
+                "Power limit must be between 0 and 100 percent, got {percent}"
+            )));
+        }
+        Ok(Self {
+            limit: Some(percent),
+            ..Self::new(PowerState::On)?
+        })
+    }
+}
+
+string_enum!(PowerState {
+    On => "on",
+    Off => "off",
+    ForcedOff => "forced-off",
+});
+
+/// The power mode payload value turning a device on.
+///
+/// The payload is the value of `powerForcedOff` requested for the device, so
+/// `0` (not forced off) turns the device on, and `1` turns it off.
+const POWER_MODE_ON: u8 = 0;
+
+/// The power mode payload value turning a device off.
+const POWER_MODE_OFF: u8 = 1;
+
 impl PowerState {
+    /// The power state reported in a power status response.
+    ///
+    /// A device which is not forced off is on. A device which is forced off
+    /// is off, unless the response reports that it was last requested to be
+    /// on, in which case it is held off by the Envoy and is
+    /// [`PowerState::ForcedOff`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::{PowerState, PowerStatusResponse};
+    ///
+    /// let response: PowerStatusResponse =
+    ///     serde_json::from_str(r#"{"powerForcedOff": true, "length": 1, "arr": [0]}"#)?;
+    /// assert_eq!(PowerState::from_response(&response), PowerState::ForcedOff);
+    /// # Ok::<(), serde_json::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn from_response(response: &PowerStatusResponse) -> Self {
+        if !response.power_forced_off {
+            return Self::On;
+        }
+        let requested = response.arr.as_deref().and_then(<[u8]>::first).copied();
+        if requested == Some(POWER_MODE_ON) {
+            Self::ForcedOff
+        } else {
+            Self::Off
+        }
+    }
+
     /// Get the payload array value for this power state.
-    pub(crate) fn payload_value(self) -> u8 {
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EnphaseError::ConfigurationError`] for
+    /// [`PowerState::ForcedOff`], which cannot be requested.
+    fn payload_value(self) -> Result<u8> {
         match self {
-            PowerState::On => 0,
-            PowerState::Off => 1,
+            PowerState::On => Ok(POWER_MODE_ON),
+            PowerState::Off => Ok(POWER_MODE_OFF),
+            PowerState::ForcedOff => Err(EnphaseError::ConfigurationError(
+                "The forced-off power state is set by the Envoy and cannot be requested".to_owned(),
+            )),
         }
     }
 }
 
+/// A device found to be off, as reported by
+/// [`Envoy::forced_off_devices`](crate::Envoy::forced_off_devices).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ForcedOffDevice {
+    /// Serial number of the device.
+    pub serial_number: String,
+    /// How the state of the device was determined.
+    pub source: PowerStateSource,
+}
+
+/// How the power state of a device was determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PowerStateSource {
+    /// Production is stopped site-wide, so every device is off.
+    ProductionSwitch,
+    /// The inventory reports whether the device is operating.
+    Inventory,
+    /// The power mode of the device was requested.
+    PowerMode,
+}
+
+string_enum!(PowerStateSource {
+    ProductionSwitch => "production-switch",
+    Inventory => "inventory",
+    PowerMode => "power-mode",
+});
+
+/// Number of digits in a canonical Envoy serial number.
+const SERIAL_NUMBER_DIGITS: usize = 12;
+
+/// A normalized Envoy serial number.
+///
+/// Serial numbers are copied from many places (the Enlighten UI, the label on
+/// the gateway, installer paperwork) and often contain extra formatting. The
+/// canonical form is the 12 digits of the serial number, with no separators.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SerialNumber(String);
+
+impl SerialNumber {
+    /// Parse and normalize a serial number.
+    ///
+    /// The following formatting variants are accepted:
+    ///
+    /// - Leading and embedded whitespace or hyphens (`4822 4301-2345`)
+    /// - A leading `EN` or `SN` label, optionally followed by a colon
+    ///   (`SN: 482243012345`)
+    /// - A trailing one or two character suffix, as printed on some labels
+    ///   (`482243012345-7`)
+    /// - A leading part-number fragment separated from the serial number
+    ///   (`800-00654 482243012345`)
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The serial number as entered by the user
+    ///
+    /// # Returns
+    ///
+    /// Returns the normalized serial number.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EnphaseError::InvalidSerialNumber`] if no 12-digit serial
+    /// number can be found in the input, or if several different
+    /// interpretations are possible. In the latter case, the error lists each
+    /// interpretation that was considered.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::SerialNumber;
+    ///
+    /// let serial = SerialNumber::parse("SN: 4822 4301 2345")?;
+    /// assert_eq!(serial.as_str(), "482243012345");
+    /// # Ok::<(), enphase_api::EnphaseError>(())
+    /// ```
+    #[inline]
+    pub fn parse(input: impl AsRef<str>) -> Result<Self> {
+        let raw = input.as_ref();
+        let invalid = |candidates: Vec<String>| EnphaseError::InvalidSerialNumber {
+            input: raw.to_owned(),
+            candidates,
+        };
+
+        let groups: Vec<&str> = strip_label(raw.trim())
+            .split(|c: char| c.is_whitespace() || c == '-')
+            .filter(|group| !group.is_empty())
+            .collect();
+        if groups.is_empty()
+            || !groups
+                .iter()
+                .all(|group| group.chars().all(|c| c.is_ascii_alphanumeric()))
+        {
+            return Err(invalid(Vec::new()));
+        }
+
+        let mut candidates: Vec<String> = Vec::new();
+        let mut consider = |parts: &[&str]| {
+            let joined = parts.concat();
+            if joined.len() == SERIAL_NUMBER_DIGITS
+                && joined.chars().all(|c| c.is_ascii_digit())
+                && !candidates.contains(&joined)
+            {
+                candidates.push(joined);
+            }
+        };
+
+        consider(&groups);
+        for group in &groups {
+            consider(&[group]);
+        }
+        if let Some((last, rest)) = groups.split_last()
+            && last.len() <= 2
+        {
+            consider(rest);
+        }
+        if let Some((_, rest)) = groups.split_first() {
+            consider(rest);
+        }
+
+        match candidates.as_slice() {
+            [serial] => Ok(Self(serial.clone())),
+            _ => Err(invalid(candidates)),
+        }
+    }
+
+    /// The canonical digits of the serial number.
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Strip a leading `EN`/`SN` label (and optional colon) from a serial number.
+fn strip_label(input: &str) -> &str {
+    ["EN", "en", "SN", "sn", "S/N", "s/n"]
+        .iter()
+        .find_map(|label| input.strip_prefix(label))
+        .map_or(input, |rest| rest.trim_start_matches(':').trim_start())
+}
+
+impl fmt::Display for SerialNumber {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for SerialNumber {
+    type Err = EnphaseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl AsRef<str> for SerialNumber {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ParseEnumError;
     use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// Check that every string form of an enum round-trips through `FromStr`
+    /// and `Display`, and that the string forms are unique.
+    fn check_string_round_trip<T>(strings: &[(T, &str)])
+    where
+        T: Copy + fmt::Debug + fmt::Display + PartialEq + FromStr<Err = ParseEnumError>,
+    {
+        for &(variant, text) in strings {
+            assert_eq!(variant.to_string(), text);
+            assert_eq!(text.parse::<T>(), Ok(variant));
+        }
+        let mut texts: Vec<&str> = strings.iter().map(|&(_, text)| text).collect();
+        texts.sort_unstable();
+        texts.dedup();
+        assert_eq!(texts.len(), strings.len(), "String forms must be unique");
+    }
+
+    macro_rules! assert_string_round_trip {
+        ($($name:ty),+ $(,)?) => {
+            $(check_string_round_trip(<$name>::STRINGS);)+
+        };
+    }
 
     #[test]
-    fn power_state_on_payload() {
-        let state = PowerState::On;
-        assert_eq!(
-            state.payload_value(),
-            0,
-            "PowerState::On should have payload value 0"
+    fn enum_string_round_trip() {
+        assert_string_round_trip!(
+            PowerState,
+            PowerStateSource,
+            production::MeasurementType,
+            production::ProductionState,
+            EnphaseUser,
+            TokenSource,
+            meters::MeterState,
+            meters::PhaseMode,
+            meters::MeteringStatus,
+            livedata::StreamState,
+            ensemble::RelayState,
+            flow::ConsumptionSource,
+            tariff::BatteryMode,
+            home::InterfaceType,
+            inventory::DeviceClass
         );
     }
 
     #[test]
-    fn power_state_off_payload() {
-        let state = PowerState::Off;
+    fn enum_from_str_unknown() {
+        let err = "maybe"
+            .parse::<PowerState>()
+            .expect_err("Unknown value should fail to parse");
+        assert_eq!(err.to_string(), r#"Unknown PowerState value "maybe""#);
+    }
+
+    #[rstest]
+    #[case::on(PowerState::On, 0)]
+    #[case::off(PowerState::Off, 1)]
+    fn power_state_payload(#[case] state: PowerState, #[case] expected: u8) {
         assert_eq!(
-            state.payload_value(),
-            1,
-            "PowerState::Off should have payload value 1"
+            state.payload_value().expect("State should be settable"),
+            expected
+        );
+    }
+
+    #[test]
+    fn power_state_forced_off_payload() {
+        let result = PowerState::ForcedOff.payload_value();
+
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Forced off should not be settable, got {result:?}"
         );
     }
 
+    #[rstest]
+    #[case::on(r#"{"powerForcedOff": false}"#, PowerState::On)]
+    #[case::off(r#"{"powerForcedOff": true}"#, PowerState::Off)]
+    #[case::requested_on(
+        r#"{"powerForcedOff": false, "length": 1, "arr": [0]}"#,
+        PowerState::On
+    )]
+    #[case::requested_off(
+        r#"{"powerForcedOff": true, "length": 1, "arr": [1]}"#,
+        PowerState::Off
+    )]
+    #[case::held_off(
+        r#"{"powerForcedOff": true, "length": 1, "arr": [0]}"#,
+        PowerState::ForcedOff
+    )]
+    #[case::empty_arr(r#"{"powerForcedOff": true, "length": 0, "arr": []}"#, PowerState::Off)]
+    fn power_state_from_response(#[case] json: &str, #[case] expected: PowerState) {
+        let response: PowerStatusResponse =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert_eq!(PowerState::from_response(&response), expected);
+    }
+
+    #[rstest]
+    #[case::on(PowerState::On)]
+    #[case::off(PowerState::Off)]
+    fn power_state_payload_round_trip(#[case] state: PowerState) {
+        let value = state.payload_value().expect("State should be settable");
+        let json = format!(
+            r#"{{"powerForcedOff": {}, "length": 1, "arr": [{value}]}}"#,
+            value == 1
+        );
+        let response: PowerStatusResponse =
+            serde_json::from_str(&json).expect("Should deserialize successfully");
+
+        assert_eq!(PowerState::from_response(&response), state);
+    }
+
     #[test]
     fn deserialize_power_forced_off_true() {
         let json = r#"{"powerForcedOff": true}"#;
@@ -74,5 +550,105 @@ mod tests {
             serde_json::from_str(json).expect("Should deserialize successfully");
 
         assert!(!response.power_forced_off, "powerForcedOff should be false");
+        assert_eq!(response.power_limit, None);
+        assert_eq!(response.length, None);
+        assert_eq!(response.arr, None);
+    }
+
+    #[test]
+    fn deserialize_power_mode_arr() {
+        let json = r#"{"powerForcedOff": true, "length": 1, "arr": [1]}"#;
+        let response: PowerStatusResponse =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert_eq!(response.length, Some(1));
+        assert_eq!(response.arr, Some(vec![1]));
+    }
+
+    #[rstest]
+    #[case::minimal(r#"{"powerForcedOff":false}"#)]
+    #[case::limit(r#"{"powerForcedOff":false,"powerLimit":50}"#)]
+    #[case::requested_mode(r#"{"powerForcedOff":true,"length":1,"arr":[0]}"#)]
+    fn power_status_response_round_trip(#[case] json: &str) {
+        let response: PowerStatusResponse =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+        let serialized = serde_json::to_string(&response).expect("Should serialize");
+
+        assert_eq!(serialized, json);
+        assert_eq!(
+            serde_json::from_str::<PowerStatusResponse>(&serialized)
+                .expect("Should deserialize successfully"),
+            response
+        );
+    }
+
+    #[rstest]
+    #[case::on(SetPowerRequest::new(PowerState::On), r#"{"length":1,"arr":[0]}"#)]
+    #[case::off(SetPowerRequest::new(PowerState::Off), r#"{"length":1,"arr":[1]}"#)]
+    #[case::limited(SetPowerRequest::limited(50), r#"{"length":1,"arr":[0],"limit":50}"#)]
+    #[case::unlimited(SetPowerRequest::limited(100), r#"{"length":1,"arr":[0],"limit":100}"#)]
+    fn set_power_request_round_trip(#[case] built: Result<SetPowerRequest>, #[case] json: &str) {
+        let request = built.expect("Request should be valid");
+        let serialized = serde_json::to_string(&request).expect("Should serialize");
+
+        assert_eq!(serialized, json);
+        assert_eq!(
+            serde_json::from_str::<SetPowerRequest>(&serialized)
+                .expect("Should deserialize successfully"),
+            request
+        );
+    }
+
+    #[rstest]
+    #[case::forced_off(SetPowerRequest::new(PowerState::ForcedOff))]
+    #[case::over_limit(SetPowerRequest::limited(101))]
+    fn set_power_request_invalid(#[case] request: Result<SetPowerRequest>) {
+        assert!(
+            matches!(request, Err(EnphaseError::ConfigurationError(_))),
+            "Expected ConfigurationError, got {request:?}"
+        );
+    }
+
+    #[test]
+    fn deserialize_power_limit() {
+        let json = r#"{"powerForcedOff": false, "powerLimit": 50}"#;
+        let response: PowerStatusResponse =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert_eq!(response.power_limit, Some(50));
+    }
+
+    #[rstest]
+    #[case::canonical("482243012345", "482243012345")]
+    #[case::whitespace("  482243012345\n", "482243012345")]
+    #[case::grouped_spaces("4822 4301 2345", "482243012345")]
+    #[case::grouped_hyphens("4822-4301-2345", "482243012345")]
+    #[case::en_label("EN482243012345", "482243012345")]
+    #[case::sn_label("SN: 482243012345", "482243012345")]
+    #[case::slash_label("S/N 4822 4301 2345", "482243012345")]
+    #[case::checksum_digit("482243012345-7", "482243012345")]
+    #[case::checksum_letters("482243012345 AB", "482243012345")]
+    #[case::part_number("800-00654 482243012345", "482243012345")]
+    fn serial_number_formats(#[case] input: &str, #[case] expected: &str) {
+        let serial = SerialNumber::parse(input).expect("Serial number should parse");
+        assert_eq!(serial.as_str(), expected);
+    }
+
+    #[rstest]
+    #[case::empty("", &[])]
+    #[case::too_short("12345", &[])]
+    #[case::too_long("4822430123456789", &[])]
+    #[case::letters("48224301234X", &[])]
+    #[case::punctuation("482243.012345", &[])]
+    #[case::two_serials(
+        "482243012345 121212121212",
+        &["482243012345", "121212121212"]
+    )]
+    fn serial_number_errors(#[case] input: &str, #[case] expected: &[&str]) {
+        let result = SerialNumber::parse(input);
+        let Err(EnphaseError::InvalidSerialNumber { candidates, .. }) = result else {
+            panic!("Expected InvalidSerialNumber error, got {result:?}");
+        };
+        assert_eq!(candidates, expected);
     }
 }
@@ -0,0 +1,409 @@
+//! # Canonical JSON
+//!
+//! The Envoy is free to reorder object keys and reformat numbers between two
+//! reads of the same settings (e.g., `20` may be read back as `20.0`), so the
+//! documents cannot be compared as strings. This module provides a canonical
+//! form for JSON documents, and a [`SettingsBundle`] of settings documents
+//! which can be compared to report what drifted between two snapshots.
+//!
+//! The canonical form has:
+//!
+//! - Object keys sorted by their UTF-8 bytes
+//! - Numbers in their shortest form, without exponent, with integral values
+//!   written as integers (`1.0` and `1e0` are both written as `1`)
+//! - Strings with only the escapes required by JSON (`"\u00e9"` is written
+//!   as `"é"`)
+//! - No insignificant whitespace
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+use serde_json::{Number, Value};
+
+/// Write a JSON value in its canonical form.
+///
+/// Two values with the same canonical form are considered equal by
+/// [`SettingsBundle::diff`].
+///
+/// # Arguments
+///
+/// * `value` - The JSON value
+///
+/// # Returns
+///
+/// Returns the canonical form of the value.
+///
+/// # Example
+///
+/// ```
+/// use enphase_api::models::canonical::canonicalize;
+/// use serde_json::json;
+///
+/// let value = json!({"reserved_soc": 20.0, "mode": "backup"});
+/// assert_eq!(canonicalize(&value), r#"{"mode":"backup","reserved_soc":20}"#);
+/// ```
+#[inline]
+#[must_use]
+pub fn canonicalize(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+/// Append the canonical form of a value to the output.
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Null | Value::Bool(_) | Value::String(_) => out.push_str(&value.to_string()),
+        Value::Number(number) => out.push_str(&canonical_number(number)),
+        Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_unstable_by_key(|&(key, _)| key);
+            out.push('{');
+            for (index, (key, item)) in entries.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// The canonical form of a number.
+///
+/// Integers are written as is. Floating point numbers are written in their
+/// shortest round-trip form without exponent, which drops the fractional part
+/// of integral values; negative zero is written as `0`.
+fn canonical_number(number: &Number) -> String {
+    match number.as_f64() {
+        Some(float) if number.is_f64() => {
+            if float == 0.0 {
+                "0".to_owned()
+            } else {
+                float.to_string()
+            }
+        }
+        _ => number.to_string(),
+    }
+}
+
+/// A change to a settings document between two snapshots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SettingsDrift {
+    /// The name of the settings document.
+    pub document: String,
+    /// The JSON pointer (RFC 6901) to the changed value within the document.
+    /// The pointer is empty when the whole document was added or removed.
+    pub pointer: String,
+    /// The value in the earlier snapshot, if it was present.
+    pub before: Option<Value>,
+    /// The value in the later snapshot, if it is present.
+    pub after: Option<Value>,
+}
+
+impl fmt::Display for SettingsDrift {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show =
+            |value: Option<&Value>| value.map_or_else(|| "(missing)".to_owned(), canonicalize);
+        write!(
+            f,
+            "{}{}: {} -> {}",
+            self.document,
+            self.pointer,
+            show(self.before.as_ref()),
+            show(self.after.as_ref())
+        )
+    }
+}
+
+/// A snapshot of named settings documents (e.g., the tariff).
+///
+/// The documents are kept as raw JSON, so that settings which are not
+/// modelled by this crate are compared too.
+///
+/// # Example
+///
+/// ```
+/// use enphase_api::models::canonical::SettingsBundle;
+/// use serde_json::json;
+///
+/// let mut backup = SettingsBundle::new();
+/// backup.insert("tariff", json!({"storage_settings": {"mode": "backup"}}));
+/// let mut current = SettingsBundle::new();
+/// current.insert("tariff", json!({"storage_settings": {"mode": "economy"}}));
+///
+/// let drift = backup.diff(&current);
+/// assert_eq!(
+///     drift[0].to_string(),
+///     r#"tariff/storage_settings/mode: "backup" -> "economy""#
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SettingsBundle {
+    /// The documents, by name.
+    documents: BTreeMap<String, Value>,
+}
+
+impl SettingsBundle {
+    /// Create an empty bundle.
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a document to the bundle, replacing any document with the same
+    /// name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the document
+    /// * `document` - The document
+    #[inline]
+    pub fn insert(&mut self, name: impl Into<String>, document: Value) {
+        self.documents.insert(name.into(), document);
+    }
+
+    /// Get a document from the bundle.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the document
+    ///
+    /// # Returns
+    ///
+    /// Returns the document, or `None` if the bundle has no such document.
+    #[inline]
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.documents.get(name)
+    }
+
+    /// Iterate over the documents of the bundle.
+    ///
+    /// # Returns
+    ///
+    /// Returns the names and documents, ordered by name.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.documents
+            .iter()
+            .map(|(name, document)| (name.as_str(), document))
+    }
+
+    /// Report the changes from this snapshot to another.
+    ///
+    /// Values are compared by their canonical form (see [`canonicalize`]),
+    /// so reordered keys and reformatted numbers are not reported. Objects
+    /// are compared key by key and arrays index by index, and each change is
+    /// reported at the deepest path where the values differ.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The later snapshot
+    ///
+    /// # Returns
+    ///
+    /// Returns the changes, ordered by document name and then by path.
+    #[inline]
+    #[must_use]
+    pub fn diff(&self, other: &Self) -> Vec<SettingsDrift> {
+        let mut names: Vec<&String> = self
+            .documents
+            .keys()
+            .chain(other.documents.keys())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let mut drift = Vec::new();
+        for name in names {
+            let mut recorder = |pointer: String, before: Option<&Value>, after: Option<&Value>| {
+                drift.push(SettingsDrift {
+                    document: name.clone(),
+                    pointer,
+                    before: before.cloned(),
+                    after: after.cloned(),
+                });
+            };
+            diff_values(
+                String::new(),
+                self.documents.get(name),
+                other.documents.get(name),
+                &mut recorder,
+            );
+        }
+        drift
+    }
+}
+
+/// Compare two (possibly missing) values at the given path, recording each
+/// change.
+fn diff_values<F>(pointer: String, before: Option<&Value>, after: Option<&Value>, record: &mut F)
+where
+    F: FnMut(String, Option<&Value>, Option<&Value>),
+{
+    match (before, after) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys {
+                diff_values(
+                    format!("{pointer}/{}", escape_pointer(key)),
+                    old.get(key),
+                    new.get(key),
+                    record,
+                );
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) => {
+            for index in 0..old.len().max(new.len()) {
+                diff_values(
+                    format!("{pointer}/{index}"),
+                    old.get(index),
+                    new.get(index),
+                    record,
+                );
+            }
+        }
+        (Some(old), Some(new)) if canonicalize(old) == canonicalize(new) => {}
+        (None, None) => {}
+        _ => record(pointer, before, after),
+    }
+}
+
+/// Escape a key for use in a JSON pointer.
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// Parse a JSON document.
+    fn parse(json: &str) -> Value {
+        serde_json::from_str(json).expect("Should parse")
+    }
+
+    /// Create a bundle from named JSON documents.
+    fn bundle(documents: &[(&str, &str)]) -> SettingsBundle {
+        let mut bundle = SettingsBundle::new();
+        for &(name, json) in documents {
+            bundle.insert(name, parse(json));
+        }
+        bundle
+    }
+
+    #[rstest]
+    #[case::integer("1", "1")]
+    #[case::integral_float("1.0", "1")]
+    #[case::exponent("1e2", "100")]
+    #[case::large_exponent("1.5e21", "1500000000000000000000")]
+    #[case::fraction("0.10", "0.1")]
+    #[case::negative_zero("-0.0", "0")]
+    #[case::negative("-2.50", "-2.5")]
+    #[case::big_integer("18446744073709551615", "18446744073709551615")]
+    #[case::unicode_escape(r#""\u00e9""#, "\"\u{e9}\"")]
+    #[case::surrogate_pair(r#""\ud83d\ude00""#, "\"\u{1f600}\"")]
+    #[case::required_escapes(r#""a\"b\\c\n\u0001""#, r#""a\"b\\c\n\u0001""#)]
+    #[case::whitespace("[ 1 ,\n 2 ]", "[1,2]")]
+    #[case::sorted_keys(r#"{"b": 1, "a": {"d": 2, "c": 3}}"#, r#"{"a":{"c":3,"d":2},"b":1}"#)]
+    #[case::empty(r#"{"a": [], "b": {}}"#, r#"{"a":[],"b":{}}"#)]
+    fn canonical_form(#[case] json: &str, #[case] expected: &str) {
+        assert_eq!(canonicalize(&parse(json)), expected);
+    }
+
+    #[test]
+    fn canonical_form_is_stable() {
+        let canonical = canonicalize(&parse(r#"{"z": [1.0, {"y": "\u00e9"}], "a": null}"#));
+
+        assert_eq!(canonicalize(&parse(&canonical)), canonical);
+    }
+
+    #[test]
+    fn diff_ignores_formatting() {
+        let before = bundle(&[(
+            "tariff",
+            r#"{"storage_settings": {"reserved_soc": 20, "mode": "backup"}}"#,
+        )]);
+        let after = bundle(&[(
+            "tariff",
+            r#"{"storage_settings":{"mode":"backup","reserved_soc":20.0}}"#,
+        )]);
+
+        assert_eq!(before.diff(&after), Vec::new());
+    }
+
+    #[test]
+    fn diff_report() {
+        let before = bundle(&[
+            (
+                "tariff",
+                r#"{
+                    "currency": {"code": "USD"},
+                    "storage_settings": {"mode": "backup", "reserved_soc": 100.0},
+                    "schedule": [1, 2, 3]
+                }"#,
+            ),
+            ("grid_profile", r#"{"name": "IEEE 1547"}"#),
+        ]);
+        let after = bundle(&[
+            (
+                "tariff",
+                r#"{
+                    "currency": {"code": "USD"},
+                    "storage_settings": {"mode": "economy", "reserved_soc": 30},
+                    "schedule": [1, 4],
+                    "single_rate": {"rate": 0.25}
+                }"#,
+            ),
+            ("dry_contacts", "[]"),
+        ]);
+
+        let report: Vec<String> = before
+            .diff(&after)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        insta::assert_snapshot!(report.join("\n"), @r#"
+        dry_contacts: (missing) -> []
+        grid_profile: {"name":"IEEE 1547"} -> (missing)
+        tariff/schedule/1: 2 -> 4
+        tariff/schedule/2: 3 -> (missing)
+        tariff/single_rate: (missing) -> {"rate":0.25}
+        tariff/storage_settings/mode: "backup" -> "economy"
+        tariff/storage_settings/reserved_soc: 100 -> 30
+        "#);
+    }
+
+    #[rstest]
+    #[case(r#"{"a/b": 1}"#, r#"{"a/b": 2}"#, "/a~1b")]
+    #[case(r#"{"a~b": 1}"#, r#"{"a~b": 2}"#, "/a~0b")]
+    #[case(r#"{"~/": 1}"#, r#"{"~/": 2}"#, "/~0~1")]
+    fn pointer_escaping(#[case] before: &str, #[case] after: &str, #[case] expected: &str) {
+        let drift = bundle(&[("doc", before)]).diff(&bundle(&[("doc", after)]));
+
+        let pointers: Vec<&str> = drift.iter().map(|change| change.pointer.as_str()).collect();
+        assert_eq!(pointers, vec![expected]);
+    }
+}
@@ -0,0 +1,501 @@
+//! # Ensemble models
+//!
+//! This module contains the models for the Ensemble devices (IQ Batteries,
+//! also known as Encharge, and IQ System Controllers, also known as Enpower)
+//! reported by the Envoy at `/ivp/ensemble/inventory` and
+//! `/ivp/ensemble/secctrl`.
+//!
+//! The inventory is reported as a list of sections, each tagged by the `type`
+//! of the devices it contains. The sections are merged into a single
+//! [`Inventory`], and sections for device types which are not (yet) supported
+//! are skipped.
+//!
+//! ## State of health
+//!
+//! Recent firmware reports the state of health of each IQ Battery. The value
+//! is not always meaningful: some firmware reports 255 when the state of
+//! health is unknown, or reports other values outside 0 to 100. Such values
+//! are kept as [`StateOfHealth::Implausible`] rather than being used in
+//! estimates.
+
+use serde::Deserialize;
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// The shortest age, in days, for which a degradation rate is estimated.
+///
+/// Over shorter periods, the rounding of the state of health dominates the
+/// estimate.
+const MIN_DEGRADATION_AGE_DAYS: i64 = 30;
+
+/// The state of a relay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum RelayState {
+    /// The relay is open (disconnected).
+    Open,
+    /// The relay is closed (connected).
+    Closed,
+    /// A state which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(RelayState {
+    Open => "open",
+    Closed => "closed",
+    Other => "other",
+});
+
+/// The Ensemble devices connected to the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[non_exhaustive]
+#[serde(from = "Vec<Section>")]
+pub struct Inventory {
+    /// The IQ Batteries.
+    pub encharge: Vec<Encharge>,
+    /// The IQ System Controllers.
+    pub enpower: Vec<Enpower>,
+}
+
+impl From<Vec<Section>> for Inventory {
+    #[inline]
+    fn from(sections: Vec<Section>) -> Self {
+        let mut inventory = Self::default();
+        for section in sections {
+            match section {
+                Section::Encharge { devices } => inventory.encharge.extend(devices),
+                Section::Enpower { devices } => inventory.enpower.extend(devices),
+                Section::Unknown => {}
+            }
+        }
+        inventory
+    }
+}
+
+/// A section of the inventory, holding the devices of a single type.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum Section {
+    /// IQ Batteries.
+    #[serde(rename = "ENCHARGE")]
+    Encharge {
+        /// The devices in the section.
+        #[serde(default)]
+        devices: Vec<Encharge>,
+    },
+    /// IQ System Controllers.
+    #[serde(rename = "ENPOWER")]
+    Enpower {
+        /// The devices in the section.
+        #[serde(default)]
+        devices: Vec<Enpower>,
+    },
+    /// A device type which is not (yet) supported.
+    #[serde(other)]
+    Unknown,
+}
+
+/// An IQ Battery (Encharge).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "The flags are independent and mirror the Envoy response"
+)]
+pub struct Encharge {
+    /// Serial number of the battery.
+    #[serde(rename = "serial_num")]
+    pub serial_number: String,
+    /// Part number of the battery.
+    #[serde(rename = "part_num")]
+    pub part_number: String,
+    /// Time of the last report, as a Unix timestamp.
+    #[serde(rename = "last_rpt_date")]
+    pub last_report_date: i64,
+    /// Administrative state of the battery (e.g., `ENCHG_STATE_READY`).
+    #[serde(rename = "admin_state_str", default)]
+    pub admin_state: String,
+    /// Status flags of the battery (e.g., `envoy.global.ok`).
+    #[serde(default)]
+    pub device_status: Vec<String>,
+    /// Whether the battery is operating.
+    #[serde(default)]
+    pub operating: bool,
+    /// Whether the battery is communicating with the Envoy.
+    #[serde(default)]
+    pub communicating: bool,
+    /// Whether the battery is allowed to sleep.
+    #[serde(default)]
+    pub sleep_enabled: bool,
+    /// Whether the DC switch of the battery is off.
+    #[serde(default)]
+    pub dc_switch_off: bool,
+    /// State of charge of the battery, in percent.
+    #[serde(rename = "percentFull")]
+    pub percent_full: u8,
+    /// Temperature of the battery, in degrees Celsius.
+    pub temperature: i32,
+    /// Temperature of the hottest cell, in degrees Celsius.
+    #[serde(rename = "maxCellTemp")]
+    pub max_cell_temperature: Option<i32>,
+    /// Capacity of the battery, in watt-hours.
+    #[serde(rename = "encharge_capacity")]
+    pub capacity: u32,
+    /// Time the battery was installed, as a Unix timestamp.
+    #[serde(default)]
+    pub installed: Option<i64>,
+    /// State of health of the battery, if reported by the firmware.
+    #[serde(default)]
+    pub soh: Option<StateOfHealth>,
+    /// Number of charge and discharge cycles, if reported by the firmware.
+    ///
+    /// This is the raw counter reported by the battery.
+    #[serde(default)]
+    pub cycle_count: Option<u32>,
+}
+
+impl Encharge {
+    /// The state of health of the battery, in percent.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the firmware does not report the state of health, or
+    /// reports an implausible value.
+    #[inline]
+    #[must_use]
+    pub fn soh_percent(&self) -> Option<u8> {
+        self.soh.as_ref().and_then(StateOfHealth::percent)
+    }
+
+    /// Estimate the degradation of the battery from its state of health and
+    /// age.
+    ///
+    /// The age is the time between the installation and the last report of
+    /// the battery. The degradation is assumed to be linear over that period.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the state of health is missing or implausible, if the
+    /// installation time is unknown, or if the battery has been installed for
+    /// less than 30 days.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::ensemble::Encharge;
+    ///
+    /// let json = r#"{
+    ///     "serial_num": "122249012345", "part_num": "830-01760-r37",
+    ///     "last_rpt_date": 1731600000, "installed": 1700064000,
+    ///     "percentFull": 58, "temperature": 24, "encharge_capacity": 3500,
+    ///     "soh": 96
+    /// }"#;
+    /// let battery: Encharge = serde_json::from_str(json)?;
+    ///
+    /// let estimate = battery.degradation_estimate().expect("SoH and age are known");
+    /// assert_eq!(estimate.age_days, 365);
+    /// assert_eq!(estimate.capacity, 3360);
+    /// # Ok::<(), serde_json::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    #[expect(
+        clippy::float_arithmetic,
+        reason = "Extrapolating the degradation rate requires floating-point arithmetic"
+    )]
+    pub fn degradation_estimate(&self) -> Option<Degradation> {
+        let soh_percent = self.soh_percent()?;
+        let age_days = self
+            .last_report_date
+            .checked_sub(self.installed?)?
+            .checked_div(SECONDS_PER_DAY)?;
+        if age_days < MIN_DEGRADATION_AGE_DAYS {
+            return None;
+        }
+
+        let loss = f64::from(100_u8.saturating_sub(soh_percent));
+        let age_years = f64::from(u32::try_from(age_days).ok()?) / 365.0_f64;
+        let capacity = u32::try_from(
+            u64::from(self.capacity)
+                .checked_mul(u64::from(soh_percent))?
+                .checked_div(100)?,
+        )
+        .ok()?;
+
+        Some(Degradation {
+            soh_percent,
+            age_days: u32::try_from(age_days).ok()?,
+            loss_per_year: loss / age_years,
+            capacity,
+        })
+    }
+}
+
+/// The state of health of an IQ Battery, as reported by the firmware.
+///
+/// Fractional values are rounded to the nearest percent.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(from = "serde_json::Number")]
+pub enum StateOfHealth {
+    /// A plausible state of health, in percent.
+    Percent(u8),
+    /// A value outside 0 to 100, as reported by the firmware.
+    Implausible(serde_json::Number),
+}
+
+impl StateOfHealth {
+    /// The state of health in percent, if plausible.
+    #[inline]
+    #[must_use]
+    pub const fn percent(&self) -> Option<u8> {
+        match *self {
+            Self::Percent(percent) => Some(percent),
+            Self::Implausible(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Number> for StateOfHealth {
+    #[inline]
+    fn from(number: serde_json::Number) -> Self {
+        let percent = number.as_u64().map_or_else(
+            || number.as_f64().and_then(round_percent),
+            |value| u8::try_from(value).ok(),
+        );
+        match percent {
+            Some(value) if value <= 100 => Self::Percent(value),
+            _ => Self::Implausible(number),
+        }
+    }
+}
+
+/// Round a fractional percentage, if it is within the range of `u8`.
+#[expect(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    reason = "The value is checked to be within the range of u8 before the cast"
+)]
+fn round_percent(value: f64) -> Option<u8> {
+    let rounded = value.round();
+    (0.0_f64..=f64::from(u8::MAX))
+        .contains(&rounded)
+        .then_some(rounded as u8)
+}
+
+/// An estimate of the degradation of an IQ Battery.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Degradation {
+    /// State of health of the battery, in percent.
+    pub soh_percent: u8,
+    /// Age of the battery at its last report, in days.
+    pub age_days: u32,
+    /// Average loss of state of health, in percentage points per year.
+    pub loss_per_year: f64,
+    /// Remaining capacity of the battery, in watt-hours.
+    pub capacity: u32,
+}
+
+/// An IQ System Controller (Enpower).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct Enpower {
+    /// Serial number of the system controller.
+    #[serde(rename = "serial_num")]
+    pub serial_number: String,
+    /// Part number of the system controller.
+    #[serde(rename = "part_num")]
+    pub part_number: String,
+    /// Time of the last report, as a Unix timestamp.
+    #[serde(rename = "last_rpt_date")]
+    pub last_report_date: i64,
+    /// Administrative state of the system controller (e.g.,
+    /// `ENPWR_STATE_OPER_CLOSED`).
+    #[serde(rename = "admin_state_str", default)]
+    pub admin_state: String,
+    /// Status flags of the system controller (e.g., `envoy.global.ok`).
+    #[serde(default)]
+    pub device_status: Vec<String>,
+    /// Whether the system controller is communicating with the Envoy.
+    #[serde(default)]
+    pub communicating: bool,
+    /// Temperature of the system controller, in degrees Fahrenheit.
+    pub temperature: i32,
+    /// Requested state of the mains relay.
+    pub mains_admin_state: RelayState,
+    /// Actual state of the mains relay.
+    pub mains_oper_state: RelayState,
+    /// Grid mode of the system controller (e.g., `multimode-ongrid`).
+    #[serde(rename = "Enpwr_grid_mode")]
+    pub grid_mode: String,
+    /// Grid mode of the batteries (e.g., `multimode-ongrid`).
+    #[serde(rename = "Enchg_grid_mode", default)]
+    pub encharge_grid_mode: String,
+}
+
+impl Enpower {
+    /// Whether the site is connected to the grid, based on the actual state of
+    /// the mains relay.
+    #[inline]
+    #[must_use]
+    pub fn is_on_grid(&self) -> bool {
+        self.mains_oper_state == RelayState::Closed
+    }
+}
+
+/// The aggregated battery status reported at `/ivp/ensemble/secctrl`.
+///
+/// Energies are in watt-hours and states of charge in percent. The `enc_*`
+/// fields only cover IQ Batteries, the `acb_*` fields only cover AC Batteries,
+/// and the `agg_*` fields cover both.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct Secctrl {
+    /// Whether the batteries are shut down.
+    #[serde(default)]
+    pub shutdown: bool,
+    /// Aggregate state of charge.
+    pub agg_soc: u8,
+    /// Aggregate available energy.
+    #[serde(default)]
+    pub agg_avail_energy: u32,
+    /// Aggregate energy reserved for backup.
+    #[serde(default)]
+    pub agg_backup_energy: u32,
+    /// Maximum energy of all batteries.
+    #[serde(rename = "Max_energy")]
+    pub max_energy: u32,
+    /// State of charge reserved for backup, as configured.
+    #[serde(default)]
+    pub configured_backup_soc: u8,
+    /// State of charge reserved for backup, as adjusted by the Envoy.
+    #[serde(default)]
+    pub adjusted_backup_soc: u8,
+    /// State of charge of the IQ Batteries.
+    #[serde(rename = "ENC_agg_soc", default)]
+    pub enc_agg_soc: u8,
+    /// State of health of the IQ Batteries.
+    #[serde(rename = "ENC_agg_soh", default)]
+    pub enc_agg_soh: u8,
+    /// Available energy of the IQ Batteries.
+    #[serde(rename = "ENC_agg_avail_energy", default)]
+    pub enc_agg_avail_energy: u32,
+    /// Energy of the IQ Batteries reserved for backup.
+    #[serde(rename = "ENC_agg_backup_energy", default)]
+    pub enc_agg_backup_energy: u32,
+    /// Commissioned capacity of the IQ Batteries.
+    #[serde(rename = "Enc_commissioned_capacity", default)]
+    pub enc_commissioned_capacity: u32,
+    /// Maximum available capacity of the IQ Batteries.
+    #[serde(rename = "Enc_max_available_capacity", default)]
+    pub enc_max_available_capacity: u32,
+    /// State of charge of the AC Batteries.
+    #[serde(rename = "ACB_agg_soc", default)]
+    pub acb_agg_soc: u8,
+    /// Energy of the AC Batteries.
+    #[serde(rename = "ACB_agg_energy", default)]
+    pub acb_agg_energy: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[test]
+    fn unknown_sections_are_skipped() {
+        let json = r#"[
+            {"type": "ENCHARGE", "devices": []},
+            {"type": "IQMETER", "devices": [{"serial_num": "1"}]}
+        ]"#;
+        let inventory: Inventory =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert_eq!(inventory, Inventory::default());
+    }
+
+    #[test]
+    fn empty_inventory() {
+        let inventory: Inventory =
+            serde_json::from_str("[]").expect("Should deserialize successfully");
+
+        assert_eq!(inventory, Inventory::default());
+    }
+
+    /// Parse a battery with the given state of health and installation time.
+    fn battery(soh: &str, installed: i64) -> Encharge {
+        let json = format!(
+            r#"{{"serial_num": "122249012345", "part_num": "830-01760-r37",
+                "last_rpt_date": 1731600000, "installed": {installed},
+                "percentFull": 58, "temperature": 24, "encharge_capacity": 3500,
+                "soh": {soh}}}"#
+        );
+        serde_json::from_str(&json).expect("Should deserialize successfully")
+    }
+
+    #[rstest]
+    #[case::whole("97", Some(97))]
+    #[case::full("100", Some(100))]
+    #[case::fractional("96.6", Some(97))]
+    #[case::unknown("255", None)]
+    #[case::above_full("101", None)]
+    #[case::fractional_above_full("100.6", None)]
+    #[case::negative("-1", None)]
+    #[case::large("65535", None)]
+    fn soh_plausibility(#[case] soh: &str, #[case] expected: Option<u8>) {
+        let encharge = battery(soh, 1_700_064_000);
+
+        assert_eq!(encharge.soh_percent(), expected);
+        if expected.is_none() {
+            assert!(
+                matches!(encharge.soh, Some(StateOfHealth::Implausible(_))),
+                "{soh} should be flagged as implausible, got {:?}",
+                encharge.soh
+            );
+            assert_eq!(encharge.degradation_estimate(), None);
+        }
+    }
+
+    #[test]
+    fn soh_missing() {
+        let json = r#"{"serial_num": "122249012345", "part_num": "830-01760-r37",
+            "last_rpt_date": 1731600000, "percentFull": 58, "temperature": 24,
+            "encharge_capacity": 3500}"#;
+        let encharge: Encharge = serde_json::from_str(json).expect("Should deserialize");
+
+        assert_eq!(encharge.soh, None);
+        assert_eq!(encharge.cycle_count, None);
+        assert_eq!(encharge.degradation_estimate(), None);
+    }
+
+    #[test]
+    fn degradation_estimate() {
+        // Installed two years before the last report.
+        let estimate = battery("94", 1_700_064_000 - 365 * SECONDS_PER_DAY)
+            .degradation_estimate()
+            .expect("Should estimate the degradation");
+
+        assert_eq!(estimate.soh_percent, 94);
+        assert_eq!(estimate.age_days, 730);
+        assert_eq!(estimate.capacity, 3290);
+        assert!(
+            (estimate.loss_per_year - 3.0_f64).abs() < 1e-9_f64,
+            "Expected a loss of 3 points per year, got {}",
+            estimate.loss_per_year
+        );
+    }
+
+    #[test]
+    fn degradation_estimate_too_young() {
+        let encharge = battery("99", 1_731_600_000 - 10 * SECONDS_PER_DAY);
+
+        assert_eq!(encharge.soh_percent(), Some(99));
+        assert_eq!(encharge.degradation_estimate(), None);
+    }
+}
@@ -0,0 +1,304 @@
+//! # Energy flow models
+//!
+//! This module combines the production data and the live data of the Envoy
+//! into the current flow of power through the site.
+//!
+//! Consumption is measured by the consumption CTs where they are installed.
+//! On sites without consumption CTs but with an IQ System Controller
+//! (Enpower), the loads are instead measured by the ensemble and reported in
+//! the live data. The two are not equally accurate (e.g., the ensemble does
+//! not see loads wired upstream of the System Controller), so the
+//! measurement is tagged with its [`ConsumptionSource`].
+
+use super::{
+    livedata::LiveData,
+    production::{EimMeasurement, Measurement, MeasurementType, ProductionResponse},
+};
+
+/// The meter consumption was measured by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConsumptionSource {
+    /// The consumption CTs of the Envoy.
+    Ct,
+    /// The load meter of the ensemble, as reported in the live data.
+    Ensemble,
+}
+
+string_enum!(ConsumptionSource {
+    Ct => "ct",
+    Ensemble => "ensemble",
+});
+
+/// The current consumption of the site.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub struct Consumption {
+    /// Active power consumed by the loads, in watts.
+    pub watts: f64,
+    /// The meter the consumption was measured by.
+    pub source: ConsumptionSource,
+}
+
+impl Consumption {
+    /// The consumption measured by the consumption CTs, if they are
+    /// installed.
+    ///
+    /// # Arguments
+    ///
+    /// * `production` - The production data of the Envoy
+    #[inline]
+    #[must_use]
+    pub fn from_cts(production: &ProductionResponse) -> Option<Self> {
+        active_eim(&production.consumption, MeasurementType::TotalConsumption).map(|meter| Self {
+            watts: meter.w_now,
+            source: ConsumptionSource::Ct,
+        })
+    }
+
+    /// The consumption measured by the ensemble, if the live data is being
+    /// streamed (otherwise its readings are not updated).
+    ///
+    /// # Arguments
+    ///
+    /// * `live_data` - The live data of the Envoy
+    #[inline]
+    #[must_use]
+    pub fn from_ensemble(live_data: &LiveData) -> Option<Self> {
+        live_data.is_streaming().then(|| Self {
+            watts: live_data.meters.load.watts(),
+            source: ConsumptionSource::Ensemble,
+        })
+    }
+}
+
+/// The current flow of power through the site, in watts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "EnergyFlow reads better than flow::Energy"
+)]
+pub struct EnergyFlow {
+    /// Active power produced by the PV array.
+    pub production_w: f64,
+    /// The consumption of the loads.
+    pub consumption: Consumption,
+    /// Active power imported from the grid (negative when exporting).
+    pub grid_w: f64,
+}
+
+impl EnergyFlow {
+    /// Combine the production data with the consumption.
+    ///
+    /// Production is measured by the production CT if it is installed, and
+    /// by the microinverters otherwise. The grid flow is measured by the
+    /// meter the consumption came from: the net consumption CT (or the
+    /// difference between consumption and production, without one), or the
+    /// grid meter of the ensemble.
+    ///
+    /// # Arguments
+    ///
+    /// * `production` - The production data of the Envoy
+    /// * `consumption` - The consumption, e.g. from [`Consumption::from_cts`]
+    /// * `live_data` - The live data of the Envoy, if the consumption came
+    ///   from the ensemble
+    ///
+    /// # Returns
+    ///
+    /// Returns the flow of power, or `None` if the consumption came from the
+    /// ensemble but no streaming live data was given.
+    #[inline]
+    #[must_use]
+    pub fn new(
+        production: &ProductionResponse,
+        consumption: Consumption,
+        live_data: Option<&LiveData>,
+    ) -> Option<Self> {
+        let production_w = production_watts(production);
+        let grid_w = match consumption.source {
+            ConsumptionSource::Ct => {
+                active_eim(&production.consumption, MeasurementType::NetConsumption).map_or_else(
+                    || difference(consumption.watts, production_w),
+                    |meter| meter.w_now,
+                )
+            }
+            ConsumptionSource::Ensemble => live_data
+                .filter(|live| live.is_streaming())?
+                .meters
+                .grid
+                .watts(),
+        };
+        Some(Self {
+            production_w,
+            consumption,
+            grid_w,
+        })
+    }
+}
+
+/// The active `eim` measurement of the given type, if any.
+fn active_eim(
+    measurements: &[Measurement],
+    measurement_type: MeasurementType,
+) -> Option<&EimMeasurement> {
+    measurements
+        .iter()
+        .find_map(|measurement| match *measurement {
+            Measurement::Eim(ref meter)
+                if meter.measurement_type == measurement_type && meter.active_count > 0 =>
+            {
+                Some(meter)
+            }
+            Measurement::Eim(_)
+            | Measurement::Inverters(_)
+            | Measurement::Acb(_)
+            | Measurement::Unknown => None,
+        })
+}
+
+/// The production, from the production CT or the microinverters.
+fn production_watts(production: &ProductionResponse) -> f64 {
+    active_eim(&production.production, MeasurementType::Production).map_or_else(
+        || {
+            production
+                .production
+                .iter()
+                .find_map(|measurement| match *measurement {
+                    Measurement::Inverters(ref inverters) => Some(inverters.w_now),
+                    Measurement::Eim(_) | Measurement::Acb(_) | Measurement::Unknown => None,
+                })
+                .unwrap_or_default()
+        },
+        |meter| meter.w_now,
+    )
+}
+
+/// The difference between two powers.
+#[expect(
+    clippy::float_arithmetic,
+    reason = "Power readings are inherently floating point"
+)]
+fn difference(minuend: f64, subtrahend: f64) -> f64 {
+    minuend - subtrahend
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// Production data with the given consumption measurements.
+    fn production(consumption: &str) -> ProductionResponse {
+        serde_json::from_str(&format!(
+            r#"{{
+                "production": [
+                    {{"type": "inverters", "activeCount": 10, "readingTime": 0, "wNow": 2000, "whLifetime": 0}}
+                ],
+                "consumption": [{consumption}]
+            }}"#
+        ))
+        .expect("Production data should parse")
+    }
+
+    /// Live data with the given stream state, load and grid powers.
+    fn live_data(stream: &str, load_mw: i32, grid_mw: i32) -> LiveData {
+        serde_json::from_str(&format!(
+            r#"{{
+                "connection": {{"sc_stream": "{stream}"}},
+                "meters": {{
+                    "last_update": 0,
+                    "pv": {{"agg_p_mw": 2000000}},
+                    "storage": {{"agg_p_mw": 0}},
+                    "grid": {{"agg_p_mw": {grid_mw}}},
+                    "load": {{"agg_p_mw": {load_mw}}}
+                }},
+                "tasks": {{"task_id": 0, "timestamp": 0}}
+            }}"#
+        ))
+        .expect("Live data should parse")
+    }
+
+    #[rstest]
+    #[case::total(
+        r#"{"type": "eim", "activeCount": 1, "measurementType": "total-consumption", "readingTime": 0, "wNow": 800, "whLifetime": 0}"#,
+        Some(800.0_f64)
+    )]
+    #[case::inactive(
+        r#"{"type": "eim", "activeCount": 0, "measurementType": "total-consumption", "readingTime": 0, "wNow": 0, "whLifetime": 0}"#,
+        None
+    )]
+    #[case::net_only(
+        r#"{"type": "eim", "activeCount": 1, "measurementType": "net-consumption", "readingTime": 0, "wNow": -1200, "whLifetime": 0}"#,
+        None
+    )]
+    #[case::absent("", None)]
+    fn consumption_from_cts(#[case] consumption: &str, #[case] watts: Option<f64>) {
+        let measured = Consumption::from_cts(&production(consumption));
+
+        assert_eq!(
+            measured,
+            watts.map(|expected| Consumption {
+                watts: expected,
+                source: ConsumptionSource::Ct,
+            })
+        );
+    }
+
+    #[rstest]
+    #[case::streaming("enabled", Some(450.0_f64))]
+    #[case::not_streaming("disabled", None)]
+    fn consumption_from_ensemble(#[case] stream: &str, #[case] watts: Option<f64>) {
+        let measured = Consumption::from_ensemble(&live_data(stream, 450_000, -1_550_000));
+
+        assert_eq!(
+            measured,
+            watts.map(|expected| Consumption {
+                watts: expected,
+                source: ConsumptionSource::Ensemble,
+            })
+        );
+    }
+
+    #[test]
+    fn flow_from_cts_without_net_meter() {
+        let data = production(
+            r#"{"type": "eim", "activeCount": 1, "measurementType": "total-consumption", "readingTime": 0, "wNow": 800, "whLifetime": 0}"#,
+        );
+        let consumption = Consumption::from_cts(&data).expect("CTs should measure consumption");
+        let flow = EnergyFlow::new(&data, consumption, None).expect("Flow should be known");
+
+        assert_eq!(
+            flow,
+            EnergyFlow {
+                production_w: 2000.0_f64,
+                consumption,
+                grid_w: -1200.0_f64,
+            }
+        );
+    }
+
+    #[test]
+    fn flow_from_ensemble() {
+        let data = production("");
+        let live = live_data("enabled", 450_000, -1_550_000);
+        let consumption =
+            Consumption::from_ensemble(&live).expect("Ensemble should measure consumption");
+
+        let flow = EnergyFlow::new(&data, consumption, Some(&live)).expect("Flow should be known");
+        assert_eq!(
+            flow,
+            EnergyFlow {
+                production_w: 2000.0_f64,
+                consumption,
+                grid_w: -1550.0_f64,
+            }
+        );
+        assert_eq!(
+            EnergyFlow::new(&data, consumption, None),
+            None,
+            "The grid flow of the ensemble needs the live data"
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! # Home models
+//!
+//! This module contains the model for the summary reported by the Envoy at
+//! `/home.json`, which backs the dashboard of its local web interface: the
+//! software build, the database usage, the network interfaces, the wireless
+//! radios and the connection to the IQ System Controller (Enpower).
+//!
+//! On sites with the Enphase Mobile Connect cellular modem, the network
+//! section also reports the status of the modem (see [`Cellular`]), including
+//! the data used in the current billing cycle.
+//!
+//! The sections reported depend on the firmware and on the installed devices,
+//! so every section is optional.
+
+use serde::{Deserialize, Deserializer};
+
+use super::ensemble::RelayState;
+
+/// The summary of the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Home {
+    /// Time the software was built, as a Unix timestamp.
+    #[serde(default)]
+    pub software_build_epoch: Option<i64>,
+    /// Whether the gateway is an IQ Combiner without a full Envoy.
+    #[serde(default)]
+    pub is_nonvoy: Option<bool>,
+    /// Size of the database, in megabytes.
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub db_size: Option<u64>,
+    /// How full the database is, as a percentage.
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub db_percent_full: Option<u8>,
+    /// The time zone of the Envoy (e.g., `Australia/Sydney`).
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// The network connectivity.
+    #[serde(default)]
+    pub network: Option<Network>,
+    /// The tariff (e.g., `single_rate`).
+    #[serde(default)]
+    pub tariff: Option<String>,
+    /// The status of the software updates (e.g., `satisfied`).
+    #[serde(default)]
+    pub update_status: Option<String>,
+    /// The wireless radios of the Envoy (e.g., Bluetooth, Zigbee).
+    #[serde(default)]
+    pub wireless_connection: Vec<WirelessConnection>,
+    /// The connection to the IQ System Controller, if one is installed.
+    #[serde(default)]
+    pub enpower: Option<EnpowerConnection>,
+}
+
+impl Home {
+    /// The network interface used to reach Enlighten.
+    ///
+    /// # Returns
+    ///
+    /// Returns the primary interface, or `None` if the Envoy does not report
+    /// one.
+    #[inline]
+    #[must_use]
+    pub fn primary_interface(&self) -> Option<&NetworkInterface> {
+        let network = self.network.as_ref()?;
+        let primary = network.primary_interface.as_deref()?;
+        network
+            .interfaces
+            .iter()
+            .find(|interface| interface.interface == primary)
+    }
+}
+
+/// The network connectivity of the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Network {
+    /// Whether the Envoy can reach Enlighten.
+    #[serde(default)]
+    pub web_comm: Option<bool>,
+    /// Whether the Envoy has ever reported to Enlighten.
+    #[serde(default)]
+    pub ever_reported_to_enlighten: Option<bool>,
+    /// Time of the last report to Enlighten, as a Unix timestamp.
+    #[serde(default)]
+    pub last_enlighten_report_time: Option<i64>,
+    /// Name of the interface used to reach Enlighten (e.g., `wlan0`).
+    #[serde(default)]
+    pub primary_interface: Option<String>,
+    /// The network interfaces.
+    #[serde(default)]
+    pub interfaces: Vec<NetworkInterface>,
+    /// The cellular modem, if one is installed.
+    #[serde(default)]
+    pub cellular: Option<Cellular>,
+}
+
+/// The status of the cellular modem (Mobile Connect) of the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Cellular {
+    /// Name of the interface of the modem (e.g., `ppp0`).
+    #[serde(default)]
+    pub interface: Option<String>,
+    /// Signal strength, in bars from 0 to [`Cellular::signal_strength_max`].
+    #[serde(default)]
+    pub signal_strength: Option<u8>,
+    /// Highest signal strength, in bars.
+    #[serde(default)]
+    pub signal_strength_max: Option<u8>,
+    /// Received signal strength, in dBm.
+    #[serde(default)]
+    pub rssi: Option<i16>,
+    /// Name of the network operator (e.g., `Telstra`).
+    #[serde(default)]
+    pub carrier: Option<String>,
+    /// Access point name used by the modem.
+    #[serde(default)]
+    pub apn: Option<String>,
+    /// Data used in the current billing cycle, in bytes, if reported.
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub bytes_used: Option<u64>,
+}
+
+impl Cellular {
+    /// How much of a data budget has been used in the current billing cycle.
+    ///
+    /// # Arguments
+    ///
+    /// * `cap_bytes` - The data budget of the billing cycle, in bytes
+    ///
+    /// # Returns
+    ///
+    /// Returns the percentage of the budget used (above 100 once the budget
+    /// is exceeded), or `None` if the data usage is not reported or the
+    /// budget is zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::home::Cellular;
+    ///
+    /// let cellular: Cellular = serde_json::from_str(r#"{"bytes_used": 250000000}"#)?;
+    /// assert_eq!(cellular.percent_of_cap(1_000_000_000), Some(25.0));
+    /// # Ok::<(), serde_json::Error>(())
+    /// ```
+    #[inline]
+    #[must_use]
+    #[expect(
+        clippy::as_conversions,
+        clippy::cast_precision_loss,
+        clippy::float_arithmetic,
+        reason = "Byte counts are far below the precision of f64 that matters for a percentage"
+    )]
+    pub fn percent_of_cap(&self, cap_bytes: u64) -> Option<f64> {
+        let used = self.bytes_used?;
+        (cap_bytes > 0).then(|| used as f64 / cap_bytes as f64 * 100.0_f64)
+    }
+}
+
+/// The kind of a network interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum InterfaceType {
+    /// A wired interface.
+    Ethernet,
+    /// A Wi-Fi interface.
+    Wifi,
+    /// A cellular modem.
+    Cellular,
+    /// A kind which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(InterfaceType {
+    Ethernet => "ethernet",
+    Wifi => "wifi",
+    Cellular => "cellular",
+    Other => "other",
+});
+
+/// A network interface of the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct NetworkInterface {
+    /// The kind of interface.
+    #[serde(rename = "type")]
+    pub interface_type: InterfaceType,
+    /// Name of the interface (e.g., `eth0`).
+    pub interface: String,
+    /// MAC address of the interface.
+    #[serde(default)]
+    pub mac: Option<String>,
+    /// Whether the address is assigned by DHCP.
+    #[serde(default)]
+    pub dhcp: Option<bool>,
+    /// IP address of the interface.
+    #[serde(default)]
+    pub ip: Option<String>,
+    /// Whether the interface has a link.
+    #[serde(default)]
+    pub carrier: Option<bool>,
+    /// Signal strength, from 0 to [`NetworkInterface::signal_strength_max`].
+    /// Only meaningful for wireless interfaces.
+    #[serde(default)]
+    pub signal_strength: Option<u8>,
+    /// Highest signal strength.
+    #[serde(default)]
+    pub signal_strength_max: Option<u8>,
+    /// Status of the connection (e.g., `connected`), for Wi-Fi interfaces.
+    #[serde(default)]
+    pub status: Option<String>,
+}
+
+impl NetworkInterface {
+    /// The signal strength of a wireless interface.
+    ///
+    /// # Returns
+    ///
+    /// Returns the signal strength and the highest signal strength, or `None`
+    /// for wired interfaces and interfaces which do not report it.
+    #[inline]
+    #[must_use]
+    pub fn wireless_signal(&self) -> Option<(u8, u8)> {
+        match self.interface_type {
+            InterfaceType::Wifi | InterfaceType::Cellular => {
+                Some((self.signal_strength?, self.signal_strength_max?))
+            }
+            InterfaceType::Ethernet | InterfaceType::Other => None,
+        }
+    }
+}
+
+/// A wireless radio of the Envoy, used to communicate with the devices.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct WirelessConnection {
+    /// The kind of radio (e.g., `BLE`, `zigbee`, `subghz`).
+    #[serde(rename = "type")]
+    pub connection_type: String,
+    /// Whether the radio is connected.
+    #[serde(default)]
+    pub connected: bool,
+    /// Signal strength, from 0 to
+    /// [`WirelessConnection::signal_strength_max`].
+    #[serde(default)]
+    pub signal_strength: Option<u8>,
+    /// Highest signal strength.
+    #[serde(default)]
+    pub signal_strength_max: Option<u8>,
+}
+
+/// The connection of the Envoy to the IQ System Controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct EnpowerConnection {
+    /// Whether the IQ System Controller is connected.
+    #[serde(default)]
+    pub connected: bool,
+    /// State of the grid relay of the IQ System Controller.
+    #[serde(default)]
+    pub grid_status: Option<RelayState>,
+}
+
+/// Deserialize a number given either as a number or as a string.
+fn number_or_string<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + core::str::FromStr<Err: core::fmt::Display>,
+{
+    /// The forms the number is found in.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum NumberOrString<T> {
+        /// A number.
+        Number(T),
+        /// A quoted number.
+        Text(String),
+    }
+
+    match Option::<NumberOrString<T>>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(NumberOrString::Number(number)) => Ok(Some(number)),
+        Some(NumberOrString::Text(text)) => text
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(format!("Invalid number {text:?}: {e}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[test]
+    fn deserialize_empty() {
+        let home: Home = serde_json::from_str("{}").expect("Should deserialize");
+
+        assert_eq!(home, Home::default());
+        assert_eq!(home.primary_interface(), None);
+    }
+
+    #[rstest]
+    #[case::numbers(r#"{"db_size": 58, "db_percent_full": 3}"#)]
+    #[case::strings(r#"{"db_size": "58", "db_percent_full": " 3 "}"#)]
+    fn database_usage(#[case] json: &str) {
+        let home: Home = serde_json::from_str(json).expect("Should deserialize");
+
+        assert_eq!(home.db_size, Some(58));
+        assert_eq!(home.db_percent_full, Some(3));
+    }
+
+    #[test]
+    fn invalid_database_usage() {
+        let result = serde_json::from_str::<Home>(r#"{"db_percent_full": "full"}"#);
+
+        assert!(result.is_err(), "Invalid number should be rejected");
+    }
+
+    #[rstest]
+    #[case::wifi("wifi", Some((4, 5)))]
+    #[case::cellular("cellular", Some((4, 5)))]
+    #[case::ethernet("ethernet", None)]
+    #[case::unknown("usb", None)]
+    fn wireless_signal(#[case] interface_type: &str, #[case] expected: Option<(u8, u8)>) {
+        let json = format!(
+            r#"{{"type": "{interface_type}", "interface": "if0", "signal_strength": 4, "signal_strength_max": 5}}"#
+        );
+        let interface: NetworkInterface = serde_json::from_str(&json).expect("Should deserialize");
+
+        assert_eq!(interface.wireless_signal(), expected);
+    }
+
+    #[rstest]
+    #[case::quarter(Some(250_000_000), 1_000_000_000, Some(25.0_f64))]
+    #[case::exceeded(Some(1_500_000_000), 1_000_000_000, Some(150.0_f64))]
+    #[case::no_cap(Some(250_000_000), 0, None)]
+    #[case::not_reported(None, 1_000_000_000, None)]
+    fn percent_of_cap(
+        #[case] bytes_used: Option<u64>,
+        #[case] cap_bytes: u64,
+        #[case] expected: Option<f64>,
+    ) {
+        let cellular = Cellular {
+            bytes_used,
+            ..Cellular::default()
+        };
+
+        assert_eq!(cellular.percent_of_cap(cap_bytes), expected);
+    }
+
+    #[test]
+    fn cellular_bytes_used_as_string() {
+        let network: Network =
+            serde_json::from_str(r#"{"cellular": {"carrier": "Telstra", "bytes_used": "1024"}}"#)
+                .expect("Should deserialize");
+        let cellular = network.cellular.expect("The modem should be reported");
+
+        assert_eq!(cellular.carrier.as_deref(), Some("Telstra"));
+        assert_eq!(cellular.bytes_used, Some(1024));
+    }
+
+    #[test]
+    fn wireless_signal_not_reported() {
+        let interface: NetworkInterface =
+            serde_json::from_str(r#"{"type": "wifi", "interface": "wlan0"}"#)
+                .expect("Should deserialize");
+
+        assert_eq!(interface.wireless_signal(), None);
+    }
+}
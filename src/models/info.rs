@@ -0,0 +1,207 @@
+//! # Device information models
+//!
+//! This module contains the model for the device information reported by the
+//! Envoy at `/info` (or `/info.xml` on older firmware).
+//!
+//! The information is only available as XML. Only a handful of simple,
+//! unnested elements are of interest, so the document is scanned for those
+//! elements directly rather than being parsed in full.
+
+use crate::error::{EnphaseError, Result};
+
+/// Device information of the Envoy gateway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "EnvoyInfo reads better than models::info::Envoy, which clashes with the client"
+)]
+pub struct EnvoyInfo {
+    /// Serial number of the Envoy.
+    pub serial_number: String,
+    /// Part number of the Envoy (e.g., `800-00555-r03`).
+    pub part_number: String,
+    /// Version of the software running on the Envoy (e.g., `D7.6.175`).
+    pub software_version: String,
+    /// Time the software was built, as a Unix timestamp.
+    ///
+    /// This is only reported by newer firmware.
+    pub build_epoch: Option<i64>,
+}
+
+impl EnvoyInfo {
+    /// Parse the device information from the XML document served by the
+    /// Envoy.
+    ///
+    /// # Arguments
+    ///
+    /// * `xml` - The XML document returned by `/info` or `/info.xml`
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed [`EnvoyInfo`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::InvalidResponse`] if the document does not
+    /// contain the device serial number, part number and software version, or
+    /// if the build time is not a valid timestamp.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::info::EnvoyInfo;
+    ///
+    /// let xml = "<envoy_info><device><sn>122133012345</sn><pn>800-00555-r03</pn>\
+    ///            <software>D7.6.175</software></device></envoy_info>";
+    /// let info = EnvoyInfo::from_xml(xml)?;
+    /// assert_eq!(info.serial_number, "122133012345");
+    /// # Ok::<(), enphase_api::EnphaseError>(())
+    /// ```
+    #[inline]
+    pub fn from_xml(xml: &str) -> Result<Self> {
+        // Other sections (such as the software packages) also contain part
+        // numbers, so the device fields are only looked up within `<device>`.
+        let device = element(xml, "device")
+            .ok_or_else(|| missing("device"))?
+            .trim();
+
+        let build_epoch = element(xml, "build_info")
+            .and_then(|build_info| element(build_info, "build_time_gmt"))
+            .map(|value| {
+                value.trim().parse().map_err(|e| {
+                    EnphaseError::InvalidResponse(format!(
+                        "Invalid build time {value:?} in Envoy info: {e}"
+                    ))
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            serial_number: text(device, "sn")?,
+            part_number: text(device, "pn")?,
+            software_version: text(device, "software")?,
+            build_epoch,
+        })
+    }
+}
+
+/// The content of the first `<name>` element in the document, if any.
+///
+/// Elements with attributes are not matched, which is sufficient for the
+/// elements read from the Envoy info document.
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{name}>");
+    let close = format!("</{name}>");
+    let start = xml.find(&open)?.checked_add(open.len())?;
+    let rest = xml.get(start..)?;
+    let end = rest.find(&close)?;
+    rest.get(..end)
+}
+
+/// The trimmed and unescaped text of a required element.
+fn text(xml: &str, name: &str) -> Result<String> {
+    let value = element(xml, name).ok_or_else(|| missing(name))?.trim();
+    if value.is_empty() {
+        return Err(missing(name));
+    }
+    Ok(unescape(value))
+}
+
+/// Replace the predefined XML entities with the characters they represent.
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The error for a missing element.
+fn missing(name: &str) -> EnphaseError {
+    EnphaseError::InvalidResponse(format!("Missing <{name}> in Envoy info"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Info document served at `/info.xml` by firmware 5.
+    const LEGACY_INFO: &str = "<?xml version='1.0' encoding='UTF-8'?>
+<envoy_info>
+  <time>1704067200</time>
+  <device>
+    <sn>121703012345</sn>
+    <pn>800-00554-r03</pn>
+    <software>R4.10.35</software>
+    <euaid>4c8675</euaid>
+    <seqnum>0</seqnum>
+    <apiver>1</apiver>
+    <imeter>false</imeter>
+  </device>
+  <package pname=\"app\">
+    <pn>500-00001-r01</pn>
+    <version>02.00.00</version>
+    <build>2366</build>
+  </package>
+</envoy_info>
+";
+
+    #[test]
+    fn parse_legacy() {
+        let info = EnvoyInfo::from_xml(LEGACY_INFO).expect("Should parse successfully");
+
+        assert_eq!(
+            info,
+            EnvoyInfo {
+                serial_number: "121703012345".to_owned(),
+                part_number: "800-00554-r03".to_owned(),
+                software_version: "R4.10.35".to_owned(),
+                build_epoch: None,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_missing_serial() {
+        let xml = "<envoy_info><device><pn>800-00555-r03</pn>\
+                   <software>D7.6.175</software></device></envoy_info>";
+        let result = EnvoyInfo::from_xml(xml);
+
+        let Err(EnphaseError::InvalidResponse(message)) = result else {
+            panic!("Expected InvalidResponse, got {result:?}");
+        };
+        assert_eq!(message, "Missing <sn> in Envoy info");
+    }
+
+    #[test]
+    fn parse_invalid_build_time() {
+        let xml = "<envoy_info><device><sn>122133012345</sn><pn>800-00555-r03</pn>\
+                   <software>D7.6.175</software></device>\
+                   <build_info><build_time_gmt>yesterday</build_time_gmt></build_info>\
+                   </envoy_info>";
+        let result = EnvoyInfo::from_xml(xml);
+
+        assert!(
+            matches!(result, Err(EnphaseError::InvalidResponse(_))),
+            "Invalid build time should be rejected, got {result:?}"
+        );
+    }
+
+    #[test]
+    fn parse_html() {
+        let result = EnvoyInfo::from_xml("<html><body>Not found</body></html>");
+
+        assert!(
+            matches!(result, Err(EnphaseError::InvalidResponse(_))),
+            "A document without device information should be rejected, got {result:?}"
+        );
+    }
+
+    #[test]
+    fn unescape_entities() {
+        assert_eq!(unescape("a &amp;lt; b &lt; c"), "a &lt; b < c");
+    }
+}
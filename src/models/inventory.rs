@@ -0,0 +1,878 @@
+//! # Device inventory models
+//!
+//! This module contains the models for the devices attached to the Envoy, as
+//! reported at `/inventory.json`: microinverters (PCU), AC Batteries (ACB) and
+//! network system relays (NSRB, also known as Q-Relays). IQ Batteries and IQ
+//! System Controllers are reported separately, see [`super::ensemble`].
+//!
+//! As in the Ensemble inventory, the devices are grouped in sections tagged by
+//! their `type`, which are merged into a single [`Inventory`], and sections
+//! for device types which are not (yet) supported are skipped. Timestamps are
+//! reported as strings by this endpoint, and are parsed to numbers.
+//!
+//! For install verification, [`Inventory::device_counts`] summarizes the
+//! devices of each [`DeviceClass`], and [`DeviceCounts::expect`] compares the
+//! summary with the expected bill of materials. [`DeviceMap`] maps the serial
+//! numbers of the devices to the EIDs used by some endpoints.
+
+use core::fmt;
+
+use serde::{Deserialize, Deserializer};
+
+use super::ensemble::RelayState;
+
+/// The devices attached to the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[non_exhaustive]
+#[serde(from = "Vec<Section>")]
+pub struct Inventory {
+    /// The microinverters.
+    pub pcu: Vec<Device>,
+    /// The AC Batteries.
+    pub acb: Vec<Device>,
+    /// The network system relays.
+    pub nsrb: Vec<Device>,
+}
+
+impl Inventory {
+    /// Iterate over the devices of every class.
+    #[inline]
+    pub fn devices(&self) -> impl Iterator<Item = (DeviceClass, &Device)> {
+        [
+            (DeviceClass::Pcu, &self.pcu),
+            (DeviceClass::Acb, &self.acb),
+            (DeviceClass::Nsrb, &self.nsrb),
+        ]
+        .into_iter()
+        .flat_map(|(class, devices)| devices.iter().map(move |device| (class, device)))
+    }
+
+    /// Count the devices of each class.
+    ///
+    /// Deleted devices are not counted. A serial number listed more than once
+    /// within a class (e.g., after a device was swapped) is counted once, using
+    /// its first entry, and recorded in [`DeviceCounts::duplicates`].
+    ///
+    /// # Returns
+    ///
+    /// Returns the [`DeviceCounts`] of the inventory.
+    #[inline]
+    #[must_use]
+    pub fn device_counts(&self) -> DeviceCounts {
+        let mut counts = DeviceCounts::default();
+        let mut seen: Vec<(DeviceClass, &str)> = Vec::new();
+        for (class, device) in self.devices().filter(|&(_, device)| !device.deleted) {
+            let key = (class, device.serial_number.as_str());
+            if seen.contains(&key) {
+                counts
+                    .duplicates
+                    .push((class, device.serial_number.clone()));
+                continue;
+            }
+            seen.push(key);
+            counts.class_mut(class).add(device);
+        }
+        counts
+    }
+}
+
+impl From<Vec<Section>> for Inventory {
+    #[inline]
+    fn from(sections: Vec<Section>) -> Self {
+        let mut inventory = Self::default();
+        for section in sections {
+            match section {
+                Section::Pcu { devices } => inventory.pcu.extend(devices),
+                Section::Acb { devices } => inventory.acb.extend(devices),
+                Section::Nsrb { devices } => inventory.nsrb.extend(devices),
+                Section::Unknown => {}
+            }
+        }
+        inventory
+    }
+}
+
+/// A section of the inventory, holding the devices of a single type.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+enum Section {
+    /// Microinverters.
+    #[serde(rename = "PCU")]
+    Pcu {
+        /// The devices in the section.
+        #[serde(default)]
+        devices: Vec<Device>,
+    },
+    /// AC Batteries.
+    #[serde(rename = "ACB")]
+    Acb {
+        /// The devices in the section.
+        #[serde(default)]
+        devices: Vec<Device>,
+    },
+    /// Network system relays.
+    #[serde(rename = "NSRB")]
+    Nsrb {
+        /// The devices in the section.
+        #[serde(default)]
+        devices: Vec<Device>,
+    },
+    /// A device type which is not (yet) supported.
+    #[serde(other)]
+    Unknown,
+}
+
+/// A device attached to the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "The flags are independent and mirror the Envoy response"
+)]
+pub struct Device {
+    /// Serial number of the device.
+    #[serde(rename = "serial_num")]
+    pub serial_number: String,
+    /// Part number of the device.
+    #[serde(rename = "part_num")]
+    pub part_number: String,
+    /// Time the device was installed, as a Unix timestamp.
+    #[serde(default, deserialize_with = "timestamp")]
+    pub installed: Option<i64>,
+    /// Time of the last report, as a Unix timestamp.
+    #[serde(rename = "last_rpt_date", default, deserialize_with = "timestamp")]
+    pub last_report_date: Option<i64>,
+    /// Status codes of the device.
+    #[serde(default)]
+    pub device_status: Vec<DeviceStatus>,
+    /// Whether the device is producing power.
+    #[serde(default)]
+    pub producing: bool,
+    /// Whether the device is communicating with the Envoy.
+    #[serde(default)]
+    pub communicating: bool,
+    /// Whether the device is operating, i.e. not turned off through its power
+    /// mode. Only reported by some firmware.
+    #[serde(default)]
+    pub operating: Option<bool>,
+    /// State of the relay, for network system relays.
+    #[serde(default)]
+    pub relay: Option<RelayState>,
+    /// EID of the device (reported as `chaneid`), by which some endpoints
+    /// identify the device instead of its serial number.
+    #[serde(rename = "chaneid", default)]
+    pub eid: Option<u64>,
+    /// Whether the device has been provisioned on the Envoy.
+    #[serde(default)]
+    pub provisioned: bool,
+    /// Whether the device has been deleted from the Envoy (e.g., after being
+    /// replaced). Deleted devices are only listed by some firmware.
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+impl Device {
+    /// Whether the device reports no problem.
+    ///
+    /// This is the case if every status code is [`DeviceStatus::Ok`] or
+    /// [`DeviceStatus::ProvisioningDone`].
+    #[inline]
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.device_status
+            .iter()
+            .all(|status| matches!(status, DeviceStatus::Ok | DeviceStatus::ProvisioningDone))
+    }
+}
+
+/// The class of a device in the [`Inventory`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[non_exhaustive]
+pub enum DeviceClass {
+    /// Microinverters.
+    Pcu,
+    /// AC Batteries.
+    Acb,
+    /// Network system relays.
+    Nsrb,
+}
+
+string_enum!(DeviceClass {
+    Pcu => "pcu",
+    Acb => "acb",
+    Nsrb => "nsrb",
+});
+
+/// The number of devices of each class in the [`Inventory`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct DeviceCounts {
+    /// The microinverters.
+    pub pcu: ClassCounts,
+    /// The AC Batteries.
+    pub acb: ClassCounts,
+    /// The network system relays.
+    pub nsrb: ClassCounts,
+    /// The serial numbers listed more than once, along with their class.
+    pub duplicates: Vec<(DeviceClass, String)>,
+}
+
+impl DeviceCounts {
+    /// The counts of the given class.
+    #[inline]
+    #[must_use]
+    pub const fn get(&self, class: DeviceClass) -> &ClassCounts {
+        match class {
+            DeviceClass::Pcu => &self.pcu,
+            DeviceClass::Acb => &self.acb,
+            DeviceClass::Nsrb => &self.nsrb,
+        }
+    }
+
+    /// The mutable counts of the given class.
+    const fn class_mut(&mut self, class: DeviceClass) -> &mut ClassCounts {
+        match class {
+            DeviceClass::Pcu => &mut self.pcu,
+            DeviceClass::Acb => &mut self.acb,
+            DeviceClass::Nsrb => &mut self.nsrb,
+        }
+    }
+
+    /// Compare the counts with the expected number of devices.
+    ///
+    /// For each class with an expected count, a [`Discrepancy::Missing`] or
+    /// [`Discrepancy::Unexpected`] is reported if a different number of
+    /// devices was detected. For every class, detected devices which are not
+    /// provisioned or not communicating are reported, as are duplicated serial
+    /// numbers. Devices which are not producing are not reported, as this is
+    /// expected at night.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The expected number of devices of each class
+    ///
+    /// # Returns
+    ///
+    /// Returns the discrepancies, grouped by class; empty if the installation
+    /// matches the expectation.
+    #[inline]
+    #[must_use]
+    pub fn expect(&self, expected: ExpectedCounts) -> Vec<Discrepancy> {
+        let mut discrepancies = Vec::new();
+        for class in [DeviceClass::Pcu, DeviceClass::Acb, DeviceClass::Nsrb] {
+            let counts = self.get(class);
+            match expected.get(class) {
+                Some(count) if counts.detected < count => {
+                    discrepancies.push(Discrepancy::Missing {
+                        class,
+                        expected: count,
+                        detected: counts.detected,
+                    });
+                }
+                Some(count) if counts.detected > count => {
+                    discrepancies.push(Discrepancy::Unexpected {
+                        class,
+                        expected: count,
+                        detected: counts.detected,
+                    });
+                }
+                Some(_) | None => {}
+            }
+
+            let unprovisioned = counts.detected.saturating_sub(counts.provisioned);
+            if unprovisioned > 0 {
+                discrepancies.push(Discrepancy::NotProvisioned {
+                    class,
+                    count: unprovisioned,
+                });
+            }
+            let silent = counts.detected.saturating_sub(counts.communicating);
+            if silent > 0 {
+                discrepancies.push(Discrepancy::NotCommunicating {
+                    class,
+                    count: silent,
+                });
+            }
+            discrepancies.extend(
+                self.duplicates
+                    .iter()
+                    .filter(|&&(duplicate_class, _)| duplicate_class == class)
+                    .map(|(_, serial_number)| Discrepancy::Duplicate {
+                        class,
+                        serial_number: serial_number.clone(),
+                    }),
+            );
+        }
+        discrepancies
+    }
+}
+
+/// The number of devices of a single class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct ClassCounts {
+    /// The devices listed by the Envoy.
+    pub detected: usize,
+    /// The devices which are provisioned.
+    pub provisioned: usize,
+    /// The devices which are communicating with the Envoy.
+    pub communicating: usize,
+    /// The devices which are producing power.
+    pub producing: usize,
+}
+
+impl ClassCounts {
+    /// Count the given device.
+    const fn add(&mut self, device: &Device) {
+        self.detected = self.detected.saturating_add(1);
+        if device.provisioned {
+            self.provisioned = self.provisioned.saturating_add(1);
+        }
+        if device.communicating {
+            self.communicating = self.communicating.saturating_add(1);
+        }
+        if device.producing {
+            self.producing = self.producing.saturating_add(1);
+        }
+    }
+}
+
+/// The expected number of devices of each class (e.g., from the design of the
+/// installation), for [`DeviceCounts::expect`].
+///
+/// # Example
+///
+/// ```
+/// use enphase_api::models::inventory::{DeviceClass, ExpectedCounts};
+///
+/// let expected = ExpectedCounts::new()
+///     .with(DeviceClass::Pcu, 24)
+///     .with(DeviceClass::Nsrb, 1);
+/// assert_eq!(expected.get(DeviceClass::Pcu), Some(24));
+/// assert_eq!(expected.get(DeviceClass::Acb), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[must_use]
+pub struct ExpectedCounts {
+    /// The expected number of microinverters, if any is expected.
+    pcu: Option<usize>,
+    /// The expected number of AC Batteries, if any is expected.
+    acb: Option<usize>,
+    /// The expected number of network system relays, if any is expected.
+    nsrb: Option<usize>,
+}
+
+impl ExpectedCounts {
+    /// Create an expectation with no expected count for any class.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            pcu: None,
+            acb: None,
+            nsrb: None,
+        }
+    }
+
+    /// Set the expected number of devices of a class.
+    ///
+    /// # Arguments
+    ///
+    /// * `class` - The class of the devices
+    /// * `count` - The expected number of devices
+    #[inline]
+    pub const fn with(mut self, class: DeviceClass, count: usize) -> Self {
+        match class {
+            DeviceClass::Pcu => self.pcu = Some(count),
+            DeviceClass::Acb => self.acb = Some(count),
+            DeviceClass::Nsrb => self.nsrb = Some(count),
+        }
+        self
+    }
+
+    /// The expected number of devices of a class, if set.
+    #[inline]
+    #[must_use]
+    pub const fn get(&self, class: DeviceClass) -> Option<usize> {
+        match class {
+            DeviceClass::Pcu => self.pcu,
+            DeviceClass::Acb => self.acb,
+            DeviceClass::Nsrb => self.nsrb,
+        }
+    }
+}
+
+/// A difference between the inventory and the expected installation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Discrepancy {
+    /// Fewer devices were detected than expected.
+    Missing {
+        /// The class of the devices.
+        class: DeviceClass,
+        /// The expected number of devices.
+        expected: usize,
+        /// The number of devices detected.
+        detected: usize,
+    },
+    /// More devices were detected than expected.
+    Unexpected {
+        /// The class of the devices.
+        class: DeviceClass,
+        /// The expected number of devices.
+        expected: usize,
+        /// The number of devices detected.
+        detected: usize,
+    },
+    /// Some detected devices are not provisioned.
+    NotProvisioned {
+        /// The class of the devices.
+        class: DeviceClass,
+        /// The number of devices which are not provisioned.
+        count: usize,
+    },
+    /// Some detected devices are not communicating with the Envoy.
+    NotCommunicating {
+        /// The class of the devices.
+        class: DeviceClass,
+        /// The number of devices which are not communicating.
+        count: usize,
+    },
+    /// A serial number is listed more than once.
+    Duplicate {
+        /// The class of the device.
+        class: DeviceClass,
+        /// The duplicated serial number.
+        serial_number: String,
+    },
+}
+
+/// The mapping between the serial numbers, EIDs and classes of the devices in
+/// the [`Inventory`].
+///
+/// When a device is replaced, its replacement may reuse its EID. Both devices
+/// are kept; the replaced device is marked as superseded (see
+/// [`DeviceMapEntry::superseded_by`]), and lookups by EID return the
+/// replacement.
+///
+/// # Example
+///
+/// ```
+/// use enphase_api::models::inventory::{DeviceMap, Inventory};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let inventory: Inventory = serde_json::from_str(
+///     r#"[{"type": "PCU", "devices": [
+///         {"serial_num": "482243012345", "part_num": "800-01391-r02", "chaneid": 1627390225}
+///     ]}]"#,
+/// )?;
+/// let map = DeviceMap::from_inventory(&inventory);
+/// assert_eq!(map.eid("482243012345"), Some(1_627_390_225));
+/// assert_eq!(map.serial_number(1_627_390_225), Some("482243012345"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub struct DeviceMap {
+    /// The devices with an EID, in inventory order.
+    entries: Vec<DeviceMapEntry>,
+}
+
+/// A device in a [`DeviceMap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DeviceMapEntry {
+    /// Serial number of the device.
+    pub serial_number: String,
+    /// EID of the device.
+    pub eid: u64,
+    /// Class of the device.
+    pub class: DeviceClass,
+    /// Serial number of the device which replaced this one with the same EID,
+    /// if any.
+    pub superseded_by: Option<String>,
+}
+
+impl DeviceMap {
+    /// Build the mapping from an inventory.
+    ///
+    /// Devices without an EID are skipped, and a serial number listed more
+    /// than once is kept once. Among the devices sharing an EID, the device
+    /// which is not deleted and was installed last is current, and the others
+    /// are superseded by it.
+    ///
+    /// # Arguments
+    ///
+    /// * `inventory` - The inventory to map
+    ///
+    /// # Returns
+    ///
+    /// Returns the [`DeviceMap`] of the inventory.
+    #[inline]
+    #[must_use]
+    pub fn from_inventory(inventory: &Inventory) -> Self {
+        let mut devices: Vec<(DeviceClass, &Device, u64)> = Vec::new();
+        for (class, device) in inventory.devices() {
+            if let Some(eid) = device.eid
+                && !devices
+                    .iter()
+                    .any(|&(_, known, _)| known.serial_number == device.serial_number)
+            {
+                devices.push((class, device, eid));
+            }
+        }
+
+        let entries = devices
+            .iter()
+            .map(|&(class, device, eid)| {
+                let current = devices
+                    .iter()
+                    .filter(|&&(_, _, other)| other == eid)
+                    .max_by_key(|&&(_, other, _)| (!other.deleted, other.installed))
+                    .map(|&(_, other, _)| other);
+                DeviceMapEntry {
+                    serial_number: device.serial_number.clone(),
+                    eid,
+                    class,
+                    superseded_by: current
+                        .filter(|other| other.serial_number != device.serial_number)
+                        .map(|other| other.serial_number.clone()),
+                }
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// The devices in the mapping, including superseded devices.
+    #[inline]
+    #[must_use]
+    pub fn entries(&self) -> &[DeviceMapEntry] {
+        &self.entries
+    }
+
+    /// The entry of the device with the given serial number.
+    #[inline]
+    #[must_use]
+    pub fn get(&self, serial_number: &str) -> Option<&DeviceMapEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.serial_number == serial_number)
+    }
+
+    /// The EID of the device with the given serial number.
+    #[inline]
+    #[must_use]
+    pub fn eid(&self, serial_number: &str) -> Option<u64> {
+        self.get(serial_number).map(|entry| entry.eid)
+    }
+
+    /// The serial number of the current device with the given EID.
+    #[inline]
+    #[must_use]
+    pub fn serial_number(&self, eid: u64) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|entry| entry.eid == eid && entry.superseded_by.is_none())
+            .map(|entry| entry.serial_number.as_str())
+    }
+
+    /// The devices which have been replaced by another device with the same
+    /// EID.
+    #[inline]
+    pub fn superseded(&self) -> impl Iterator<Item = &DeviceMapEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.superseded_by.is_some())
+    }
+}
+
+/// A status code reported for a device (e.g., `envoy.global.ok`).
+///
+/// The codes which are not (yet) known are kept as [`DeviceStatus::Other`].
+/// [`DeviceStatus::as_str`] returns the code as reported by the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(from = "String")]
+pub enum DeviceStatus {
+    /// No problem (`envoy.global.ok`).
+    Ok,
+    /// Provisioning is complete (`prop.done`).
+    ProvisioningDone,
+    /// The device is not communicating (`envoy.cond_flags.obs_strs.failure`).
+    CommunicationFailure,
+    /// The device is being discovered (`envoy.cond_flags.obs_strs.discovering`).
+    Discovering,
+    /// The DC power is too low to produce
+    /// (`envoy.cond_flags.pcu_ctrl.dc-pwr-low`).
+    DcPowerLow,
+    /// The DC voltage is too low (`envoy.cond_flags.pcu_chan.dcvoltagetoolow`).
+    DcVoltageTooLow,
+    /// The grid is unstable (`envoy.cond_flags.pcu_ctrl.gridinstability`).
+    GridInstability,
+    /// A code which is not (yet) known.
+    Other(String),
+}
+
+impl DeviceStatus {
+    /// The status code, as reported by the Envoy.
+    #[inline]
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match *self {
+            Self::Ok => "envoy.global.ok",
+            Self::ProvisioningDone => "prop.done",
+            Self::CommunicationFailure => "envoy.cond_flags.obs_strs.failure",
+            Self::Discovering => "envoy.cond_flags.obs_strs.discovering",
+            Self::DcPowerLow => "envoy.cond_flags.pcu_ctrl.dc-pwr-low",
+            Self::DcVoltageTooLow => "envoy.cond_flags.pcu_chan.dcvoltagetoolow",
+            Self::GridInstability => "envoy.cond_flags.pcu_ctrl.gridinstability",
+            Self::Other(ref code) => code,
+        }
+    }
+}
+
+impl From<String> for DeviceStatus {
+    #[inline]
+    fn from(code: String) -> Self {
+        match code.as_str() {
+            "envoy.global.ok" => Self::Ok,
+            "prop.done" => Self::ProvisioningDone,
+            "envoy.cond_flags.obs_strs.failure" => Self::CommunicationFailure,
+            "envoy.cond_flags.obs_strs.discovering" => Self::Discovering,
+            "envoy.cond_flags.pcu_ctrl.dc-pwr-low" => Self::DcPowerLow,
+            "envoy.cond_flags.pcu_chan.dcvoltagetoolow" => Self::DcVoltageTooLow,
+            "envoy.cond_flags.pcu_ctrl.gridinstability" => Self::GridInstability,
+            _ => Self::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for DeviceStatus {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Deserialize a Unix timestamp given either as a number or as a string.
+fn timestamp<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    /// The forms the timestamp is found in.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        /// A numeric timestamp.
+        Number(i64),
+        /// A quoted timestamp.
+        Text(String),
+    }
+
+    match Option::<Timestamp>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Timestamp::Number(number)) => Ok(Some(number)),
+        Some(Timestamp::Text(text)) => text
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| serde::de::Error::custom(format!("Invalid timestamp {text:?}: {e}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("envoy.global.ok", DeviceStatus::Ok)]
+    #[case("prop.done", DeviceStatus::ProvisioningDone)]
+    #[case("envoy.cond_flags.pcu_ctrl.dc-pwr-low", DeviceStatus::DcPowerLow)]
+    #[case(
+        "envoy.cond_flags.acb_ctrl.bmuCellMinVoltage",
+        DeviceStatus::Other("envoy.cond_flags.acb_ctrl.bmuCellMinVoltage".to_owned())
+    )]
+    fn device_status_codes(#[case] code: &str, #[case] expected: DeviceStatus) {
+        let status = DeviceStatus::from(code.to_owned());
+
+        assert_eq!(status, expected);
+        assert_eq!(status.to_string(), code);
+    }
+
+    #[test]
+    fn device_status_codes_round_trip() {
+        let known = [
+            DeviceStatus::Ok,
+            DeviceStatus::ProvisioningDone,
+            DeviceStatus::CommunicationFailure,
+            DeviceStatus::Discovering,
+            DeviceStatus::DcPowerLow,
+            DeviceStatus::DcVoltageTooLow,
+            DeviceStatus::GridInstability,
+        ];
+        for status in known {
+            assert_eq!(DeviceStatus::from(status.to_string()), status);
+        }
+    }
+
+    #[rstest]
+    #[case::string(r#""1700000000""#, Some(1_700_000_000))]
+    #[case::number("1700000000", Some(1_700_000_000))]
+    #[case::null("null", None)]
+    fn timestamps(#[case] installed: &str, #[case] expected: Option<i64>) {
+        let json = format!(r#"{{"serial_num": "1", "part_num": "2", "installed": {installed}}}"#);
+        let device: Device = serde_json::from_str(&json).expect("Should deserialize");
+
+        assert_eq!(device.installed, expected);
+        assert_eq!(device.last_report_date, None);
+    }
+
+    #[test]
+    fn invalid_timestamp() {
+        let json = r#"{"serial_num": "1", "part_num": "2", "installed": "yesterday"}"#;
+        let result = serde_json::from_str::<Device>(json);
+
+        assert!(result.is_err(), "Invalid timestamp should be rejected");
+    }
+
+    /// A device entry for the counting tests.
+    fn device(
+        serial_number: &str,
+        provisioned: bool,
+        communicating: bool,
+        deleted: bool,
+    ) -> String {
+        format!(
+            r#"{{"serial_num": "{serial_number}", "part_num": "800-01391-r02",
+                "provisioned": {provisioned}, "communicating": {communicating},
+                "producing": {communicating}, "deleted": {deleted}}}"#
+        )
+    }
+
+    #[test]
+    fn device_counts() {
+        let json = format!(
+            r#"[
+                {{"type": "PCU", "devices": [{}, {}, {}, {}, {}]}},
+                {{"type": "NSRB", "devices": [{}]}}
+            ]"#,
+            device("482243012345", true, true, false),
+            device("482243012346", true, false, false),
+            device("482243012347", false, false, false),
+            // Swapped out, and listed again with its replacement.
+            device("482243012348", true, true, true),
+            device("482243012345", true, true, false),
+            device("482243054321", true, true, false),
+        );
+        let inventory: Inventory = serde_json::from_str(&json).expect("Should deserialize");
+        let counts = inventory.device_counts();
+
+        assert_eq!(
+            counts.pcu,
+            ClassCounts {
+                detected: 3,
+                provisioned: 2,
+                communicating: 1,
+                producing: 1,
+            }
+        );
+        assert_eq!(counts.get(DeviceClass::Acb), &ClassCounts::default());
+        assert_eq!(
+            counts.duplicates,
+            vec![(DeviceClass::Pcu, "482243012345".to_owned())]
+        );
+
+        let expected = ExpectedCounts::new()
+            .with(DeviceClass::Pcu, 4)
+            .with(DeviceClass::Acb, 0)
+            .with(DeviceClass::Nsrb, 0);
+        assert_eq!(
+            counts.expect(expected),
+            vec![
+                Discrepancy::Missing {
+                    class: DeviceClass::Pcu,
+                    expected: 4,
+                    detected: 3,
+                },
+                Discrepancy::NotProvisioned {
+                    class: DeviceClass::Pcu,
+                    count: 1,
+                },
+                Discrepancy::NotCommunicating {
+                    class: DeviceClass::Pcu,
+                    count: 2,
+                },
+                Discrepancy::Duplicate {
+                    class: DeviceClass::Pcu,
+                    serial_number: "482243012345".to_owned(),
+                },
+                Discrepancy::Unexpected {
+                    class: DeviceClass::Nsrb,
+                    expected: 0,
+                    detected: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn device_counts_match_expectation() {
+        let json = format!(
+            r#"[{{"type": "PCU", "devices": [{}]}}]"#,
+            device("482243012345", true, true, false)
+        );
+        let inventory: Inventory = serde_json::from_str(&json).expect("Should deserialize");
+
+        assert_eq!(
+            inventory
+                .device_counts()
+                .expect(ExpectedCounts::new().with(DeviceClass::Pcu, 1)),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn device_map_replacement() {
+        let json = r#"[
+            {"type": "PCU", "devices": [
+                {"serial_num": "482243012345", "part_num": "1", "chaneid": 1, "installed": "1700000000", "deleted": true},
+                {"serial_num": "482243012399", "part_num": "1", "chaneid": 1, "installed": "1710000000"},
+                {"serial_num": "482243012346", "part_num": "1", "chaneid": 2},
+                {"serial_num": "482243012347", "part_num": "1"}
+            ]},
+            {"type": "NSRB", "devices": [
+                {"serial_num": "482243054321", "part_num": "2", "chaneid": 3}
+            ]}
+        ]"#;
+        let inventory: Inventory = serde_json::from_str(json).expect("Should deserialize");
+        let map = DeviceMap::from_inventory(&inventory);
+
+        assert_eq!(map.entries().len(), 4, "Devices without an EID are skipped");
+        assert_eq!(map.serial_number(1), Some("482243012399"));
+        assert_eq!(map.eid("482243012345"), Some(1));
+        assert_eq!(
+            map.superseded()
+                .map(|entry| (entry.serial_number.as_str(), entry.superseded_by.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![("482243012345", Some("482243012399"))]
+        );
+        assert_eq!(
+            map.get("482243054321").map(|entry| entry.class),
+            Some(DeviceClass::Nsrb)
+        );
+        assert_eq!(map.eid("482243012347"), None);
+    }
+
+    #[test]
+    fn unknown_sections_are_skipped() {
+        let json = r#"[
+            {"type": "PCU", "devices": []},
+            {"type": "ESUB", "devices": [{"serial_num": "1"}]}
+        ]"#;
+        let inventory: Inventory = serde_json::from_str(json).expect("Should deserialize");
+
+        assert_eq!(inventory, Inventory::default());
+    }
+}
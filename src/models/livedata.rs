@@ -0,0 +1,279 @@
+//! # Live data models
+//!
+//! This module contains the models for the live data reported by the Envoy at
+//! `/ivp/livedata/status`.
+//!
+//! The Envoy only updates the live data while streaming is enabled (see
+//! [`Envoy::enable_live_data`](crate::Envoy::enable_live_data)), and the
+//! stream stops by itself after a few minutes. While streaming is inactive,
+//! the power readings are all zero, which is indistinguishable from a system
+//! producing and consuming nothing; use [`LiveData::is_streaming`] to tell the
+//! two apart.
+//!
+//! Power readings are reported in milliwatts (and milli-volt-amperes), with
+//! accessors converting them to watts (and volt-amperes).
+
+use alloc::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Whether the Envoy is streaming live data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum StreamState {
+    /// Live data is being streamed.
+    Enabled,
+    /// Live data is not being streamed.
+    #[default]
+    Disabled,
+    /// A state which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(StreamState {
+    Enabled => "enabled",
+    Disabled => "disabled",
+    Other => "other",
+});
+
+/// The request body enabling or disabling the live data stream, sent to
+/// `/ivp/livedata/stream`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct StreamRequest {
+    /// `1` to enable streaming, `0` to disable it.
+    pub enable: u8,
+}
+
+impl StreamRequest {
+    /// Create a request enabling or disabling the live data stream.
+    #[inline]
+    #[must_use]
+    pub fn new(enable: bool) -> Self {
+        Self {
+            enable: u8::from(enable),
+        }
+    }
+}
+
+/// Live data reported by the Envoy.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct LiveData {
+    /// The state of the connections used for streaming.
+    pub connection: Connection,
+    /// The latest power readings.
+    pub meters: Meters,
+    /// The task which produced the latest update.
+    pub tasks: Tasks,
+    /// Internal counters of the Envoy, by name.
+    #[serde(default)]
+    pub counters: BTreeMap<String, i64>,
+}
+
+impl LiveData {
+    /// Whether the Envoy is streaming live data.
+    ///
+    /// When streaming is inactive, the power readings are not updated and are
+    /// reported as zero.
+    #[inline]
+    #[must_use]
+    pub fn is_streaming(&self) -> bool {
+        self.connection.sc_stream == StreamState::Enabled
+    }
+}
+
+/// The state of the connections used for streaming.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct Connection {
+    /// State of the MQTT connection (e.g., `connected`).
+    #[serde(default)]
+    pub mqtt_state: String,
+    /// State of the provisioning (e.g., `configured`).
+    #[serde(default)]
+    pub prov_state: String,
+    /// State of the authentication (e.g., `ok`).
+    #[serde(default)]
+    pub auth_state: String,
+    /// Whether live data is being streamed.
+    #[serde(default)]
+    pub sc_stream: StreamState,
+    /// Whether debug data is being streamed.
+    #[serde(default)]
+    pub sc_debug: StreamState,
+}
+
+/// The latest power readings.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct Meters {
+    /// Time of the latest update, as a Unix timestamp.
+    pub last_update: i64,
+    /// Aggregate state of charge of the batteries, in percent.
+    #[serde(default)]
+    pub soc: i32,
+    /// Number of phases of the installation.
+    #[serde(default)]
+    pub phase_count: u8,
+    /// Solar production.
+    pub pv: Power,
+    /// Battery charge (negative) and discharge (positive).
+    pub storage: Power,
+    /// Grid import (positive) and export (negative).
+    pub grid: Power,
+    /// Consumption of the site.
+    pub load: Power,
+    /// Generator production.
+    #[serde(default)]
+    pub generator: Power,
+}
+
+/// A power reading, in total and per phase.
+///
+/// Phases which are not present in the installation are reported as zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[non_exhaustive]
+pub struct Power {
+    /// Active power, in milliwatts.
+    pub agg_p_mw: i32,
+    /// Apparent power, in milli-volt-amperes.
+    #[serde(default)]
+    pub agg_s_mva: i32,
+    /// Active power of phase A, in milliwatts.
+    #[serde(default)]
+    pub agg_p_ph_a_mw: i32,
+    /// Active power of phase B, in milliwatts.
+    #[serde(default)]
+    pub agg_p_ph_b_mw: i32,
+    /// Active power of phase C, in milliwatts.
+    #[serde(default)]
+    pub agg_p_ph_c_mw: i32,
+    /// Apparent power of phase A, in milli-volt-amperes.
+    #[serde(default)]
+    pub agg_s_ph_a_mva: i32,
+    /// Apparent power of phase B, in milli-volt-amperes.
+    #[serde(default)]
+    pub agg_s_ph_b_mva: i32,
+    /// Apparent power of phase C, in milli-volt-amperes.
+    #[serde(default)]
+    pub agg_s_ph_c_mva: i32,
+}
+
+impl Power {
+    /// Active power, in watts.
+    #[inline]
+    #[must_use]
+    pub fn watts(&self) -> f64 {
+        from_milli(self.agg_p_mw)
+    }
+
+    /// Apparent power, in volt-amperes.
+    #[inline]
+    #[must_use]
+    pub fn volt_amperes(&self) -> f64 {
+        from_milli(self.agg_s_mva)
+    }
+
+    /// Active power of each phase (A, B and C), in watts.
+    #[inline]
+    #[must_use]
+    pub fn phase_watts(&self) -> [f64; 3] {
+        [
+            from_milli(self.agg_p_ph_a_mw),
+            from_milli(self.agg_p_ph_b_mw),
+            from_milli(self.agg_p_ph_c_mw),
+        ]
+    }
+}
+
+/// The task which produced the latest update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+pub struct Tasks {
+    /// Identifier of the task.
+    pub task_id: i64,
+    /// Time of the task, as a Unix timestamp.
+    pub timestamp: i64,
+}
+
+/// Convert a value in thousandths of a unit to the unit.
+#[expect(
+    clippy::float_arithmetic,
+    reason = "Converting to fractional units requires floating-point division"
+)]
+fn from_milli(value: i32) -> f64 {
+    f64::from(value) / 1000.0_f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[test]
+    fn power_in_watts() {
+        let power = Power {
+            agg_p_mw: 2_234_512,
+            agg_s_mva: 2_324_113,
+            agg_p_ph_a_mw: 1_117_256,
+            agg_p_ph_b_mw: -1_117_256,
+            agg_p_ph_c_mw: 0,
+            ..Power::default()
+        };
+
+        assert_eq!(
+            (power.watts(), power.volt_amperes(), power.phase_watts()),
+            (
+                2_234.512_f64,
+                2_324.113_f64,
+                [1_117.256_f64, -1_117.256_f64, 0.0_f64]
+            )
+        );
+    }
+
+    #[test]
+    fn deserialize_minimal() {
+        let json = r#"{
+            "connection": {},
+            "meters": {
+                "last_update": 1704067201,
+                "pv": {"agg_p_mw": 0},
+                "storage": {"agg_p_mw": 0},
+                "grid": {"agg_p_mw": 0},
+                "load": {"agg_p_mw": 0}
+            },
+            "tasks": {"task_id": 1, "timestamp": 1704067201}
+        }"#;
+        let live_data: LiveData =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert!(
+            !live_data.is_streaming(),
+            "A missing stream state should be treated as inactive"
+        );
+        assert_eq!(live_data.meters.generator, Power::default());
+        assert!(
+            live_data.counters.is_empty(),
+            "Counters should default to empty"
+        );
+    }
+
+    #[rstest]
+    #[case::enable(true, r#"{"enable":1}"#)]
+    #[case::disable(false, r#"{"enable":0}"#)]
+    fn stream_request_round_trip(#[case] enable: bool, #[case] json: &str) {
+        let request = StreamRequest::new(enable);
+        let serialized = serde_json::to_string(&request).expect("Should serialize");
+
+        assert_eq!(serialized, json);
+        assert_eq!(
+            serde_json::from_str::<StreamRequest>(&serialized)
+                .expect("Should deserialize successfully"),
+            request
+        );
+    }
+}
@@ -0,0 +1,451 @@
+//! # Merging readings
+//!
+//! This module merges the readings collected from the same Envoy by several
+//! independent collectors (e.g., for redundancy) into a single sequence.
+//!
+//! Readings are matched by their timestamp. Readings with the same timestamp
+//! usually have identical values, but may differ when a collector caught the
+//! Envoy mid-update; such readings are reported as [`Conflict`]s and resolved
+//! according to a [`ConflictPolicy`].
+
+use alloc::collections::BTreeMap;
+
+use super::{
+    meters::Readings,
+    production::{AcbMeasurement, EimMeasurement, InvertersMeasurement},
+};
+
+/// A reading which can be merged with readings from other sources.
+pub trait Sample: Sized + PartialEq {
+    /// Time of the reading, as a Unix timestamp.
+    fn timestamp(&self) -> i64;
+
+    /// Combine readings with the same timestamp into their average.
+    ///
+    /// Measured values are averaged, while counts, states and types are taken
+    /// from the first reading. Returns `None` if there are no readings.
+    fn average(samples: &[Self]) -> Option<Self>;
+}
+
+/// How readings with the same timestamp but different values are resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum ConflictPolicy {
+    /// Keep the reading from the first source which has one.
+    #[default]
+    FirstWins,
+    /// Keep the reading from the given source (by index), which is deemed the
+    /// most reliable. If it has no reading for the timestamp, the reading from
+    /// the first source which has one is kept.
+    PreferSource(usize),
+    /// Keep the average of the readings (see [`Sample::average`]).
+    Average,
+}
+
+/// Readings with the same timestamp but different values.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Conflict {
+    /// Time of the readings, as a Unix timestamp.
+    pub timestamp: i64,
+    /// The sources (by index) which had a reading for the timestamp.
+    pub sources: Vec<usize>,
+}
+
+/// The result of merging readings.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Merged<T> {
+    /// The readings, with a single reading per timestamp, in ascending order of
+    /// timestamp.
+    pub samples: Vec<T>,
+    /// The conflicts encountered, in ascending order of timestamp.
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Merge the readings from several sources, deduplicating them by timestamp.
+///
+/// Each timestamp present in any source appears exactly once in the result,
+/// and the result is sorted by timestamp (the sources themselves need not be
+/// sorted). Readings with the same timestamp and the same values are merged
+/// silently; readings with the same timestamp but different values are
+/// resolved according to `policy` and reported as conflicts.
+///
+/// # Arguments
+///
+/// * `sources` - The readings from each source
+/// * `policy` - How conflicting readings are resolved
+///
+/// # Returns
+///
+/// Returns the merged readings along with the conflicts encountered.
+///
+/// # Example
+///
+/// ```
+/// use enphase_api::models::{
+///     merge::{ConflictPolicy, merge_samples},
+///     production::InvertersMeasurement,
+/// };
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let reading: InvertersMeasurement = serde_json::from_str(
+///     r#"{"activeCount": 10, "readingTime": 1704067200, "wNow": 225, "whLifetime": 1470271}"#,
+/// )?;
+/// let merged = merge_samples(
+///     [vec![reading.clone()], vec![reading]],
+///     ConflictPolicy::FirstWins,
+/// );
+/// assert_eq!(merged.samples.len(), 1);
+/// assert!(merged.conflicts.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[inline]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "merge_samples reads better than merge::samples at the call site"
+)]
+pub fn merge_samples<T, S>(
+    sources: impl IntoIterator<Item = S>,
+    policy: ConflictPolicy,
+) -> Merged<T>
+where
+    T: Sample,
+    S: IntoIterator<Item = T>,
+{
+    let mut by_timestamp: BTreeMap<i64, Vec<(usize, T)>> = BTreeMap::new();
+    for (source, samples) in sources.into_iter().enumerate() {
+        for sample in samples {
+            by_timestamp
+                .entry(sample.timestamp())
+                .or_default()
+                .push((source, sample));
+        }
+    }
+
+    let mut merged = Merged {
+        samples: Vec::with_capacity(by_timestamp.len()),
+        conflicts: Vec::new(),
+    };
+    for (timestamp, mut candidates) in by_timestamp {
+        let conflicting = candidates
+            .split_first()
+            .is_some_and(|((_, first), rest)| rest.iter().any(|(_, sample)| sample != first));
+        if conflicting {
+            let mut contributors: Vec<usize> =
+                candidates.iter().map(|&(source, _)| source).collect();
+            contributors.dedup();
+            merged.conflicts.push(Conflict {
+                timestamp,
+                sources: contributors,
+            });
+        }
+
+        let chosen = if conflicting {
+            match policy {
+                ConflictPolicy::FirstWins => None,
+                ConflictPolicy::PreferSource(preferred) => candidates
+                    .iter()
+                    .position(|&(source, _)| source == preferred)
+                    .map(|index| candidates.swap_remove(index).1),
+                ConflictPolicy::Average => {
+                    let samples: Vec<T> = candidates.drain(..).map(|(_, sample)| sample).collect();
+                    T::average(&samples)
+                }
+            }
+        } else {
+            None
+        };
+        let sample =
+            chosen.or_else(|| (!candidates.is_empty()).then(|| candidates.swap_remove(0).1));
+        merged.samples.extend(sample);
+    }
+    merged
+}
+
+impl Sample for InvertersMeasurement {
+    #[inline]
+    fn timestamp(&self) -> i64 {
+        self.reading_time
+    }
+
+    #[inline]
+    fn average(samples: &[Self]) -> Option<Self> {
+        let first = samples.first()?;
+        Some(Self {
+            w_now: mean(samples, |s| s.w_now),
+            wh_lifetime: mean(samples, |s| s.wh_lifetime),
+            ..first.clone()
+        })
+    }
+}
+
+impl Sample for EimMeasurement {
+    #[inline]
+    fn timestamp(&self) -> i64 {
+        self.reading_time
+    }
+
+    #[inline]
+    fn average(samples: &[Self]) -> Option<Self> {
+        let first = samples.first()?;
+        Some(Self {
+            w_now: mean(samples, |s| s.w_now),
+            wh_lifetime: mean(samples, |s| s.wh_lifetime),
+            wh_today: mean_present(samples, |s| s.wh_today),
+            wh_last_seven_days: mean_present(samples, |s| s.wh_last_seven_days),
+            vah_lifetime: mean_present(samples, |s| s.vah_lifetime),
+            vah_today: mean_present(samples, |s| s.vah_today),
+            varh_lead_lifetime: mean_present(samples, |s| s.varh_lead_lifetime),
+            varh_lead_today: mean_present(samples, |s| s.varh_lead_today),
+            varh_lag_lifetime: mean_present(samples, |s| s.varh_lag_lifetime),
+            varh_lag_today: mean_present(samples, |s| s.varh_lag_today),
+            rms_current: mean_present(samples, |s| s.rms_current),
+            rms_voltage: mean_present(samples, |s| s.rms_voltage),
+            react_pwr: mean_present(samples, |s| s.react_pwr),
+            apprnt_pwr: mean_present(samples, |s| s.apprnt_pwr),
+            pwr_factor: mean_present(samples, |s| s.pwr_factor),
+            ..first.clone()
+        })
+    }
+}
+
+impl Sample for AcbMeasurement {
+    #[inline]
+    fn timestamp(&self) -> i64 {
+        self.reading_time
+    }
+
+    #[inline]
+    fn average(samples: &[Self]) -> Option<Self> {
+        let first = samples.first()?;
+        Some(Self {
+            w_now: mean(samples, |s| s.w_now),
+            wh_now: mean(samples, |s| s.wh_now),
+            ..first.clone()
+        })
+    }
+}
+
+impl Sample for Readings {
+    #[inline]
+    fn timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    #[inline]
+    fn average(samples: &[Self]) -> Option<Self> {
+        let first = samples.first()?;
+        Some(Self {
+            timestamp: first.timestamp,
+            act_energy_dlvd: mean(samples, |s| s.act_energy_dlvd),
+            act_energy_rcvd: mean(samples, |s| s.act_energy_rcvd),
+            apparent_energy: mean(samples, |s| s.apparent_energy),
+            react_energy_lagg: mean(samples, |s| s.react_energy_lagg),
+            react_energy_lead: mean(samples, |s| s.react_energy_lead),
+            instantaneous_demand: mean(samples, |s| s.instantaneous_demand),
+            active_power: mean(samples, |s| s.active_power),
+            apparent_power: mean(samples, |s| s.apparent_power),
+            reactive_power: mean(samples, |s| s.reactive_power),
+            pwr_factor: mean(samples, |s| s.pwr_factor),
+            voltage: mean(samples, |s| s.voltage),
+            current: mean(samples, |s| s.current),
+            freq: mean(samples, |s| s.freq),
+        })
+    }
+}
+
+/// The mean of a value across readings, or zero if there are no readings.
+fn mean<T>(samples: &[T], value: impl Fn(&T) -> f64) -> f64 {
+    mean_present(samples, |sample| Some(value(sample))).unwrap_or_default()
+}
+
+/// The mean of a value across the readings which report it, if any.
+#[expect(
+    clippy::float_arithmetic,
+    reason = "Averaging readings requires floating-point arithmetic"
+)]
+fn mean_present<T>(samples: &[T], value: impl Fn(&T) -> Option<f64>) -> Option<f64> {
+    let (sum, count) = samples
+        .iter()
+        .filter_map(value)
+        .fold((0.0_f64, 0.0_f64), |(sum, count), v| {
+            (sum + v, count + 1.0_f64)
+        });
+    (count > 0.0_f64).then(|| sum / count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// An inverter reading at the given time with the given power.
+    fn reading(reading_time: i64, w_now: u32) -> InvertersMeasurement {
+        InvertersMeasurement {
+            active_count: 10,
+            reading_time,
+            w_now: f64::from(w_now),
+            wh_lifetime: 1_000.0_f64,
+        }
+    }
+
+    /// The timestamps and powers of merged readings.
+    fn summary(merged: &Merged<InvertersMeasurement>) -> Vec<(i64, String)> {
+        merged
+            .samples
+            .iter()
+            .map(|sample| (sample.reading_time, sample.w_now.to_string()))
+            .collect()
+    }
+
+    #[rstest]
+    #[case::overlapping(
+        vec![vec![reading(1, 10), reading(2, 20)], vec![reading(2, 20), reading(3, 30)]],
+        vec![(1, "10"), (2, "20"), (3, "30")]
+    )]
+    #[case::gapped(
+        vec![vec![reading(1, 10), reading(5, 50)], vec![reading(3, 30), reading(7, 70)]],
+        vec![(1, "10"), (3, "30"), (5, "50"), (7, "70")]
+    )]
+    #[case::unsorted(
+        vec![vec![reading(3, 30), reading(1, 10)], vec![reading(2, 20)]],
+        vec![(1, "10"), (2, "20"), (3, "30")]
+    )]
+    #[case::duplicated_within_source(vec![vec![reading(1, 10), reading(1, 10)]], vec![(1, "10")])]
+    #[case::single_source(vec![vec![reading(1, 10)]], vec![(1, "10")])]
+    #[case::no_sources(vec![], vec![])]
+    #[case::empty_sources(vec![vec![], vec![]], vec![])]
+    fn merge_without_conflicts(
+        #[case] sources: Vec<Vec<InvertersMeasurement>>,
+        #[case] expected: Vec<(i64, &str)>,
+    ) {
+        for policy in [
+            ConflictPolicy::FirstWins,
+            ConflictPolicy::PreferSource(1),
+            ConflictPolicy::Average,
+        ] {
+            let merged = merge_samples(sources.clone(), policy);
+
+            assert_eq!(
+                summary(&merged),
+                expected
+                    .iter()
+                    .map(|&(time, power)| (time, power.to_owned()))
+                    .collect::<Vec<_>>(),
+                "Unexpected readings for {policy:?}"
+            );
+            assert_eq!(
+                merged.conflicts,
+                vec![],
+                "Unexpected conflicts for {policy:?}"
+            );
+        }
+    }
+
+    #[rstest]
+    #[case::first_wins(ConflictPolicy::FirstWins, "20")]
+    #[case::prefer_second(ConflictPolicy::PreferSource(1), "24")]
+    #[case::prefer_missing(ConflictPolicy::PreferSource(5), "20")]
+    #[case::average(ConflictPolicy::Average, "22")]
+    fn merge_with_conflicts(#[case] policy: ConflictPolicy, #[case] power: &str) {
+        let sources = vec![
+            vec![reading(1, 10), reading(2, 20), reading(3, 30)],
+            vec![reading(1, 10), reading(2, 24)],
+        ];
+        let merged = merge_samples(sources, policy);
+
+        assert_eq!(
+            summary(&merged),
+            vec![
+                (1, "10".to_owned()),
+                (2, power.to_owned()),
+                (3, "30".to_owned())
+            ]
+        );
+        assert_eq!(
+            merged.conflicts,
+            vec![Conflict {
+                timestamp: 2,
+                sources: vec![0, 1],
+            }]
+        );
+    }
+
+    #[test]
+    fn merge_invariants() {
+        // Synthetic streams with overlaps, gaps and conflicts: source `n`
+        // reports every `n + 1` seconds, and disagrees with the other sources
+        // every third second. As source 2 reports every third second, each of
+        // those readings conflicts with the reading of source 0.
+        let sources: Vec<Vec<InvertersMeasurement>> = (0..4_u32)
+            .zip(1_usize..)
+            .map(|(n, step)| {
+                (0..50_u32)
+                    .step_by(step)
+                    .map(|t| {
+                        let power = if t.is_multiple_of(3) {
+                            t.saturating_add(n)
+                        } else {
+                            t
+                        };
+                        reading(i64::from(t), power)
+                    })
+                    .collect()
+            })
+            .collect();
+        let conflicting: Vec<i64> = (0..50_i64).step_by(3).collect();
+
+        for policy in [
+            ConflictPolicy::FirstWins,
+            ConflictPolicy::PreferSource(2),
+            ConflictPolicy::Average,
+        ] {
+            let merged = merge_samples(sources.clone(), policy);
+            let timestamps: Vec<i64> = merged.samples.iter().map(|s| s.reading_time).collect();
+            let conflicts: Vec<i64> = merged.conflicts.iter().map(|c| c.timestamp).collect();
+
+            assert_eq!(timestamps, (0..50_i64).collect::<Vec<_>>());
+            assert_eq!(
+                conflicts, conflicting,
+                "Unexpected conflicts for {policy:?}"
+            );
+            assert!(
+                merged
+                    .samples
+                    .iter()
+                    .filter(|s| !conflicting.contains(&s.reading_time))
+                    .all(|s| s.w_now.to_string() == s.reading_time.to_string()),
+                "Readings without conflicts should be kept unchanged"
+            );
+        }
+    }
+
+    #[test]
+    fn average_optional_values() {
+        let json = r#"{
+            "activeCount": 1,
+            "measurementType": "production",
+            "readingTime": 1,
+            "wNow": 100,
+            "whLifetime": 1000,
+            "rmsVoltage": 240
+        }"#;
+        let first: EimMeasurement = serde_json::from_str(json).expect("Should deserialize");
+        let second = EimMeasurement {
+            w_now: 200.0_f64,
+            rms_voltage: None,
+            ..first.clone()
+        };
+
+        let average = EimMeasurement::average(&[first, second]).expect("Should average");
+        assert_eq!(average.w_now.to_string(), "150");
+        assert_eq!(
+            average.rms_voltage.map(|v| v.to_string()),
+            Some("240".to_owned())
+        );
+        assert_eq!(average.react_pwr, None);
+    }
+}
@@ -0,0 +1,667 @@
+//! # Meter models
+//!
+//! This module contains the models for the integrated meters (CTs) reported
+//! by the Envoy at `/ivp/meters` (the meter configuration) and
+//! `/ivp/meters/readings` (the instantaneous readings).
+//!
+//! Each meter is identified by its `eid`, which links a meter configuration to
+//! its readings. Readings are reported for the meter as a whole, and for each
+//! of its phases (channels).
+//!
+//! A CT clamped backwards is the most common installation error. It shows as
+//! a channel whose active power has the wrong sign, which
+//! [`suspect_reversed_cts`] detects over a series of readings.
+
+use serde::Deserialize;
+
+use super::production::MeasurementType;
+
+/// Whether a meter is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum MeterState {
+    /// The meter is enabled.
+    Enabled,
+    /// The meter is disabled.
+    Disabled,
+    /// A state which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(MeterState {
+    Enabled => "enabled",
+    Disabled => "disabled",
+    Other => "other",
+});
+
+/// How the phases of a meter are wired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum PhaseMode {
+    /// Single phase.
+    Single,
+    /// Split phase.
+    Split,
+    /// Three phase.
+    Three,
+    /// A phase mode which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(PhaseMode {
+    Single => "single",
+    Split => "split",
+    Three => "three",
+    Other => "other",
+});
+
+/// The status of the measurements of a meter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "kebab-case")]
+pub enum MeteringStatus {
+    /// The meter is measuring normally.
+    Normal,
+    /// The meter is not measuring.
+    NotMetering,
+    /// The meter wiring should be checked (e.g., a CT is reversed).
+    CheckWiring,
+    /// A status which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(MeteringStatus {
+    Normal => "normal",
+    NotMetering => "not-metering",
+    CheckWiring => "check-wiring",
+    Other => "other",
+});
+
+/// The configuration of an integrated meter.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct Meter {
+    /// Identifier of the meter.
+    pub eid: u64,
+    /// Whether the meter is enabled.
+    pub state: MeterState,
+    /// The quantity measured by the meter.
+    pub measurement_type: MeasurementType,
+    /// How the phases of the meter are wired.
+    pub phase_mode: PhaseMode,
+    /// Number of phases measured by the meter.
+    pub phase_count: u8,
+    /// The status of the measurements of the meter.
+    pub metering_status: MeteringStatus,
+    /// Additional status flags reported for the meter.
+    #[serde(default)]
+    pub status_flags: Vec<String>,
+}
+
+/// Instantaneous values and energy totals measured by a meter or channel.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct Readings {
+    /// Time of the reading, as a Unix timestamp.
+    pub timestamp: i64,
+    /// Active energy delivered (imported), in watt-hours.
+    pub act_energy_dlvd: f64,
+    /// Active energy received (exported), in watt-hours.
+    pub act_energy_rcvd: f64,
+    /// Apparent energy, in volt-ampere-hours.
+    pub apparent_energy: f64,
+    /// Lagging reactive energy, in volt-ampere-reactive-hours.
+    pub react_energy_lagg: f64,
+    /// Leading reactive energy, in volt-ampere-reactive-hours.
+    pub react_energy_lead: f64,
+    /// Instantaneous demand, in watts.
+    pub instantaneous_demand: f64,
+    /// Active power, in watts.
+    pub active_power: f64,
+    /// Apparent power, in volt-amperes.
+    pub apparent_power: f64,
+    /// Reactive power, in volt-amperes reactive.
+    pub reactive_power: f64,
+    /// Power factor.
+    pub pwr_factor: f64,
+    /// RMS voltage, in volts.
+    pub voltage: f64,
+    /// RMS current, in amperes.
+    pub current: f64,
+    /// Frequency, in hertz.
+    pub freq: f64,
+}
+
+/// The readings of a single phase of a meter.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[non_exhaustive]
+pub struct ChannelReading {
+    /// Identifier of the channel.
+    pub eid: u64,
+    /// The values measured on the channel.
+    #[serde(flatten)]
+    pub readings: Readings,
+}
+
+/// The readings of an integrated meter.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[non_exhaustive]
+pub struct MeterReading {
+    /// Identifier of the meter, matching [`Meter::eid`].
+    pub eid: u64,
+    /// The values measured by the meter, across all phases.
+    #[serde(flatten)]
+    pub readings: Readings,
+    /// The readings of each phase of the meter.
+    #[serde(default)]
+    pub channels: Vec<ChannelReading>,
+}
+
+/// The thresholds of the reversed CT heuristic (see [`suspect_reversed_cts`]).
+///
+/// # Example
+///
+/// ```
+/// use enphase_api::models::meters::ReversedCtThresholds;
+///
+/// let thresholds = ReversedCtThresholds::default()
+///     .min_power(100.0)
+///     .min_samples(5);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[must_use]
+pub struct ReversedCtThresholds {
+    /// How far beyond a possible reading the power must be, in watts.
+    min_power_w: f64,
+    /// How many consecutive readings must indicate a reversal.
+    min_samples: usize,
+}
+
+impl Default for ReversedCtThresholds {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            min_power_w: 50.0_f64,
+            min_samples: 3,
+        }
+    }
+}
+
+impl ReversedCtThresholds {
+    /// Set how far beyond a possible reading the power must be.
+    ///
+    /// The margin absorbs the standby draw of the microinverters at night
+    /// (which the production CT reads as a small negative power) and the
+    /// measurement error of the CTs.
+    ///
+    /// # Arguments
+    ///
+    /// * `watts` - The margin, in watts (50 W by default)
+    #[inline]
+    pub const fn min_power(mut self, watts: f64) -> Self {
+        self.min_power_w = watts;
+        self
+    }
+
+    /// Set how many consecutive readings must indicate a reversal.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - The number of readings (3 by default, and at least 1)
+    #[inline]
+    pub const fn min_samples(mut self, samples: usize) -> Self {
+        self.min_samples = if samples == 0 { 1 } else { samples };
+        self
+    }
+}
+
+/// A channel whose CT is suspected to be clamped backwards.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ReversedCt {
+    /// Identifier of the meter, matching [`Meter::eid`].
+    pub meter_eid: u64,
+    /// Identifier of the channel, or of the meter if it reports no channels.
+    pub eid: u64,
+    /// The quantity measured by the meter.
+    pub measurement_type: MeasurementType,
+    /// Why the CT is suspected to be reversed.
+    pub rationale: String,
+}
+
+/// Find the channels whose CT is suspected to be clamped backwards.
+///
+/// A channel is suspected when, in each of the latest readings, its active
+/// power has a sign which the installation cannot produce:
+///
+/// - a production CT reads power flowing into the PV array;
+/// - a total consumption CT reads power flowing out of the loads;
+/// - a net consumption CT reads an export larger than the production on the
+///   same phase (an exporting site never exports more than it produces).
+///
+/// A net consumption CT is only checked on phases with a production CT.
+/// Batteries discharging into the grid can also export more than the
+/// production, so the heuristic is best run while they are idle.
+///
+/// # Arguments
+///
+/// * `meters` - The meter configuration (see [`crate::Envoy::meters`])
+/// * `samples` - Successive readings of the meters, oldest first (see
+///   [`crate::Envoy::meter_readings`])
+/// * `thresholds` - The thresholds of the heuristic
+///
+/// # Returns
+///
+/// Returns the suspected channels, each with the rationale for the finding.
+/// Nothing is returned with fewer readings than the thresholds require.
+#[inline]
+#[must_use]
+pub fn suspect_reversed_cts(
+    meters: &[Meter],
+    samples: &[Vec<MeterReading>],
+    thresholds: &ReversedCtThresholds,
+) -> Vec<ReversedCt> {
+    let Some(window) = samples
+        .len()
+        .checked_sub(thresholds.min_samples)
+        .and_then(|start| samples.get(start..))
+    else {
+        return Vec::new();
+    };
+    let Some(latest) = window.last() else {
+        return Vec::new();
+    };
+
+    let enabled = || {
+        meters
+            .iter()
+            .filter(|meter| meter.state == MeterState::Enabled)
+    };
+    let production = enabled().find(|meter| meter.measurement_type == MeasurementType::Production);
+
+    let mut findings = Vec::new();
+    for meter in enabled() {
+        let Some(reading) = latest.iter().find(|reading| reading.eid == meter.eid) else {
+            continue;
+        };
+        for phase in 0..reading.channels.len().max(1) {
+            let least_excess = window.iter().try_fold(f64::INFINITY, |least, sample| {
+                let (_, power) = channel_power(sample, meter.eid, phase)?;
+                let produced = production
+                    .and_then(|source| channel_power(sample, source.eid, phase))
+                    .map(|(_, produced_w)| produced_w);
+                let over = excess(meter.measurement_type, power, produced)?;
+                (over > thresholds.min_power_w).then_some(least.min(over))
+            });
+            let (Some(over), Some((eid, _))) =
+                (least_excess, channel_power(latest, meter.eid, phase))
+            else {
+                continue;
+            };
+            findings.push(ReversedCt {
+                meter_eid: meter.eid,
+                eid,
+                measurement_type: meter.measurement_type,
+                rationale: rationale(meter.measurement_type, over, window.len()),
+            });
+        }
+    }
+    findings
+}
+
+/// The identifier and the active power of a phase of a meter in a sample.
+fn channel_power(sample: &[MeterReading], meter_eid: u64, phase: usize) -> Option<(u64, f64)> {
+    let reading = sample.iter().find(|reading| reading.eid == meter_eid)?;
+    if reading.channels.is_empty() {
+        (phase == 0).then_some((reading.eid, reading.readings.active_power))
+    } else {
+        reading
+            .channels
+            .get(phase)
+            .map(|channel| (channel.eid, channel.readings.active_power))
+    }
+}
+
+/// How far the power of a channel is beyond what the installation can
+/// produce, in watts, or `None` if the channel cannot be checked.
+#[expect(
+    clippy::float_arithmetic,
+    reason = "Power readings are inherently floating point"
+)]
+fn excess(measurement_type: MeasurementType, power: f64, produced: Option<f64>) -> Option<f64> {
+    match measurement_type {
+        MeasurementType::Production | MeasurementType::TotalConsumption => Some(-power),
+        MeasurementType::NetConsumption => {
+            produced.map(|production| -power - production.max(0.0_f64))
+        }
+        MeasurementType::Other => None,
+    }
+}
+
+/// The rationale of a finding.
+fn rationale(measurement_type: MeasurementType, excess: f64, samples: usize) -> String {
+    match measurement_type {
+        MeasurementType::Production => format!(
+            "The production CT read at least {excess:.0} W flowing into the PV array in each of \
+             the last {samples} readings, but the array can only produce power"
+        ),
+        MeasurementType::TotalConsumption => format!(
+            "The total consumption CT read at least {excess:.0} W flowing out of the loads in \
+             each of the last {samples} readings, but the loads can only consume power"
+        ),
+        MeasurementType::NetConsumption | MeasurementType::Other => format!(
+            "The net consumption CT read an export at least {excess:.0} W larger than the \
+             production in each of the last {samples} readings, but the site cannot export more \
+             than it produces"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// Identifier of the production meter.
+    const PRODUCTION: u64 = 704_643_328;
+    /// Identifier of the consumption meter.
+    const CONSUMPTION: u64 = 704_643_584;
+
+    /// An enabled meter measuring the given quantity.
+    fn meter(eid: u64, measurement_type: MeasurementType, phase_count: u8) -> Meter {
+        Meter {
+            eid,
+            state: MeterState::Enabled,
+            measurement_type,
+            phase_mode: if phase_count == 1 {
+                PhaseMode::Single
+            } else {
+                PhaseMode::Three
+            },
+            phase_count,
+            metering_status: MeteringStatus::Normal,
+            status_flags: Vec::new(),
+        }
+    }
+
+    /// Values with the given active power.
+    fn readings(active_power: f64) -> Readings {
+        Readings {
+            timestamp: 1_704_067_201,
+            act_energy_dlvd: 0.0_f64,
+            act_energy_rcvd: 0.0_f64,
+            apparent_energy: 0.0_f64,
+            react_energy_lagg: 0.0_f64,
+            react_energy_lead: 0.0_f64,
+            instantaneous_demand: active_power,
+            active_power,
+            apparent_power: active_power.abs(),
+            reactive_power: 0.0_f64,
+            pwr_factor: 1.0_f64,
+            voltage: 240.0_f64,
+            current: 0.0_f64,
+            freq: 50.0_f64,
+        }
+    }
+
+    /// The reading of a meter with the given active power on each phase.
+    fn reading(eid: u64, phases: &[f64]) -> MeterReading {
+        MeterReading {
+            eid,
+            readings: readings(phases.iter().sum()),
+            channels: phases
+                .iter()
+                .zip(1_u64..)
+                .map(|(&power, channel)| ChannelReading {
+                    eid: eid.saturating_add(channel),
+                    readings: readings(power),
+                })
+                .collect(),
+        }
+    }
+
+    /// The same production and consumption readings, three times.
+    fn samples(production: &[f64], consumption: &[f64]) -> Vec<Vec<MeterReading>> {
+        vec![
+            vec![
+                reading(PRODUCTION, production),
+                reading(CONSUMPTION, consumption),
+            ];
+            3
+        ]
+    }
+
+    #[rstest]
+    #[case::exporting(MeasurementType::NetConsumption, 2200.0_f64, -1400.0_f64)]
+    #[case::importing(MeasurementType::NetConsumption, 300.0_f64, 850.0_f64)]
+    #[case::night(MeasurementType::NetConsumption, -4.0_f64, 600.0_f64)]
+    #[case::consuming(MeasurementType::TotalConsumption, 2200.0_f64, 800.0_f64)]
+    fn reversed_cts_not_suspected(
+        #[case] consumption_type: MeasurementType,
+        #[case] production: f64,
+        #[case] consumption: f64,
+    ) {
+        let meters = [
+            meter(PRODUCTION, MeasurementType::Production, 1),
+            meter(CONSUMPTION, consumption_type, 1),
+        ];
+
+        let findings = suspect_reversed_cts(
+            &meters,
+            &samples(&[production], &[consumption]),
+            &ReversedCtThresholds::default(),
+        );
+
+        assert_eq!(findings, Vec::new());
+    }
+
+    #[rstest]
+    #[case::production(
+        MeasurementType::NetConsumption,
+        -2100.0_f64,
+        2800.0_f64,
+        PRODUCTION,
+        MeasurementType::Production,
+        "The production CT read at least 2100 W flowing into the PV array in each of the last 3 \
+         readings, but the array can only produce power"
+    )]
+    #[case::net_consumption(
+        MeasurementType::NetConsumption,
+        300.0_f64,
+        -850.0_f64,
+        CONSUMPTION,
+        MeasurementType::NetConsumption,
+        "The net consumption CT read an export at least 550 W larger than the production in each \
+         of the last 3 readings, but the site cannot export more than it produces"
+    )]
+    #[case::total_consumption(
+        MeasurementType::TotalConsumption,
+        2200.0_f64,
+        -800.0_f64,
+        CONSUMPTION,
+        MeasurementType::TotalConsumption,
+        "The total consumption CT read at least 800 W flowing out of the loads in each of the \
+         last 3 readings, but the loads can only consume power"
+    )]
+    fn reversed_cts_suspected(
+        #[case] consumption_type: MeasurementType,
+        #[case] production: f64,
+        #[case] consumption: f64,
+        #[case] meter_eid: u64,
+        #[case] measurement_type: MeasurementType,
+        #[case] rationale: &str,
+    ) {
+        let meters = [
+            meter(PRODUCTION, MeasurementType::Production, 1),
+            meter(CONSUMPTION, consumption_type, 1),
+        ];
+
+        let findings = suspect_reversed_cts(
+            &meters,
+            &samples(&[production], &[consumption]),
+            &ReversedCtThresholds::default(),
+        );
+
+        assert_eq!(
+            findings,
+            vec![ReversedCt {
+                meter_eid,
+                eid: meter_eid.saturating_add(1),
+                measurement_type,
+                rationale: rationale.to_owned(),
+            }]
+        );
+    }
+
+    #[test]
+    fn reversed_ct_on_one_phase() {
+        let meters = [
+            meter(PRODUCTION, MeasurementType::Production, 3),
+            meter(CONSUMPTION, MeasurementType::NetConsumption, 3),
+        ];
+
+        let findings = suspect_reversed_cts(
+            &meters,
+            &samples(
+                &[1320.0_f64, 1320.0_f64, 1320.0_f64],
+                &[-810.0_f64, -1750.0_f64, 410.0_f64],
+            ),
+            &ReversedCtThresholds::default(),
+        );
+
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| finding.eid)
+                .collect::<Vec<_>>(),
+            vec![CONSUMPTION.saturating_add(2)]
+        );
+    }
+
+    #[test]
+    fn reversed_ct_not_sustained() {
+        let meters = [
+            meter(PRODUCTION, MeasurementType::Production, 1),
+            meter(CONSUMPTION, MeasurementType::NetConsumption, 1),
+        ];
+        let mut history = samples(&[300.0_f64], &[-850.0_f64]);
+        history.insert(
+            1,
+            vec![
+                reading(PRODUCTION, &[300.0_f64]),
+                reading(CONSUMPTION, &[850.0_f64]),
+            ],
+        );
+
+        let thresholds = ReversedCtThresholds::default();
+        let recent = history.get(2..).expect("History should have four readings");
+        assert_eq!(
+            suspect_reversed_cts(&meters, &history, &thresholds),
+            Vec::new(),
+            "A reading with the expected sign should clear the suspicion"
+        );
+        assert_eq!(
+            suspect_reversed_cts(&meters, recent, &thresholds),
+            Vec::new(),
+            "Too few readings should not raise a suspicion"
+        );
+        assert_eq!(
+            suspect_reversed_cts(&meters, recent, &thresholds.min_samples(2)).len(),
+            1,
+            "The number of readings should be configurable"
+        );
+    }
+
+    #[test]
+    fn reversed_ct_margin() {
+        let meters = [
+            meter(PRODUCTION, MeasurementType::Production, 1),
+            meter(CONSUMPTION, MeasurementType::NetConsumption, 1),
+        ];
+        let history = samples(&[-120.0_f64], &[500.0_f64]);
+
+        assert_eq!(
+            suspect_reversed_cts(&meters, &history, &ReversedCtThresholds::default()).len(),
+            1
+        );
+        assert_eq!(
+            suspect_reversed_cts(
+                &meters,
+                &history,
+                &ReversedCtThresholds::default().min_power(200.0_f64)
+            ),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn net_consumption_without_production_ct() {
+        let meters = [meter(CONSUMPTION, MeasurementType::NetConsumption, 1)];
+        let history = vec![vec![reading(CONSUMPTION, &[-850.0_f64])]; 3];
+
+        assert_eq!(
+            suspect_reversed_cts(&meters, &history, &ReversedCtThresholds::default()),
+            Vec::new(),
+            "An export cannot be told from a reversed CT without the production"
+        );
+    }
+
+    #[test]
+    fn deserialize_meter_unknown_values() {
+        let json = r#"{
+            "eid": 704643328,
+            "state": "enabled",
+            "measurementType": "storage",
+            "phaseMode": "two",
+            "phaseCount": 2,
+            "meteringStatus": "calibrating"
+        }"#;
+        let meter: Meter = serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert_eq!(meter.measurement_type, MeasurementType::Other);
+        assert_eq!(meter.phase_mode, PhaseMode::Other);
+        assert_eq!(meter.metering_status, MeteringStatus::Other);
+        assert!(
+            meter.status_flags.is_empty(),
+            "Missing status flags should default to empty"
+        );
+    }
+
+    #[test]
+    fn deserialize_reading_without_channels() {
+        let json = r#"{
+            "eid": 704643328,
+            "timestamp": 1704067201,
+            "actEnergyDlvd": 14650943.211,
+            "actEnergyRcvd": 0.0,
+            "apparentEnergy": 18099213.706,
+            "reactEnergyLagg": 4967180.024,
+            "reactEnergyLead": 0.029,
+            "instantaneousDemand": 2234.512,
+            "activePower": 2234.512,
+            "apparentPower": 2324.113,
+            "reactivePower": 312.284,
+            "pwrFactor": 0.96,
+            "voltage": 240.311,
+            "current": 9.671,
+            "freq": 60.0
+        }"#;
+        let reading: MeterReading =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert_eq!(reading.eid, 704_643_328);
+        assert_eq!(reading.readings.timestamp, 1_704_067_201);
+        assert!(
+            reading.channels.is_empty(),
+            "Missing channels should default to empty"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! # Derived energy metrics
+//!
+//! This module contains helpers to compute the standard derived metrics of a
+//! solar installation (self-consumption, autarky and battery round-trip
+//! efficiency) from energy totals accumulated over a period.
+
+/// Energy totals accumulated over a period, in watt-hours.
+///
+/// All values are expected to be non-negative amounts of energy. Negative and
+/// non-finite values are treated as missing data by [`PeriodMetrics`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[non_exhaustive]
+pub struct EnergyTotals {
+    /// Energy produced by the PV array.
+    pub production_wh: f64,
+    /// Energy consumed by the loads.
+    pub consumption_wh: f64,
+    /// Energy imported from the grid.
+    pub import_wh: f64,
+    /// Energy exported to the grid.
+    pub export_wh: f64,
+    /// Energy used to charge the battery, if known.
+    pub battery_charge_wh: Option<f64>,
+    /// Energy delivered by the battery, if known.
+    pub battery_discharge_wh: Option<f64>,
+}
+
+impl EnergyTotals {
+    /// Create a new set of energy totals without battery information.
+    ///
+    /// # Arguments
+    ///
+    /// * `production_wh` - Energy produced by the PV array
+    /// * `consumption_wh` - Energy consumed by the loads
+    /// * `import_wh` - Energy imported from the grid
+    /// * `export_wh` - Energy exported to the grid
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::metrics::EnergyTotals;
+    ///
+    /// let totals = EnergyTotals::new(10_000.0, 8_000.0, 2_000.0, 4_000.0);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn new(production_wh: f64, consumption_wh: f64, import_wh: f64, export_wh: f64) -> Self {
+        Self {
+            production_wh,
+            consumption_wh,
+            import_wh,
+            export_wh,
+            battery_charge_wh: None,
+            battery_discharge_wh: None,
+        }
+    }
+
+    /// Add battery charge and discharge energies to the totals.
+    ///
+    /// # Arguments
+    ///
+    /// * `charge_wh` - Energy used to charge the battery
+    /// * `discharge_wh` - Energy delivered by the battery
+    #[inline]
+    #[must_use]
+    pub fn with_battery(self, charge_wh: f64, discharge_wh: f64) -> Self {
+        Self {
+            battery_charge_wh: Some(charge_wh),
+            battery_discharge_wh: Some(discharge_wh),
+            ..self
+        }
+    }
+}
+
+/// Derived metrics over a period.
+///
+/// Each ratio is in the range `0.0..=1.0`, and is `None` when it is undefined
+/// for the period rather than being `NaN` or infinite. In particular:
+///
+/// - With no production (e.g., at night), the self-consumption ratio is
+///   `None`.
+/// - With no consumption (e.g., an export-only interval), the autarky ratio is
+///   `None`.
+/// - Without battery totals, or with no battery charge, the round-trip
+///   estimate is `None`.
+///
+/// Ratios are clamped to `0.0..=1.0` to absorb small measurement
+/// inconsistencies between meters (e.g., export slightly exceeding production).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[non_exhaustive]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "PeriodMetrics reads better than models::metrics::Period"
+)]
+pub struct PeriodMetrics {
+    /// Fraction of the production consumed on site rather than exported.
+    pub self_consumption: Option<f64>,
+    /// Fraction of the consumption covered without importing from the grid
+    /// (also known as self-sufficiency).
+    pub autarky: Option<f64>,
+    /// Ratio of battery discharge to battery charge over the period.
+    ///
+    /// This is only an estimate of the round-trip efficiency, as it ignores any
+    /// change in the battery state of charge between the start and end of the
+    /// period. The value is not clamped, since a battery which was fuller at
+    /// the start of the period can legitimately discharge more than it charged.
+    pub battery_round_trip: Option<f64>,
+}
+
+impl PeriodMetrics {
+    /// Compute the derived metrics from the energy totals of a period.
+    ///
+    /// # Arguments
+    ///
+    /// * `totals` - The energy totals accumulated over the period
+    ///
+    /// # Returns
+    ///
+    /// Returns the derived metrics, with undefined ratios set to `None`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::metrics::{EnergyTotals, PeriodMetrics};
+    ///
+    /// let totals = EnergyTotals::new(10_000.0, 8_000.0, 2_000.0, 4_000.0);
+    /// let metrics = PeriodMetrics::from_totals(&totals);
+    /// assert_eq!(metrics.self_consumption, Some(0.6));
+    /// assert_eq!(metrics.autarky, Some(0.75));
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn from_totals(totals: &EnergyTotals) -> Self {
+        Self {
+            self_consumption: covered_fraction(totals.production_wh, totals.export_wh),
+            autarky: covered_fraction(totals.consumption_wh, totals.import_wh),
+            battery_round_trip: match (totals.battery_charge_wh, totals.battery_discharge_wh) {
+                (Some(charge), Some(discharge)) => ratio(discharge, charge),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// The fraction of `total` not accounted for by `external`, clamped to
+/// `0.0..=1.0`.
+#[expect(
+    clippy::float_arithmetic,
+    reason = "Energy ratios are inherently floating point"
+)]
+fn covered_fraction(total: f64, external: f64) -> Option<f64> {
+    if !external.is_finite() || external < 0.0_f64 {
+        return None;
+    }
+    ratio((total - external).max(0.0), total).map(|r| r.clamp(0.0, 1.0))
+}
+
+/// The ratio `numerator / denominator`, or `None` if it is undefined or
+/// either value is negative.
+#[expect(
+    clippy::float_arithmetic,
+    reason = "Energy ratios are inherently floating point"
+)]
+fn ratio(numerator: f64, denominator: f64) -> Option<f64> {
+    (numerator.is_finite() && numerator >= 0.0 && denominator.is_finite() && denominator > 0.0)
+        .then(|| numerator / denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case::typical(
+        EnergyTotals::new(10_000.0, 8_000.0, 2_000.0, 4_000.0),
+        Some(0.6_f64),
+        Some(0.75_f64)
+    )]
+    #[case::night(EnergyTotals::new(0.0, 1_000.0, 1_000.0, 0.0), None, Some(0.0_f64))]
+    #[case::export_only(EnergyTotals::new(2_000.0, 0.0, 0.0, 2_000.0), Some(0.0_f64), None)]
+    #[case::idle(EnergyTotals::new(0.0, 0.0, 0.0, 0.0), None, None)]
+    #[case::off_grid(
+        EnergyTotals::new(5_000.0, 5_000.0, 0.0, 0.0),
+        Some(1.0_f64),
+        Some(1.0_f64)
+    )]
+    #[case::export_exceeds_production(
+        EnergyTotals::new(1_000.0, 500.0, 600.0, 1_100.0),
+        Some(0.0_f64),
+        Some(0.0_f64)
+    )]
+    #[case::negative_export(EnergyTotals::new(1_000.0, 500.0, 0.0, -10.0), None, Some(1.0_f64))]
+    #[case::nan_production(EnergyTotals::new(f64::NAN, 500.0, 0.0, 0.0), None, Some(1.0_f64))]
+    fn ratios(
+        #[case] totals: EnergyTotals,
+        #[case] self_consumption: Option<f64>,
+        #[case] autarky: Option<f64>,
+    ) {
+        let metrics = PeriodMetrics::from_totals(&totals);
+        assert_eq!(metrics.self_consumption, self_consumption);
+        assert_eq!(metrics.autarky, autarky);
+        assert_eq!(metrics.battery_round_trip, None);
+    }
+
+    #[rstest]
+    #[case(4_000.0_f64, 3_600.0_f64, Some(0.9_f64))]
+    #[case(0.0_f64, 100.0_f64, None)]
+    #[case(1_000.0_f64, 0.0_f64, Some(0.0_f64))]
+    #[case::negative_discharge(1_000.0_f64, -100.0_f64, None)]
+    #[case::negative_charge(-1_000.0_f64, 100.0_f64, None)]
+    fn battery_round_trip(
+        #[case] charge: f64,
+        #[case] discharge: f64,
+        #[case] expected: Option<f64>,
+    ) {
+        let totals =
+            EnergyTotals::new(10_000.0, 8_000.0, 2_000.0, 4_000.0).with_battery(charge, discharge);
+        let metrics = PeriodMetrics::from_totals(&totals);
+        assert_eq!(metrics.battery_round_trip, expected);
+    }
+}
@@ -0,0 +1,420 @@
+//! # Production models
+//!
+//! This module contains the models for the production data reported by the
+//! Envoy at `/production.json`, and for the per-inverter production reported
+//! at `/api/v1/production/inverters`.
+//!
+//! The response contains `production`, `consumption` and `storage` arrays.
+//! Each array mixes different measurement types (e.g., `inverters` for the
+//! microinverter totals, `eim` for the integrated meters), each with their own
+//! set of fields. Measurement types which are not modelled are deserialized as
+//! [`Measurement::Unknown`] rather than failing the whole response.
+//!
+//! Some firmware also report whether production is enabled site-wide (the
+//! production toggle of the local interface) in [`ProductionState`].
+//!
+//! On split- and three-phase sites, the detailed `eim` measurements also break
+//! the readings down per line (see [`LineReading`]).
+
+use serde::Deserialize;
+
+/// Response structure for the production endpoint.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[non_exhaustive]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "Matches the naming of the other response types, such as PowerStatusResponse"
+)]
+pub struct ProductionResponse {
+    /// Production measurements.
+    #[serde(default)]
+    pub production: Vec<Measurement>,
+    /// Consumption measurements, if consumption meters are installed.
+    #[serde(default)]
+    pub consumption: Vec<Measurement>,
+    /// Storage measurements.
+    #[serde(default)]
+    pub storage: Vec<Measurement>,
+    /// Whether production is enabled site-wide, if reported by the firmware.
+    #[serde(default, rename = "productionSwitch")]
+    pub production_switch: Option<ProductionState>,
+}
+
+/// The site-wide production switch.
+///
+/// This is the production toggle of the local interface, which stops all
+/// production when off regardless of the power state of each device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "Distinguishes the site-wide state from the PowerState of a device"
+)]
+pub enum ProductionState {
+    /// Production is enabled.
+    On,
+    /// Production is stopped.
+    Off,
+    /// A state which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(ProductionState {
+    On => "on",
+    Off => "off",
+    Other => "other",
+});
+
+impl ProductionResponse {
+    /// The number of phases measured by the integrated meters.
+    ///
+    /// This is derived from the per-line breakdown of the `eim` measurements,
+    /// which is only reported on split- and three-phase sites when details
+    /// are requested. Meters without a breakdown count as single phase.
+    ///
+    /// # Returns
+    ///
+    /// Returns the largest number of lines reported by an `eim` measurement
+    /// (at least one), or `None` if there are no `eim` measurements.
+    #[inline]
+    #[must_use]
+    pub fn phase_count(&self) -> Option<usize> {
+        self.production
+            .iter()
+            .chain(&self.consumption)
+            .chain(&self.storage)
+            .filter_map(|measurement| match *measurement {
+                Measurement::Eim(ref eim) => Some(eim.lines.len().max(1)),
+                Measurement::Inverters(_) | Measurement::Acb(_) | Measurement::Unknown => None,
+            })
+            .max()
+    }
+}
+
+/// A single measurement within the production response.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[non_exhaustive]
+#[serde(tag = "type")]
+#[expect(
+    clippy::large_enum_variant,
+    reason = "Boxing the eim readings would complicate matching on the measurements"
+)]
+pub enum Measurement {
+    /// Totals reported by the microinverters.
+    #[serde(rename = "inverters")]
+    Inverters(InvertersMeasurement),
+    /// Readings from an integrated meter (CT).
+    #[serde(rename = "eim")]
+    Eim(EimMeasurement),
+    /// Readings from AC batteries.
+    #[serde(rename = "acb")]
+    Acb(AcbMeasurement),
+    /// A measurement type which is not (yet) supported.
+    #[serde(other)]
+    Unknown,
+}
+
+/// Totals reported by the microinverters.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct InvertersMeasurement {
+    /// Number of microinverters reporting.
+    pub active_count: u32,
+    /// Time of the reading, as a Unix timestamp.
+    pub reading_time: i64,
+    /// Current power, in watts.
+    pub w_now: f64,
+    /// Lifetime energy, in watt-hours.
+    pub wh_lifetime: f64,
+}
+
+/// The quantity measured by an integrated meter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "kebab-case")]
+pub enum MeasurementType {
+    /// Production from the PV array.
+    Production,
+    /// Total consumption of the loads.
+    TotalConsumption,
+    /// Net consumption (consumption minus production) at the grid connection.
+    NetConsumption,
+    /// A measurement type which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(MeasurementType {
+    Production => "production",
+    TotalConsumption => "total-consumption",
+    NetConsumption => "net-consumption",
+    Other => "other",
+});
+
+/// Readings from an integrated meter (CT).
+///
+/// Only the totals are always present. The remaining fields are only reported
+/// when details are requested, and may be absent on some firmware.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct EimMeasurement {
+    /// Number of meters reporting.
+    pub active_count: u32,
+    /// The quantity measured by the meter.
+    pub measurement_type: MeasurementType,
+    /// Time of the reading, as a Unix timestamp.
+    pub reading_time: i64,
+    /// Current active power, in watts.
+    pub w_now: f64,
+    /// Lifetime active energy, in watt-hours.
+    pub wh_lifetime: f64,
+    /// Active energy today, in watt-hours.
+    pub wh_today: Option<f64>,
+    /// Active energy over the last seven days, in watt-hours.
+    pub wh_last_seven_days: Option<f64>,
+    /// Lifetime apparent energy, in volt-ampere-hours.
+    pub vah_lifetime: Option<f64>,
+    /// Apparent energy today, in volt-ampere-hours.
+    pub vah_today: Option<f64>,
+    /// Lifetime leading reactive energy, in volt-ampere-reactive-hours.
+    pub varh_lead_lifetime: Option<f64>,
+    /// Leading reactive energy today, in volt-ampere-reactive-hours.
+    pub varh_lead_today: Option<f64>,
+    /// Lifetime lagging reactive energy, in volt-ampere-reactive-hours.
+    pub varh_lag_lifetime: Option<f64>,
+    /// Lagging reactive energy today, in volt-ampere-reactive-hours.
+    pub varh_lag_today: Option<f64>,
+    /// RMS current, in amperes.
+    pub rms_current: Option<f64>,
+    /// RMS voltage, in volts.
+    pub rms_voltage: Option<f64>,
+    /// Reactive power, in volt-amperes reactive.
+    pub react_pwr: Option<f64>,
+    /// Apparent power, in volt-amperes.
+    pub apprnt_pwr: Option<f64>,
+    /// Power factor.
+    pub pwr_factor: Option<f64>,
+    /// Readings of each line (e.g., `l1`, `l2` and `l3` on three-phase sites),
+    /// in order. Empty on single-phase sites, or without details.
+    #[serde(default)]
+    pub lines: Vec<LineReading>,
+}
+
+/// Readings of a single line of an integrated meter (CT).
+///
+/// As for [`EimMeasurement`], the fields other than the totals may be absent
+/// on some firmware.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct LineReading {
+    /// Current active power, in watts.
+    pub w_now: f64,
+    /// Lifetime active energy, in watt-hours.
+    pub wh_lifetime: f64,
+    /// Active energy today, in watt-hours.
+    pub wh_today: Option<f64>,
+    /// RMS current, in amperes.
+    pub rms_current: Option<f64>,
+    /// RMS voltage, in volts.
+    pub rms_voltage: Option<f64>,
+    /// Reactive power, in volt-amperes reactive.
+    pub react_pwr: Option<f64>,
+    /// Apparent power, in volt-amperes.
+    pub apprnt_pwr: Option<f64>,
+    /// Power factor.
+    pub pwr_factor: Option<f64>,
+}
+
+/// Readings from AC batteries.
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct AcbMeasurement {
+    /// Number of batteries reporting.
+    pub active_count: u32,
+    /// Time of the reading, as a Unix timestamp.
+    pub reading_time: i64,
+    /// Current power, in watts. Positive when discharging.
+    pub w_now: f64,
+    /// Energy currently stored, in watt-hours.
+    pub wh_now: f64,
+    /// State of the batteries (e.g., `idle`, `charging`, `discharging`).
+    pub state: String,
+}
+
+/// The latest report from a single microinverter.
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "Distinguishes the per-inverter report from InvertersMeasurement"
+)]
+pub struct InverterProduction {
+    /// Serial number of the microinverter.
+    pub serial_number: String,
+    /// Time of the last report, as a Unix timestamp.
+    pub last_report_date: i64,
+    /// Device type code of the microinverter.
+    pub dev_type: u32,
+    /// Power in the last report, in watts.
+    pub last_report_watts: i32,
+    /// Maximum reported power, in watts.
+    pub max_report_watts: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn deserialize_minimal() {
+        let json = r#"{
+            "production": [
+                {"type": "inverters", "activeCount": 10, "readingTime": 1704067200, "wNow": 225, "whLifetime": 1470271}
+            ],
+            "storage": [
+                {"type": "acb", "activeCount": 0, "readingTime": 0, "wNow": 0, "whNow": 0, "state": "idle"}
+            ]
+        }"#;
+        let response: ProductionResponse =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert_eq!(
+            response.production,
+            vec![Measurement::Inverters(InvertersMeasurement {
+                active_count: 10,
+                reading_time: 1_704_067_200,
+                w_now: 225.0,
+                wh_lifetime: 1_470_271.0,
+            })]
+        );
+        assert!(
+            response.consumption.is_empty(),
+            "Missing consumption should default to empty"
+        );
+        assert_eq!(response.production_switch, None);
+        assert_eq!(response.storage.len(), 1);
+    }
+
+    #[test]
+    fn deserialize_unknown_type() {
+        let json = r#"{
+            "production": [
+                {"type": "rgm", "activeCount": 1, "someField": 42},
+                {"type": "inverters", "activeCount": 1, "readingTime": 0, "wNow": 0, "whLifetime": 0}
+            ]
+        }"#;
+        let response: ProductionResponse =
+            serde_json::from_str(json).expect("Unknown types should not fail the response");
+
+        assert_eq!(response.production.first(), Some(&Measurement::Unknown));
+        assert!(
+            matches!(response.production.get(1), Some(Measurement::Inverters(_))),
+            "Known types after an unknown type should still be parsed"
+        );
+    }
+
+    #[test]
+    fn deserialize_eim_without_details() {
+        let json = r#"{
+            "type": "eim",
+            "activeCount": 0,
+            "measurementType": "net-consumption",
+            "readingTime": 1704067200,
+            "wNow": -12.5,
+            "whLifetime": 0
+        }"#;
+        let measurement: Measurement =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        let Measurement::Eim(eim) = measurement else {
+            panic!("Expected an eim measurement, got {measurement:?}");
+        };
+        assert_eq!(eim.measurement_type, MeasurementType::NetConsumption);
+        assert_eq!(eim.rms_voltage, None);
+        assert!(
+            eim.lines.is_empty(),
+            "Missing lines should default to empty"
+        );
+    }
+
+    #[test]
+    fn phase_count() {
+        let single = r#"{
+            "production": [
+                {"type": "eim", "activeCount": 1, "measurementType": "production", "readingTime": 0, "wNow": 0, "whLifetime": 0}
+            ]
+        }"#;
+        let split = r#"{
+            "production": [
+                {"type": "eim", "activeCount": 1, "measurementType": "production", "readingTime": 0, "wNow": 0, "whLifetime": 0,
+                 "lines": [{"wNow": 0, "whLifetime": 0}, {"wNow": 0, "whLifetime": 0}]}
+            ]
+        }"#;
+        let inverters_only = r#"{
+            "production": [
+                {"type": "inverters", "activeCount": 1, "readingTime": 0, "wNow": 0, "whLifetime": 0}
+            ]
+        }"#;
+        let phases: Vec<Option<usize>> = [single, split, inverters_only]
+            .into_iter()
+            .map(|json| {
+                serde_json::from_str::<ProductionResponse>(json)
+                    .expect("Should deserialize successfully")
+                    .phase_count()
+            })
+            .collect();
+
+        assert_eq!(phases, vec![Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn deserialize_unknown_production_switch() {
+        let json = r#"{"productionSwitch": "paused"}"#;
+        let response: ProductionResponse =
+            serde_json::from_str(json).expect("Unknown states should not fail the response");
+
+        assert_eq!(response.production_switch, Some(ProductionState::Other));
+    }
+
+    #[test]
+    fn deserialize_unknown_measurement_type() {
+        let json = r#""storage-consumption""#;
+        let measurement_type: MeasurementType =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+        assert_eq!(measurement_type, MeasurementType::Other);
+    }
+
+    #[test]
+    fn deserialize_inverter_production() {
+        let json = r#"[
+            {
+                "serialNumber": "482243012345",
+                "lastReportDate": 1704067200,
+                "devType": 1,
+                "lastReportWatts": 225,
+                "maxReportWatts": 290
+            }
+        ]"#;
+        let inverters: Vec<InverterProduction> =
+            serde_json::from_str(json).expect("Should deserialize successfully");
+
+        assert_eq!(
+            inverters,
+            vec![InverterProduction {
+                serial_number: "482243012345".to_owned(),
+                last_report_date: 1_704_067_200,
+                dev_type: 1,
+                last_report_watts: 225,
+                max_report_watts: 290,
+            }]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! # Site models
+//!
+//! This module contains the model for the sites of an Enphase account, as
+//! listed by the Entrez site search (the endpoint backing the site selection
+//! field of the token generation page).
+//!
+//! The search answers with a JSON array of sites. Site identifiers are
+//! numeric, but some responses quote them, so both forms are accepted.
+
+use serde::{Deserialize, Deserializer};
+
+/// A site of the Enphase account.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+pub struct Site {
+    /// Identifier of the site.
+    #[serde(deserialize_with = "id_from_number_or_string")]
+    pub id: String,
+    /// Name of the site, as used when generating a token.
+    pub name: String,
+    /// Serial numbers of the Envoy devices installed at the site.
+    #[serde(default)]
+    pub serial_numbers: Vec<String>,
+}
+
+impl AsRef<str> for Site {
+    /// The name of the site, so that a [`Site`] can be passed wherever a site
+    /// name is expected (e.g., [`Entrez::generate_token`]).
+    ///
+    /// [`Entrez::generate_token`]: crate::Entrez::generate_token
+    #[inline]
+    fn as_ref(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Deserialize a site identifier given either as a number or as a string.
+fn id_from_number_or_string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    /// The forms the identifier is found in.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        /// A numeric identifier.
+        Number(u64),
+        /// A quoted identifier.
+        Text(String),
+    }
+
+    Ok(match Id::deserialize(deserializer)? {
+        Id::Number(number) => number.to_string(),
+        Id::Text(text) => text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn deserialize_sites() {
+        let json = r#"[
+            {"id": 1234567, "name": "My Site", "serial_numbers": ["121212121212"]},
+            {"id": "7654321", "name": "Holiday House", "serial_numbers": []}
+        ]"#;
+        let sites: Vec<Site> = serde_json::from_str(json).expect("Sites should deserialize");
+
+        assert_eq!(
+            sites,
+            vec![
+                Site {
+                    id: "1234567".to_owned(),
+                    name: "My Site".to_owned(),
+                    serial_numbers: vec!["121212121212".to_owned()],
+                },
+                Site {
+                    id: "7654321".to_owned(),
+                    name: "Holiday House".to_owned(),
+                    serial_numbers: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_site_without_serial_numbers() {
+        let site: Site =
+            serde_json::from_str(r#"{"id": 1, "name": "Shed"}"#).expect("Site should deserialize");
+
+        assert_eq!(site.serial_numbers, Vec::<String>::new());
+        assert_eq!(site.as_ref(), "Shed");
+    }
+}
@@ -0,0 +1,94 @@
+//! # Tariff models
+//!
+//! This module contains the models for the tariff and battery storage
+//! settings reported by the Envoy at `/admin/lib/tariff`.
+//!
+//! Only the storage settings are modelled. When the settings are changed (see
+//! [`Envoy::set_battery_mode`](crate::Envoy::set_battery_mode)), the rest of
+//! the tariff document is sent back to the Envoy unchanged.
+
+use serde::Deserialize;
+
+/// The operating mode of the batteries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+pub enum BatteryMode {
+    /// Store excess solar production and use it to cover consumption.
+    #[serde(rename = "self-consumption")]
+    SelfConsumption,
+    /// Keep the batteries charged for backup during grid outages.
+    #[serde(rename = "backup")]
+    Backup,
+    /// Use the batteries to avoid importing at peak rates (shown as "Savings"
+    /// in the Enlighten app).
+    #[serde(rename = "economy")]
+    Savings,
+    /// A mode which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(BatteryMode {
+    SelfConsumption => "self-consumption",
+    Backup => "backup",
+    Savings => "savings",
+    Other => "other",
+});
+
+impl BatteryMode {
+    /// Get the value used by the Envoy for this mode, if it is known.
+    #[cfg(any(feature = "client", test))]
+    pub(crate) fn wire_value(self) -> Option<&'static str> {
+        match self {
+            Self::SelfConsumption => Some("self-consumption"),
+            Self::Backup => Some("backup"),
+            Self::Savings => Some("economy"),
+            Self::Other => None,
+        }
+    }
+}
+
+/// The tariff configured on the Envoy.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[non_exhaustive]
+pub struct Tariff {
+    /// The battery storage settings.
+    pub storage_settings: StorageSettings,
+}
+
+/// The battery storage settings.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[non_exhaustive]
+pub struct StorageSettings {
+    /// The operating mode of the batteries.
+    pub mode: BatteryMode,
+    /// State of charge reserved for backup, in percent.
+    pub reserved_soc: f64,
+    /// State of charge below which the batteries stop discharging, in percent.
+    #[serde(default)]
+    pub very_low_soc: Option<f64>,
+    /// Whether the batteries may be charged from the grid.
+    #[serde(default)]
+    pub charge_from_grid: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("\"self-consumption\"", BatteryMode::SelfConsumption)]
+    #[case("\"backup\"", BatteryMode::Backup)]
+    #[case("\"economy\"", BatteryMode::Savings)]
+    #[case("\"time-of-use\"", BatteryMode::Other)]
+    fn wire_round_trip(#[case] json: &str, #[case] mode: BatteryMode) {
+        let parsed: BatteryMode = serde_json::from_str(json).expect("Should deserialize");
+        assert_eq!(parsed, mode);
+        assert_eq!(
+            parsed.wire_value().map(|value| format!("\"{value}\"")),
+            (mode != BatteryMode::Other).then(|| json.to_owned())
+        );
+    }
+}
@@ -0,0 +1,625 @@
+//! # Site configuration templates
+//!
+//! Installers often configure many sites identically, with only a few values
+//! (e.g., the battery reserve) differing from one site to the next. A
+//! [`SiteTemplate`] is a [`SettingsBundle`] in which:
+//!
+//! - the site-specific values which must not be copied (e.g., serial numbers)
+//!   are removed with [`SiteTemplate::exclude`], and
+//! - the values which vary between sites are replaced by named parameters
+//!   with [`SiteTemplate::parameterize`].
+//!
+//! Rendering the template with the parameters of a site gives the settings of
+//! that site, and [`SiteTemplate::compliance`] reports where a site has
+//! drifted from its template.
+//!
+//! A parameter is stored in the template as an object with a single
+//! `"$parameter"` key naming the parameter (e.g., `{"$parameter":
+//! "reserve_soc"}`), so that templates can be saved as JSON and edited by
+//! hand. Parameters are substituted with any JSON value, keeping its type.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use serde_json::{Map, Value};
+
+use super::canonical::{SettingsBundle, SettingsDrift};
+use crate::error::{EnphaseError, Result};
+
+/// The key of the objects standing for a parameter.
+const PARAMETER_KEY: &str = "$parameter";
+
+/// A template of the settings of a site.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::BTreeMap;
+///
+/// use enphase_api::models::{canonical::SettingsBundle, template::SiteTemplate};
+/// use serde_json::json;
+///
+/// let mut reference = SettingsBundle::new();
+/// reference.insert(
+///     "tariff",
+///     json!({"serial": "122133012345", "storage_settings": {"mode": "self-consumption", "reserved_soc": 20}}),
+/// );
+///
+/// let mut template = SiteTemplate::new(reference);
+/// template.exclude("tariff", "/serial")?;
+/// template.parameterize("tariff", "/storage_settings/reserved_soc", "reserve_soc")?;
+///
+/// let parameters = BTreeMap::from([("reserve_soc".to_owned(), json!(30))]);
+/// let settings = template.render(&parameters)?;
+/// assert_eq!(
+///     settings.get("tariff"),
+///     Some(&json!({"storage_settings": {"mode": "self-consumption", "reserved_soc": 30}}))
+/// );
+/// # Ok::<(), enphase_api::EnphaseError>(())
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+#[expect(
+    clippy::module_name_repetitions,
+    reason = "SiteTemplate reads better than template::Site at the call site"
+)]
+pub struct SiteTemplate {
+    /// The documents of the template, with parameters in place of the values
+    /// which vary between sites.
+    documents: SettingsBundle,
+}
+
+impl SiteTemplate {
+    /// Create a template from the settings of a reference site.
+    ///
+    /// The template initially has no parameters: rendering it gives the
+    /// settings of the reference site.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference` - The settings of the reference site
+    #[inline]
+    #[must_use]
+    pub fn new(reference: SettingsBundle) -> Self {
+        Self {
+            documents: reference,
+        }
+    }
+
+    /// Replace a value of the template by a parameter.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The name of the settings document
+    /// * `pointer` - The JSON pointer (RFC 6901) to the value within the
+    ///   document
+    /// * `parameter` - The name of the parameter
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::ConfigurationError`] if the parameter name is
+    /// empty, or if the template has no value at the pointer.
+    #[inline]
+    pub fn parameterize(&mut self, document: &str, pointer: &str, parameter: &str) -> Result<()> {
+        if parameter.trim().is_empty() {
+            return Err(EnphaseError::ConfigurationError(
+                "Template parameter name must not be empty".to_owned(),
+            ));
+        }
+
+        let mut contents = self.document(document)?;
+        let value = contents
+            .pointer_mut(pointer)
+            .ok_or_else(|| missing_value(document, pointer))?;
+        *value = placeholder(parameter);
+        self.documents.insert(document, contents);
+        Ok(())
+    }
+
+    /// Remove a site-specific value from the template.
+    ///
+    /// Removed values are not set when rendering the template, and are not
+    /// checked by [`SiteTemplate::compliance`].
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - The name of the settings document
+    /// * `pointer` - The JSON pointer (RFC 6901) to the value within the
+    ///   document
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::ConfigurationError`] if the template has no
+    /// value at the pointer. The pointer must not be empty; documents are
+    /// removed as a whole by leaving them out of the reference settings.
+    #[inline]
+    pub fn exclude(&mut self, document: &str, pointer: &str) -> Result<()> {
+        let mut contents = self.document(document)?;
+        if !remove_pointer(&mut contents, pointer) {
+            return Err(missing_value(document, pointer));
+        }
+        self.documents.insert(document, contents);
+        Ok(())
+    }
+
+    /// The names of the parameters of the template.
+    #[inline]
+    #[must_use]
+    pub fn parameters(&self) -> BTreeSet<String> {
+        let mut parameters = BTreeSet::new();
+        for (_, document) in self.documents.iter() {
+            collect_parameters(document, &mut parameters);
+        }
+        parameters
+    }
+
+    /// Render the settings of a site.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - The values of the parameters for the site
+    ///
+    /// # Returns
+    ///
+    /// Returns the settings, with each parameter replaced by its value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::ConfigurationError`] if a parameter of the
+    /// template has no value, or if a value is given for a parameter the
+    /// template does not have (e.g., a misspelled name).
+    #[inline]
+    pub fn render(&self, parameters: &BTreeMap<String, Value>) -> Result<SettingsBundle> {
+        let expected = self.parameters();
+        let missing: Vec<&str> = expected
+            .iter()
+            .filter(|name| !parameters.contains_key(name.as_str()))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Err(EnphaseError::ConfigurationError(format!(
+                "Missing template parameters: {}",
+                missing.join(", ")
+            )));
+        }
+        let unknown: Vec<&str> = parameters
+            .keys()
+            .filter(|name| !expected.contains(name.as_str()))
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            return Err(EnphaseError::ConfigurationError(format!(
+                "Unknown template parameters: {}",
+                unknown.join(", ")
+            )));
+        }
+
+        let mut settings = SettingsBundle::new();
+        for (name, document) in self.documents.iter() {
+            settings.insert(name, substitute(document, parameters));
+        }
+        Ok(settings)
+    }
+
+    /// Report where the settings of a site differ from the template.
+    ///
+    /// Only the values set by the template are checked: documents, object
+    /// keys and array items which the template does not have (including the
+    /// values removed with [`SiteTemplate::exclude`]) are not reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - The values of the parameters for the site
+    /// * `actual` - The current settings of the site
+    ///
+    /// # Returns
+    ///
+    /// Returns the differences, from the rendered template (`before`) to the
+    /// current settings (`after`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::ConfigurationError`] if the template cannot
+    /// be rendered with the parameters (see [`SiteTemplate::render`]).
+    #[inline]
+    pub fn compliance(
+        &self,
+        parameters: &BTreeMap<String, Value>,
+        actual: &SettingsBundle,
+    ) -> Result<Vec<SettingsDrift>> {
+        Ok(self
+            .render(parameters)?
+            .diff(actual)
+            .into_iter()
+            .filter(|drift| drift.before.is_some())
+            .collect())
+    }
+
+    /// Serialize the template to JSON.
+    ///
+    /// # Returns
+    ///
+    /// Returns the template as a JSON object of the documents, by name.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::JsonError`] if serialization fails.
+    #[inline]
+    pub fn to_json(&self) -> Result<String> {
+        let documents: BTreeMap<&str, &Value> = self.documents.iter().collect();
+        Ok(serde_json::to_string(&documents)?)
+    }
+
+    /// Parse a template serialized by [`SiteTemplate::to_json`].
+    ///
+    /// # Arguments
+    ///
+    /// * `json` - The serialized template
+    ///
+    /// # Returns
+    ///
+    /// Returns the parsed template.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::JsonError`] if the JSON is not an object.
+    #[inline]
+    pub fn from_json(json: &str) -> Result<Self> {
+        let documents: BTreeMap<String, Value> = serde_json::from_str(json)?;
+        let mut bundle = SettingsBundle::new();
+        for (name, document) in documents {
+            bundle.insert(name, document);
+        }
+        Ok(Self::new(bundle))
+    }
+
+    /// A copy of a document of the template.
+    fn document(&self, name: &str) -> Result<Value> {
+        self.documents.get(name).cloned().ok_or_else(|| {
+            EnphaseError::ConfigurationError(format!("The template has no {name} document"))
+        })
+    }
+}
+
+/// The error for a pointer which does not point to a value.
+fn missing_value(document: &str, pointer: &str) -> EnphaseError {
+    EnphaseError::ConfigurationError(format!("The template has no value at {document}{pointer}"))
+}
+
+/// The object standing for a parameter.
+fn placeholder(parameter: &str) -> Value {
+    let mut map = Map::new();
+    map.insert(PARAMETER_KEY.to_owned(), Value::from(parameter));
+    Value::Object(map)
+}
+
+/// The name of the parameter a value stands for, if it is a parameter.
+fn parameter_name(value: &Value) -> Option<&str> {
+    match *value {
+        Value::Object(ref map) if map.len() == 1 => map.get(PARAMETER_KEY)?.as_str(),
+        Value::Null
+        | Value::Bool(_)
+        | Value::Number(_)
+        | Value::String(_)
+        | Value::Array(_)
+        | Value::Object(_) => None,
+    }
+}
+
+/// Collect the names of the parameters within a value.
+fn collect_parameters(value: &Value, parameters: &mut BTreeSet<String>) {
+    if let Some(name) = parameter_name(value) {
+        parameters.insert(name.to_owned());
+        return;
+    }
+    match *value {
+        Value::Array(ref items) => {
+            for item in items {
+                collect_parameters(item, parameters);
+            }
+        }
+        Value::Object(ref map) => {
+            for item in map.values() {
+                collect_parameters(item, parameters);
+            }
+        }
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => {}
+    }
+}
+
+/// Replace the parameters within a value by their values.
+///
+/// The parameters must all have a value; this is checked by
+/// [`SiteTemplate::render`].
+fn substitute(value: &Value, parameters: &BTreeMap<String, Value>) -> Value {
+    if let Some(name) = parameter_name(value) {
+        return parameters.get(name).cloned().unwrap_or(Value::Null);
+    }
+    match *value {
+        Value::Array(ref items) => Value::Array(
+            items
+                .iter()
+                .map(|item| substitute(item, parameters))
+                .collect(),
+        ),
+        Value::Object(ref map) => Value::Object(
+            map.iter()
+                .map(|(key, item)| (key.clone(), substitute(item, parameters)))
+                .collect(),
+        ),
+        Value::Null | Value::Bool(_) | Value::Number(_) | Value::String(_) => value.clone(),
+    }
+}
+
+/// Remove the value at a JSON pointer.
+///
+/// Returns whether a value was removed.
+fn remove_pointer(document: &mut Value, pointer: &str) -> bool {
+    let Some((parent, token)) = pointer.rsplit_once('/') else {
+        return false;
+    };
+    let key = token.replace("~1", "/").replace("~0", "~");
+    match document.pointer_mut(parent) {
+        Some(&mut Value::Object(ref mut map)) => map.remove(&key).is_some(),
+        Some(&mut Value::Array(ref mut items)) => match key.parse::<usize>() {
+            Ok(index) if index < items.len() => {
+                items.remove(index);
+                true
+            }
+            _ => false,
+        },
+        Some(_) | None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// Parse a JSON document.
+    fn parse(json: &str) -> Value {
+        serde_json::from_str(json).expect("Should parse")
+    }
+
+    /// A template with a reserve parameter and the serial number excluded.
+    fn template() -> SiteTemplate {
+        let mut reference = SettingsBundle::new();
+        reference.insert(
+            "tariff",
+            parse(
+                r#"{
+                    "serial": "122133012345",
+                    "currency": {"code": "AUD"},
+                    "storage_settings": {"mode": "self-consumption", "reserved_soc": 20.0},
+                    "schedule": [{"start": 0, "end": 360}, {"start": 960, "end": 1440}]
+                }"#,
+            ),
+        );
+        reference.insert(
+            "display",
+            parse(r#"{"units": "kW", "site/name": "Reference"}"#),
+        );
+
+        let mut template = SiteTemplate::new(reference);
+        template
+            .exclude("tariff", "/serial")
+            .expect("Should exclude");
+        template
+            .parameterize("tariff", "/storage_settings/reserved_soc", "reserve_soc")
+            .expect("Should parameterize");
+        template
+            .parameterize("display", "/site~1name", "site_name")
+            .expect("Should parameterize");
+        template
+    }
+
+    /// Parameters for a site, given as JSON values.
+    fn parameters(values: &[(&str, &str)]) -> BTreeMap<String, Value> {
+        values
+            .iter()
+            .map(|&(name, json)| (name.to_owned(), parse(json)))
+            .collect()
+    }
+
+    /// The parameters of the site used in the tests.
+    fn site_parameters() -> BTreeMap<String, Value> {
+        parameters(&[("reserve_soc", "30"), ("site_name", r#""Smith""#)])
+    }
+
+    #[test]
+    fn render() {
+        let settings = template()
+            .render(&site_parameters())
+            .expect("Should render");
+
+        assert_eq!(
+            settings.get("tariff"),
+            Some(&parse(
+                r#"{
+                    "currency": {"code": "AUD"},
+                    "storage_settings": {"mode": "self-consumption", "reserved_soc": 30},
+                    "schedule": [{"start": 0, "end": 360}, {"start": 960, "end": 1440}]
+                }"#
+            ))
+        );
+        assert_eq!(
+            settings.get("display"),
+            Some(&parse(r#"{"units": "kW", "site/name": "Smith"}"#))
+        );
+    }
+
+    #[test]
+    fn parameters_are_listed() {
+        let names: Vec<String> = template().parameters().into_iter().collect();
+
+        assert_eq!(names, vec!["reserve_soc", "site_name"]);
+    }
+
+    #[test]
+    fn repeated_parameter() {
+        let mut reference = SettingsBundle::new();
+        reference.insert("limits", parse(r#"{"low": 10, "high": [10, 90]}"#));
+        let mut template = SiteTemplate::new(reference);
+        template
+            .parameterize("limits", "/low", "floor")
+            .expect("Should parameterize");
+        template
+            .parameterize("limits", "/high/0", "floor")
+            .expect("Should parameterize");
+
+        let settings = template
+            .render(&parameters(&[("floor", "15")]))
+            .expect("Should render");
+
+        assert_eq!(
+            settings.get("limits"),
+            Some(&parse(r#"{"low": 15, "high": [15, 90]}"#))
+        );
+    }
+
+    #[rstest]
+    #[case::missing(&[("reserve_soc", "30")], "Missing template parameters: site_name")]
+    #[case::unknown(
+        &[("reserve_soc", "30"), ("site_name", r#""Smith""#), ("reserve", "10")],
+        "Unknown template parameters: reserve"
+    )]
+    fn render_invalid_parameters(#[case] values: &[(&str, &str)], #[case] expected: &str) {
+        let result = template().render(&parameters(values));
+
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(ref message)) if message == expected),
+            "Expected a configuration error, got {result:?}"
+        );
+    }
+
+    #[rstest]
+    #[case::no_document("meters", "/enabled")]
+    #[case::no_key("tariff", "/storage_settings/mode_")]
+    #[case::no_index("tariff", "/schedule/2")]
+    #[case::excluded("tariff", "/serial")]
+    fn missing_values(#[case] document: &str, #[case] pointer: &str) {
+        let mut template = template();
+
+        let parameterized = template.parameterize(document, pointer, "value");
+        let excluded = template.exclude(document, pointer);
+
+        assert!(
+            matches!(parameterized, Err(EnphaseError::ConfigurationError(_))),
+            "Expected a configuration error, got {parameterized:?}"
+        );
+        assert!(
+            matches!(excluded, Err(EnphaseError::ConfigurationError(_))),
+            "Expected a configuration error, got {excluded:?}"
+        );
+    }
+
+    #[test]
+    fn exclude_array_item() {
+        let mut template = template();
+        template
+            .exclude("tariff", "/schedule/0")
+            .expect("Should exclude");
+
+        let settings = template.render(&site_parameters()).expect("Should render");
+
+        assert_eq!(
+            settings
+                .get("tariff")
+                .and_then(|tariff| tariff.get("schedule")),
+            Some(&parse(r#"[{"start": 960, "end": 1440}]"#))
+        );
+    }
+
+    #[test]
+    fn exclude_rejects_empty_pointer() {
+        let result = template().exclude("tariff", "");
+
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Expected a configuration error, got {result:?}"
+        );
+    }
+
+    #[test]
+    fn parameterize_rejects_empty_name() {
+        let result = template().parameterize("tariff", "/currency/code", " ");
+
+        assert!(
+            matches!(result, Err(EnphaseError::ConfigurationError(_))),
+            "Expected a configuration error, got {result:?}"
+        );
+    }
+
+    #[test]
+    fn compliance() {
+        let mut actual = SettingsBundle::new();
+        actual.insert(
+            "tariff",
+            parse(
+                r#"{
+                    "serial": "122133054321",
+                    "currency": {"code": "AUD"},
+                    "storage_settings": {"mode": "backup", "reserved_soc": 30.0, "extra": true},
+                    "schedule": [{"start": 0, "end": 360}]
+                }"#,
+            ),
+        );
+        actual.insert("meters", parse(r#"{"enabled": true}"#));
+
+        let drift: Vec<String> = template()
+            .compliance(&site_parameters(), &actual)
+            .expect("Should compare")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            drift,
+            vec![
+                r#"display: {"site/name":"Smith","units":"kW"} -> (missing)"#,
+                r#"tariff/schedule/1: {"end":1440,"start":960} -> (missing)"#,
+                r#"tariff/storage_settings/mode: "self-consumption" -> "backup""#,
+            ]
+        );
+    }
+
+    #[test]
+    fn compliant_site() {
+        let template = template();
+        let actual = template.render(&site_parameters()).expect("Should render");
+
+        assert_eq!(
+            template
+                .compliance(&site_parameters(), &actual)
+                .expect("Should compare"),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let template = template();
+        let json = template.to_json().expect("Should serialize");
+
+        assert!(
+            json.contains(r#"{"$parameter":"reserve_soc"}"#),
+            "Parameters should be serialized as placeholders: {json}"
+        );
+        assert_eq!(
+            SiteTemplate::from_json(&json).expect("Should parse"),
+            template
+        );
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("[]")]
+    fn json_invalid(#[case] json: &str) {
+        let result = SiteTemplate::from_json(json);
+
+        assert!(
+            matches!(result, Err(EnphaseError::JsonError(_))),
+            "Expected a JSON error, got {result:?}"
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! # Token models
+//!
+//! This module decodes the claims of the JWT tokens issued by Entrez for
+//! accessing an Envoy.
+//!
+//! The signature is not verified: the Envoy is the authority on whether a
+//! token is accepted, and the claims are only used to inspect the token (e.g.,
+//! to anticipate its expiry).
+
+use core::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use serde::Deserialize;
+
+use crate::error::{EnphaseError, Result};
+
+/// The kind of user a token was issued to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "lowercase")]
+pub enum EnphaseUser {
+    /// The owner of the system.
+    Owner,
+    /// An installer of the system.
+    Installer,
+    /// A kind of user which is not (yet) supported.
+    #[serde(other)]
+    Other,
+}
+
+string_enum!(EnphaseUser {
+    Owner => "owner",
+    Installer => "installer",
+    Other => "other",
+});
+
+/// Where a token was obtained from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TokenSource {
+    /// The token was read from a token cache.
+    Cache,
+    /// The token was generated by Entrez, which needs an Entrez login.
+    Entrez,
+    /// The token was supplied by the application (e.g., persisted, or
+    /// obtained elsewhere).
+    Local,
+}
+
+string_enum!(TokenSource {
+    Cache => "cache",
+    Entrez => "entrez",
+    Local => "local",
+});
+
+/// The claims of a JWT token issued by Entrez.
+///
+/// All claims are optional, as their presence depends on the kind of token.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[non_exhaustive]
+#[serde(rename_all = "camelCase")]
+pub struct EnvoyToken {
+    /// Expiry time, as a Unix timestamp.
+    pub exp: Option<i64>,
+    /// Issue time, as a Unix timestamp.
+    pub iat: Option<i64>,
+    /// Username of the Enlighten account the token was issued to.
+    pub username: Option<String>,
+    /// The kind of user the token was issued to.
+    pub enphase_user: Option<EnphaseUser>,
+    /// The audience of the token, which is the serial number of the Envoy it
+    /// was issued for.
+    pub aud: Option<String>,
+}
+
+impl EnvoyToken {
+    /// Decode the claims of a JWT token.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The JWT token, as returned by Entrez
+    ///
+    /// # Returns
+    ///
+    /// Returns the decoded [`EnvoyToken`] claims.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`EnphaseError::InvalidResponse`] if the token does not
+    /// consist of three dot-separated base64url segments, or if the payload is
+    /// not a JSON object.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use enphase_api::models::EnvoyToken;
+    ///
+    /// // {"aud":"482243012345","exp":1735689601}
+    /// let token = "eyJhbGciOiJFUzI1NiJ9.\
+    ///              eyJhdWQiOiI0ODIyNDMwMTIzNDUiLCJleHAiOjE3MzU2ODk2MDF9.\
+    ///              signature";
+    /// let claims = EnvoyToken::parse(token)?;
+    /// assert_eq!(claims.serial_number(), Some("482243012345"));
+    /// assert!(claims.is_expired());
+    /// # Ok::<(), enphase_api::EnphaseError>(())
+    /// ```
+    #[inline]
+    pub fn parse(token: &str) -> Result<Self> {
+        let mut segments = token.trim().split('.');
+        let (Some(header), Some(payload), Some(signature), None) = (
+            segments.next(),
+            segments.next(),
+            segments.next(),
+            segments.next(),
+        ) else {
+            return Err(EnphaseError::InvalidResponse(
+                "JWT must consist of three dot-separated segments".to_owned(),
+            ));
+        };
+
+        for segment in [header, signature] {
+            if !segment
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'='))
+            {
+                return Err(EnphaseError::InvalidResponse(
+                    "JWT segments must be base64url-encoded".to_owned(),
+                ));
+            }
+        }
+
+        // Some issuers keep the padding, which the URL-safe engine rejects.
+        let decoded = URL_SAFE_NO_PAD
+            .decode(payload.trim_end_matches('='))
+            .map_err(|e| {
+                EnphaseError::InvalidResponse(format!("JWT payload is not base64url: {e}"))
+            })?;
+        serde_json::from_slice(&decoded).map_err(|e| {
+            EnphaseError::InvalidResponse(format!("JWT payload is not valid JSON: {e}"))
+        })
+    }
+
+    /// The serial number of the Envoy the token was issued for.
+    #[inline]
+    #[must_use]
+    pub fn serial_number(&self) -> Option<&str> {
+        self.aud.as_deref()
+    }
+
+    /// When the token was issued, if known.
+    #[inline]
+    pub fn issued_at(&self) -> Option<SystemTime> {
+        self.iat.map(timestamp)
+    }
+
+    /// When the token expires, if it expires at all.
+    #[inline]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.exp.map(timestamp)
+    }
+
+    /// How long until the token expires, if it expires at all.
+    ///
+    /// This is zero for tokens which have already expired.
+    #[inline]
+    #[must_use]
+    pub fn remaining(&self) -> Option<Duration> {
+        self.expires_at()
+            .map(|expiry| expiry.duration_since(SystemTime::now()).unwrap_or_default())
+    }
+
+    /// Whether the token has expired.
+    ///
+    /// Tokens without an expiry never expire.
+    #[inline]
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(Duration::ZERO)
+    }
+
+    /// Whether the token expires within the given margin (or has already
+    /// expired).
+    ///
+    /// Tokens without an expiry never expire.
+    ///
+    /// # Arguments
+    ///
+    /// * `margin` - How far ahead to look for the expiry
+    #[inline]
+    #[must_use]
+    pub fn expires_within(&self, margin: Duration) -> bool {
+        self.expires_at().is_some_and(|expiry| {
+            expiry
+                .duration_since(SystemTime::now())
+                .ok()
+                .is_none_or(|remaining| remaining <= margin)
+        })
+    }
+}
+
+/// Convert a Unix timestamp to a system time, clamping times before the
+/// epoch to the epoch.
+fn timestamp(seconds: i64) -> SystemTime {
+    UNIX_EPOCH
+        .checked_add(Duration::from_secs(seconds.max(0).unsigned_abs()))
+        .unwrap_or(UNIX_EPOCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::URL_SAFE;
+    use pretty_assertions::assert_eq;
+    use rstest::rstest;
+
+    /// Build an unsigned token with the given payload.
+    fn token(payload: &str) -> String {
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"ES256","typ":"JWT"}"#),
+            URL_SAFE_NO_PAD.encode(payload)
+        )
+    }
+
+    #[test]
+    fn parse_claims() {
+        let claims = EnvoyToken::parse(&token(
+            r#"{
+                "aud": "482243012345",
+                "iss": "Entrez",
+                "enphaseUser": "owner",
+                "exp": 1735689601,
+                "iat": 1704153601,
+                "jti": "b8f2f1a4-0000-0000-0000-000000000000",
+                "username": "user@example.com"
+            }"#,
+        ))
+        .expect("Token should be valid");
+
+        assert_eq!(
+            claims,
+            EnvoyToken {
+                exp: Some(1_735_689_601),
+                iat: Some(1_704_153_601),
+                username: Some("user@example.com".to_owned()),
+                enphase_user: Some(EnphaseUser::Owner),
+                aud: Some("482243012345".to_owned()),
+            }
+        );
+        assert_eq!(claims.serial_number(), Some("482243012345"));
+        assert_eq!(
+            claims.expires_at(),
+            UNIX_EPOCH.checked_add(Duration::from_secs(1_735_689_601))
+        );
+        assert_eq!(
+            claims.issued_at(),
+            UNIX_EPOCH.checked_add(Duration::from_secs(1_704_153_601))
+        );
+        assert!(claims.is_expired(), "Token expired in 2025");
+        assert_eq!(claims.remaining(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn no_expiry() {
+        let claims = EnvoyToken::parse(&token(r#"{"enphaseUser":"support"}"#))
+            .expect("Token should be valid");
+
+        assert_eq!(claims.expires_at(), None);
+        assert_eq!(claims.remaining(), None);
+        assert_eq!(claims.enphase_user, Some(EnphaseUser::Other));
+        assert!(!claims.is_expired(), "Tokens without expiry never expire");
+    }
+
+    #[test]
+    fn padded_payload() {
+        let padded = format!("header.{}.signature", URL_SAFE.encode(r#"{"exp":1}"#));
+        let claims = EnvoyToken::parse(&padded).expect("Padding should be accepted");
+        assert_eq!(claims.exp, Some(1));
+    }
+
+    #[test]
+    fn expiry_margin() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Clock should be after the epoch")
+            .as_secs();
+        let expiring = |seconds: u64| EnvoyToken {
+            exp: i64::try_from(now.saturating_add(seconds)).ok(),
+            iat: None,
+            username: None,
+            enphase_user: None,
+            aud: None,
+        };
+        let margin = Duration::from_mins(5);
+
+        assert!(expiring(60).expires_within(margin));
+        assert!(!expiring(60).is_expired());
+        assert!(!expiring(3600).expires_within(margin));
+        assert!(
+            expiring(3600)
+                .remaining()
+                .is_some_and(|remaining| remaining > margin && remaining <= Duration::from_hours(1)),
+            "Token should expire within the hour"
+        );
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("not-a-token")]
+    #[case("a.b")]
+    #[case("a.b.c.d")]
+    #[case("head er.e30.signature")]
+    #[case("header.!!!.signature")]
+    #[case("header.bm90IGpzb24.signature")]
+    fn invalid(#[case] input: &str) {
+        let result = EnvoyToken::parse(input);
+        assert!(
+            matches!(result, Err(EnphaseError::InvalidResponse(_))),
+            "{input:?} should be rejected, got {result:?}"
+        );
+    }
+}
@@ -70,10 +70,9 @@ async fn authenticate_and_get_power_state() -> Result<(), Box<dyn core::error::E
     println!("Successfully authenticated with Envoy device");
 
     // Step 3: Get current power state
-    let is_on = envoy.get_power_state("603980032").await?;
-    println!("Power is {}", if is_on { "on" } else { "off" });
+    let state = envoy.get_power_state("603980032").await?;
+    println!("Power is {state}");
 
-    // Verify the response is a valid boolean
     println!("Successfully retrieved power state");
 
     Ok(())
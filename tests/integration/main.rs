@@ -2,5 +2,7 @@
 
 #![cfg(test)]
 
+#[cfg(feature = "client")]
 mod entrez;
+#[cfg(feature = "client")]
 mod envoy;